@@ -1,31 +1,16 @@
 use dobot_client::*;
 
-fn hold<T>(dobot: &mut DobotClient<T>) -> Result<u64, failure::Error>
-where
-    T: Device,
-{
-    dobot.set_iodo_queued(17, IoLevel::Low)?;
-    dobot.set_iodo_queued(18, IoLevel::Low)
-}
-
-fn release<T>(dobot: &mut DobotClient<T>) -> Result<u64, failure::Error>
-where
-    T: Device,
-{
-    dobot.set_iodo_queued(17, IoLevel::Low)?;
-    dobot.set_iodo_queued(18, IoLevel::High)
-}
-
-fn main() -> Result<(), failure::Error> {
+fn main() -> Result<(), DobotError> {
     let mut args = std::env::args();
     let path = args.nth(1).unwrap_or("/dev/tty.usbserial".to_owned());
     let d = SerialDevice::new(&path)?;
     let mut dobot = DobotClient::new(d);
+    let pump = EndEffector::m1_air_pump();
     println!("SN number={:?}", dobot.get_device_sn()?);
-    println!("{:?}", dobot.get_alarm_state()?);
+    println!("{}", dobot.get_alarm_state()?);
     dobot.clear_all_alarm_state()?;
     //        std::thread::sleep(std::time::Duration::from_millis(1000));
-    println!("{:?}", dobot.get_alarm_state()?);
+    println!("{}", dobot.get_alarm_state()?);
     let pose = dobot.get_pose()?;
 
     println!("{:?}", pose);
@@ -92,7 +77,7 @@ fn main() -> Result<(), failure::Error> {
     };
     dobot.set_ptp_command_queued(cmd1)?;
 
-    hold(&mut dobot)?;
+    pump.grip_queued(&mut dobot)?;
 
     let cmd1 = PtpCommand {
         ptp_mode: PtpMode::JumpXyz,
@@ -102,7 +87,7 @@ fn main() -> Result<(), failure::Error> {
         r: 0.0,
     };
     dobot.set_ptp_command_queued(cmd1)?;
-    release(&mut dobot)?;
+    pump.release_queued(&mut dobot)?;
 
     let cmd1 = PtpCommand {
         ptp_mode: PtpMode::MovjAngle,
@@ -141,7 +126,7 @@ fn main() -> Result<(), failure::Error> {
     */
     dobot.set_queued_command_start_exec()?;
 
-    println!("{:?}", dobot.get_alarm_state()?);
+    println!("{}", dobot.get_alarm_state()?);
 
     for _ in 0..10 {
     std::thread::sleep(std::time::Duration::from_millis(200));
@@ -151,9 +136,9 @@ fn main() -> Result<(), failure::Error> {
      */
     let pose = dobot.get_pose()?;
     println!("{:?}", pose);
-    println!("{:?}", dobot.get_alarm_state()?);
+    println!("{}", dobot.get_alarm_state()?);
     dobot.clear_all_alarm_state()?;
-    println!("{:?}", dobot.get_alarm_state()?);
+    println!("{}", dobot.get_alarm_state()?);
     /*
     // Vacuum ON
     dobot.set_iodo(17, IoLevel::Low)?;
@@ -0,0 +1,399 @@
+use crate::error::DobotError;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// Axis of an alarm, 1 ~ 4.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Axis(u8);
+
+impl Axis {
+    pub const MIN: u8 = 1;
+    pub const MAX: u8 = 4;
+
+    pub fn new(number: u8) -> Result<Self, DobotError> {
+        if (Self::MIN..=Self::MAX).contains(&number) {
+            Ok(Axis(number))
+        } else {
+            Err(DobotError::InvalidValue(format!(
+                "axis {} is not in {} ~ {}",
+                number,
+                Self::MIN,
+                Self::MAX
+            )))
+        }
+    }
+
+    pub fn number(&self) -> u8 {
+        self.0
+    }
+}
+
+impl TryFrom<u8> for Axis {
+    type Error = DobotError;
+
+    fn try_from(number: u8) -> Result<Self, DobotError> {
+        Axis::new(number)
+    }
+}
+
+impl fmt::Display for Axis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Alarm codes reported in the alarm state (protocol ID 20).
+///
+/// Codes that are not in the published Dobot alarm
+/// list decode to `Unknown`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Alarm {
+    /// 0x00, the controller has been reset.
+    Reset,
+    /// 0x01
+    UndefinedInstruction,
+    /// 0x02
+    FileSystem,
+    /// 0x03, communication between MCU and FPGA failed.
+    McuFpgaCommunication,
+    /// 0x04
+    AngleSensor,
+    /// 0x10, planning: target is a singular point.
+    PlanInvSingularity,
+    /// 0x11, planning: inverse kinematics has no solution.
+    PlanInvCalc,
+    /// 0x12, planning: target is beyond joint limits.
+    PlanInvLimit,
+    /// 0x13, planning: the same point was pushed twice.
+    PlanPushDataRepeat,
+    /// 0x14, planning: invalid ARC parameters.
+    PlanArcInputParam,
+    /// 0x15, planning: invalid JUMP parameters.
+    PlanJumpParam,
+    /// 0x20, kinematics: moving through a singular point.
+    MoveInvSingularity,
+    /// 0x21, kinematics: inverse kinematics has no solution.
+    MoveInvCalc,
+    /// 0x22, kinematics: moving beyond joint limits.
+    MoveInvLimit,
+    /// 0x30 ~ 0x33
+    Overspeed(Axis),
+    /// 0x40, 0x42, 0x44, 0x46
+    LimitPositive(Axis),
+    /// 0x41, 0x43, 0x45, 0x47
+    LimitNegative(Axis),
+    /// 0x48
+    LimitAxis23Positive,
+    /// 0x49
+    LimitAxis23Negative,
+    /// 0x4A
+    LimitAxis123Positive,
+    /// 0x4B
+    LimitAxis123Negative,
+    /// 0x50 ~ 0x53, motor lost steps.
+    LoseStep(Axis),
+    /// 0x60 ~ 0x63, motor driver alarm.
+    MotorDriver(Axis),
+    /// 0x64 ~ 0x67, motor driver overflow.
+    MotorOverflow(Axis),
+    /// 0x68 ~ 0x6B, motor following error.
+    MotorFollow(Axis),
+    Unknown(u8),
+}
+
+impl Alarm {
+    pub fn from_code(code: u8) -> Self {
+        let axis = |base: u8| Axis(code - base + 1);
+        match code {
+            0x00 => Alarm::Reset,
+            0x01 => Alarm::UndefinedInstruction,
+            0x02 => Alarm::FileSystem,
+            0x03 => Alarm::McuFpgaCommunication,
+            0x04 => Alarm::AngleSensor,
+            0x10 => Alarm::PlanInvSingularity,
+            0x11 => Alarm::PlanInvCalc,
+            0x12 => Alarm::PlanInvLimit,
+            0x13 => Alarm::PlanPushDataRepeat,
+            0x14 => Alarm::PlanArcInputParam,
+            0x15 => Alarm::PlanJumpParam,
+            0x20 => Alarm::MoveInvSingularity,
+            0x21 => Alarm::MoveInvCalc,
+            0x22 => Alarm::MoveInvLimit,
+            0x30..=0x33 => Alarm::Overspeed(axis(0x30)),
+            0x40..=0x47 if code & 1 == 0 => Alarm::LimitPositive(Axis((code - 0x40) / 2 + 1)),
+            0x40..=0x47 => Alarm::LimitNegative(Axis((code - 0x41) / 2 + 1)),
+            0x48 => Alarm::LimitAxis23Positive,
+            0x49 => Alarm::LimitAxis23Negative,
+            0x4A => Alarm::LimitAxis123Positive,
+            0x4B => Alarm::LimitAxis123Negative,
+            0x50..=0x53 => Alarm::LoseStep(axis(0x50)),
+            0x60..=0x63 => Alarm::MotorDriver(axis(0x60)),
+            0x64..=0x67 => Alarm::MotorOverflow(axis(0x64)),
+            0x68..=0x6B => Alarm::MotorFollow(axis(0x68)),
+            _ => Alarm::Unknown(code),
+        }
+    }
+
+    pub fn code(&self) -> u8 {
+        match *self {
+            Alarm::Reset => 0x00,
+            Alarm::UndefinedInstruction => 0x01,
+            Alarm::FileSystem => 0x02,
+            Alarm::McuFpgaCommunication => 0x03,
+            Alarm::AngleSensor => 0x04,
+            Alarm::PlanInvSingularity => 0x10,
+            Alarm::PlanInvCalc => 0x11,
+            Alarm::PlanInvLimit => 0x12,
+            Alarm::PlanPushDataRepeat => 0x13,
+            Alarm::PlanArcInputParam => 0x14,
+            Alarm::PlanJumpParam => 0x15,
+            Alarm::MoveInvSingularity => 0x20,
+            Alarm::MoveInvCalc => 0x21,
+            Alarm::MoveInvLimit => 0x22,
+            Alarm::Overspeed(axis) => 0x30 + axis.0 - 1,
+            Alarm::LimitPositive(axis) => 0x40 + (axis.0 - 1) * 2,
+            Alarm::LimitNegative(axis) => 0x41 + (axis.0 - 1) * 2,
+            Alarm::LimitAxis23Positive => 0x48,
+            Alarm::LimitAxis23Negative => 0x49,
+            Alarm::LimitAxis123Positive => 0x4A,
+            Alarm::LimitAxis123Negative => 0x4B,
+            Alarm::LoseStep(axis) => 0x50 + axis.0 - 1,
+            Alarm::MotorDriver(axis) => 0x60 + axis.0 - 1,
+            Alarm::MotorOverflow(axis) => 0x64 + axis.0 - 1,
+            Alarm::MotorFollow(axis) => 0x68 + axis.0 - 1,
+            Alarm::Unknown(code) => code,
+        }
+    }
+
+    /// Whether the alarm comes from planning or executing a motion, which
+    /// is then not completed.
+    pub fn stops_motion(&self) -> bool {
+        !matches!(
+            self,
+            Alarm::Reset
+                | Alarm::UndefinedInstruction
+                | Alarm::FileSystem
+                | Alarm::McuFpgaCommunication
+                | Alarm::AngleSensor
+                | Alarm::Unknown(_)
+        )
+    }
+}
+
+impl fmt::Display for Alarm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Alarm::Reset => write!(f, "controller reset"),
+            Alarm::UndefinedInstruction => write!(f, "undefined instruction"),
+            Alarm::FileSystem => write!(f, "file system error"),
+            Alarm::McuFpgaCommunication => write!(f, "MCU/FPGA communication failure"),
+            Alarm::AngleSensor => write!(f, "angle sensor reading error"),
+            Alarm::PlanInvSingularity => write!(f, "planning: target is a singular point"),
+            Alarm::PlanInvCalc => write!(f, "planning: target is unreachable"),
+            Alarm::PlanInvLimit => write!(f, "planning: target is beyond joint limits"),
+            Alarm::PlanPushDataRepeat => write!(f, "planning: repeated point"),
+            Alarm::PlanArcInputParam => write!(f, "planning: invalid ARC parameters"),
+            Alarm::PlanJumpParam => write!(f, "planning: invalid JUMP parameters"),
+            Alarm::MoveInvSingularity => write!(f, "motion: passing a singular point"),
+            Alarm::MoveInvCalc => write!(f, "motion: position is unreachable"),
+            Alarm::MoveInvLimit => write!(f, "motion: joint limit reached"),
+            Alarm::Overspeed(axis) => write!(f, "axis {} overspeed", axis),
+            Alarm::LimitPositive(axis) => write!(f, "axis {} positive limit", axis),
+            Alarm::LimitNegative(axis) => write!(f, "axis {} negative limit", axis),
+            Alarm::LimitAxis23Positive => write!(f, "axis 2/3 positive limit"),
+            Alarm::LimitAxis23Negative => write!(f, "axis 2/3 negative limit"),
+            Alarm::LimitAxis123Positive => write!(f, "axis 1/2/3 positive limit"),
+            Alarm::LimitAxis123Negative => write!(f, "axis 1/2/3 negative limit"),
+            Alarm::LoseStep(axis) => write!(f, "axis {} lost steps", axis),
+            Alarm::MotorDriver(axis) => write!(f, "axis {} motor driver alarm", axis),
+            Alarm::MotorOverflow(axis) => write!(f, "axis {} motor driver overflow", axis),
+            Alarm::MotorFollow(axis) => write!(f, "axis {} following error", axis),
+            Alarm::Unknown(code) => write!(f, "unknown alarm 0x{:02X}", code),
+        }
+    }
+}
+
+/// Alarm state bitfield: bit `code % 8` of byte `code / 8` is set for every
+/// raised alarm.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct AlarmSet {
+    bytes: Vec<u8>,
+}
+
+impl AlarmSet {
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            bytes: bytes.to_vec(),
+        }
+    }
+
+    /// Raw bitfield as read from the device.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.codes().iter().all(|b| *b == 0)
+    }
+
+    pub fn contains(&self, alarm: Alarm) -> bool {
+        let code = alarm.code() as usize;
+        self.bytes
+            .get(code / 8)
+            .is_some_and(|b| b & (1 << (code % 8)) != 0)
+    }
+
+    /// Sets the bit of `alarm`, growing the bitfield if needed.
+    pub fn insert(&mut self, alarm: Alarm) {
+        let code = alarm.code() as usize;
+        if self.bytes.len() <= code / 8 {
+            self.bytes.resize(code / 8 + 1, 0);
+        }
+        self.bytes[code / 8] |= 1 << (code % 8);
+    }
+
+    /// Raised alarms in ascending code order.
+    pub fn iter(&self) -> impl Iterator<Item = Alarm> + '_ {
+        self.codes().iter().enumerate().flat_map(|(i, b)| {
+            (0..8)
+                .filter(move |bit| b & (1 << bit) != 0)
+                .map(move |bit| Alarm::from_code((i * 8 + bit) as u8))
+        })
+    }
+
+    // bytes of codes that fit in a u8, a longer payload carries no alarms
+    fn codes(&self) -> &[u8] {
+        &self.bytes[..self.bytes.len().min(32)]
+    }
+}
+
+/// Alarm check of the waits on the command queue.
+///
+/// The first checked state is remembered. Later checks fail on alarms that
+/// stop motion and on any alarm raised since, so a stale `Reset` alarm
+/// from power-up does not abort a wait.
+#[derive(Default)]
+pub(crate) struct AlarmWatch {
+    before: Option<AlarmSet>,
+}
+
+impl AlarmWatch {
+    pub fn check(&mut self, alarms: AlarmSet) -> Result<(), DobotError> {
+        let before = self.before.get_or_insert_with(|| alarms.clone());
+        if alarms
+            .iter()
+            .any(|alarm| alarm.stops_motion() || !before.contains(alarm))
+        {
+            return Err(DobotError::Alarm(alarms));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for AlarmSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl fmt::Display for AlarmSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no alarm");
+        }
+        for (i, alarm) in self.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", alarm)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_round_trip() {
+        for code in 0..=255u8 {
+            assert_eq!(Alarm::from_code(code).code(), code);
+        }
+        let axis = |n| Axis::new(n).unwrap();
+        assert_eq!(Alarm::from_code(0x43), Alarm::LimitNegative(axis(2)));
+        assert_eq!(Alarm::from_code(0x46), Alarm::LimitPositive(axis(4)));
+        assert_eq!(Alarm::from_code(0x32), Alarm::Overspeed(axis(3)));
+    }
+
+    #[test]
+    fn axis_range() {
+        assert!(matches!(Axis::new(0), Err(DobotError::InvalidValue(_))));
+        assert!(matches!(Axis::new(5), Err(DobotError::InvalidValue(_))));
+        let code = Alarm::MotorFollow(Axis::new(4).unwrap()).code();
+        assert_eq!(
+            Alarm::from_code(code),
+            Alarm::MotorFollow(Axis::new(4).unwrap())
+        );
+        assert_eq!(Alarm::Overspeed(Axis::new(1).unwrap()).code(), 0x30);
+    }
+
+    #[test]
+    fn decode_bitfield() {
+        let mut bytes = vec![0; 16];
+        bytes[0] = 0b1;
+        bytes[2] = 0b10;
+        bytes[9] = 0b1000_0000;
+        let set = AlarmSet::from_bytes(&bytes);
+        assert!(!set.is_empty());
+        assert!(set.contains(Alarm::Reset));
+        assert!(set.contains(Alarm::PlanInvCalc));
+        assert!(!set.contains(Alarm::PlanInvLimit));
+        assert_eq!(
+            set.iter().collect::<Vec<_>>(),
+            vec![Alarm::Reset, Alarm::PlanInvCalc, Alarm::Unknown(0x4F)]
+        );
+        assert_eq!(
+            set.to_string(),
+            "controller reset, planning: target is unreachable, unknown alarm 0x4F"
+        );
+    }
+
+    #[test]
+    fn watch_ignores_stale_alarms() {
+        let mut watch = AlarmWatch::default();
+        let mut alarms = AlarmSet::default();
+        alarms.insert(Alarm::Reset);
+        watch.check(alarms.clone()).unwrap();
+        watch.check(alarms.clone()).unwrap();
+        alarms.insert(Alarm::AngleSensor);
+        assert!(matches!(watch.check(alarms), Err(DobotError::Alarm(_))));
+
+        let mut alarms = AlarmSet::default();
+        alarms.insert(Alarm::PlanInvLimit);
+        assert!(AlarmWatch::default().check(alarms).is_err());
+    }
+
+    #[test]
+    fn ignores_bytes_past_code_range() {
+        let mut bytes = vec![0; 33];
+        bytes[32] = 0b1;
+        let set = AlarmSet::from_bytes(&bytes);
+        assert!(set.is_empty());
+        assert_eq!(set.iter().count(), 0);
+        bytes[31] = 0b1000_0000;
+        let set = AlarmSet::from_bytes(&bytes);
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![Alarm::from_code(255)]);
+    }
+
+    #[test]
+    fn empty_and_insert() {
+        let mut set = AlarmSet::from_bytes(&[0; 16]);
+        assert!(set.is_empty());
+        assert_eq!(set.to_string(), "no alarm");
+        set.insert(Alarm::MotorFollow(Axis::new(4).unwrap()));
+        assert!(set.contains(Alarm::MotorFollow(Axis::new(4).unwrap())));
+        assert_eq!(set.as_bytes()[13], 0b1000);
+        assert_eq!(format!("{:?}", set), "{MotorFollow(Axis(4))}");
+    }
+}
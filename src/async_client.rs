@@ -0,0 +1,272 @@
+use crate::alarm::{AlarmSet, AlarmWatch};
+use crate::client::QueuedHandle;
+use crate::error::DobotError;
+use crate::mock::MockDevice;
+use crate::params::*;
+use crate::protocol::PayloadStruct;
+use crate::request::{self, request_methods, ClientState, Request, Start};
+use crate::safety::SafetyEnvelope;
+use crate::sim::SimulatedDevice;
+use crate::traits::Device;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::time;
+
+/// Async counterpart of `Device`.
+///
+/// Implementations must stay usable when a `send` future is dropped before
+/// it completes: the response to the abandoned request must never be
+/// returned for a later one.
+pub trait AsyncDevice {
+    fn send(
+        &mut self,
+        packet: PayloadStruct,
+    ) -> impl Future<Output = Result<PayloadStruct, DobotError>> + Send;
+
+    /// How long to wait for a response, see `Device::set_timeout`.
+    fn set_timeout(&mut self, _timeout: Duration) -> Result<(), DobotError> {
+        Ok(())
+    }
+}
+
+// The in-process devices answer right away, so they can be used from async
+// code (and tests) as they are.
+impl AsyncDevice for SimulatedDevice {
+    async fn send(&mut self, packet: PayloadStruct) -> Result<PayloadStruct, DobotError> {
+        Device::send(self, packet)
+    }
+}
+
+impl AsyncDevice for MockDevice {
+    async fn send(&mut self, packet: PayloadStruct) -> Result<PayloadStruct, DobotError> {
+        Device::send(self, packet)
+    }
+}
+
+/// `DobotClient` for tokio applications, every method of `DobotClient` is
+/// available as an `async fn` with the same name and behavior.
+pub struct AsyncDobotClient<T: AsyncDevice> {
+    device: T,
+    state: ClientState,
+}
+
+impl<T> AsyncDobotClient<T>
+where
+    T: AsyncDevice,
+{
+    pub fn new(device: T) -> Self {
+        Self {
+            device,
+            state: ClientState::default(),
+        }
+    }
+
+    pub fn device(&self) -> &T {
+        &self.device
+    }
+
+    pub fn device_mut(&mut self) -> &mut T {
+        &mut self.device
+    }
+
+    pub fn into_device(self) -> T {
+        self.device
+    }
+
+    /// Checks every PTP, CP, ARC and circle command and jog start against
+    /// `envelope` before it is sent. Rejected commands fail with `DobotError::SafetyViolation`.
+    ///
+    /// A jog is only checked as it starts, see `SafetyEnvelope::check_jog`.
+    pub fn set_safety_envelope(&mut self, envelope: Option<SafetyEnvelope>) {
+        self.state.safety = envelope;
+    }
+
+    pub fn safety_envelope(&self) -> Option<&SafetyEnvelope> {
+        self.state.safety.as_ref()
+    }
+
+    async fn call<R: 'static>(&mut self, request: Request<R>) -> Result<R, DobotError> {
+        let (packet, pending) = request.start(&mut self.state);
+        let response = self.device.send(packet).await?;
+        pending.finish(&mut self.state, response)
+    }
+
+    /// Reads where a motion starts if the safety envelope needs it.
+    async fn motion_start(&mut self) -> Result<Option<Start>, DobotError> {
+        if self.state.safety.is_none() {
+            return Ok(None);
+        }
+        let joints = match self.state.last_joints {
+            Some(joints) => joints,
+            None => self.get_pose().await?.joint_angles,
+        };
+        let orientation = match self.state.orientation {
+            Some(orientation) => orientation,
+            None => self.get_arm_orientation().await?,
+        };
+        Ok(Some(Start {
+            joints,
+            orientation,
+        }))
+    }
+
+    /// Whether the commands queued through this client have been executed.
+    /// Only tracked with a safety envelope, which is the only user.
+    async fn queue_idle(&mut self) -> Result<bool, DobotError> {
+        if self.state.safety.is_none() {
+            return Ok(true);
+        }
+        if let Some(index) = self.state.last_queued {
+            if self.get_queued_command_current_index().await? < index {
+                return Ok(false);
+            }
+            self.state.last_queued = None;
+        }
+        Ok(true)
+    }
+
+    request_methods!(async);
+
+    /// Reads the pose of the arm. Incremental motions start from it once
+    /// the commands queued through this client have been executed, until
+    /// then they start at the target of the last one.
+    pub async fn get_pose(&mut self) -> Result<Pose, DobotError> {
+        // read before the pose, which is then final if the queue is done
+        let idle = self.queue_idle().await?;
+        self.call(request::get_pose(idle)?).await
+    }
+
+    /// Queues a HOME command, starts queue execution and waits until the
+    /// arm has finished homing.
+    ///
+    /// Commands queued before are executed first.
+    pub async fn home_and_wait(
+        &mut self,
+        timeout: Duration,
+        poll_interval: Duration,
+    ) -> Result<(), DobotError> {
+        let handle = self.set_home_cmd_queued().await?;
+        self.set_queued_command_start_exec().await?;
+        self.wait_for_index(handle.index(), timeout, poll_interval)
+            .await
+    }
+
+    pub async fn set_jog_command(
+        &mut self,
+        mode: JogCommandType,
+        cmd: JogCommand,
+    ) -> Result<(), DobotError> {
+        let start = match cmd {
+            JogCommand::Idel => None,
+            _ => self.motion_start().await?,
+        };
+        self.call(request::set_jog_command(&self.state, start, mode, cmd)?)
+            .await
+    }
+
+    pub async fn set_jog_command_queued(
+        &mut self,
+        mode: JogCommandType,
+        cmd: JogCommand,
+    ) -> Result<QueuedHandle, DobotError> {
+        let start = match cmd {
+            JogCommand::Idel => None,
+            _ => self.motion_start().await?,
+        };
+        self.call(request::set_jog_command_queued(
+            &self.state,
+            start,
+            mode,
+            cmd,
+        )?)
+        .await
+    }
+
+    /// Waits until the queued command `index` has been executed.
+    ///
+    /// The alarm state is checked on every poll, so a command that can not
+    /// complete because the arm stopped on an alarm fails with
+    /// `DobotError::Alarm` instead of running into the timeout. Alarms that
+    /// do not stop motion only count when raised during the wait.
+    pub async fn wait_for_index(
+        &mut self,
+        index: u64,
+        timeout: Duration,
+        poll_interval: Duration,
+    ) -> Result<(), DobotError> {
+        let start = Instant::now();
+        let mut watch = AlarmWatch::default();
+        loop {
+            watch.check(self.get_alarm_state().await?)?;
+            if self.get_queued_command_current_index().await? >= index {
+                return Ok(());
+            }
+            if start.elapsed() >= timeout {
+                return Err(DobotError::Timeout);
+            }
+            time::sleep(poll_interval).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::Encode;
+    use crate::protocol::PayloadStruct;
+
+    #[tokio::test]
+    async fn mirrors_blocking_client() {
+        let mut mock = MockDevice::new();
+        mock.expect(
+            PayloadStruct::with_id(0),
+            PayloadStruct::with_id(0).set_params(b"SN01".to_vec()),
+        )
+        .expect(
+            PayloadStruct::with_id(131)
+                .set_write()
+                .set_params(vec![18, 1])
+                .set_queued(),
+            PayloadStruct::with_id(131).set_params(5u64.to_bytes()),
+        );
+        let mut dobot = AsyncDobotClient::new(mock);
+        assert_eq!(dobot.get_device_sn().await.unwrap(), "SN01");
+        let handle = dobot
+            .set_iodo_queued(IoPin::new(18).unwrap(), IoLevel::High)
+            .await
+            .unwrap();
+        assert_eq!(handle.index(), 5);
+        dobot.device().verify().unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn moves_simulator_from_spawned_task() {
+        let mut dobot = AsyncDobotClient::new(SimulatedDevice::new());
+        let pose = tokio::spawn(async move {
+            dobot
+                .home_and_wait(Duration::from_secs(10), Duration::from_millis(0))
+                .await?;
+            let handle = dobot
+                .set_ptp_command_queued(PtpCommand {
+                    ptp_mode: PtpMode::MovlXyz,
+                    x: 250.0,
+                    y: -50.0,
+                    z: 80.0,
+                    r: 0.0,
+                })
+                .await?;
+            dobot
+                .wait_for_index(
+                    handle.index(),
+                    Duration::from_secs(10),
+                    Duration::from_millis(0),
+                )
+                .await?;
+            dobot.get_pose().await
+        })
+        .await
+        .unwrap()
+        .unwrap();
+        assert!((pose.x - 250.0).abs() < 0.01 && (pose.y + 50.0).abs() < 0.01);
+    }
+}
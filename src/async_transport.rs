@@ -0,0 +1,307 @@
+use crate::async_client::AsyncDevice;
+use crate::decoder::FrameDecoder;
+use crate::error::DobotError;
+use crate::protocol::PayloadStruct;
+use crate::tcp::TcpDeviceConfig;
+use crate::udp::UdpDeviceConfig;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{self, TcpStream, ToSocketAddrs, UdpSocket};
+use tokio::time::{self, Instant};
+use tokio_serial::{DataBits, FlowControl, Parity, SerialPortBuilderExt, SerialStream, StopBits};
+
+/// Request/response matching over a byte stream, shared by the serial and
+/// TCP devices.
+struct FramedLink<S> {
+    stream: S,
+    decoder: FrameDecoder,
+    // id of a request whose future was dropped before its response arrived,
+    // and until when that response may still come
+    in_flight: Option<(u8, Instant)>,
+}
+
+impl<S> FramedLink<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn new(stream: S) -> Self {
+        Self {
+            stream,
+            decoder: FrameDecoder::new(),
+            in_flight: None,
+        }
+    }
+
+    async fn request(
+        &mut self,
+        packet: PayloadStruct,
+        write_timeout: Duration,
+        read_timeout: Duration,
+    ) -> Result<PayloadStruct, DobotError> {
+        if let Some((id, deadline)) = self.in_flight {
+            // A late response of the abandoned request is skipped by its id,
+            // unless ours has the same one. Then it has to be swallowed, but
+            // it is not waited for beyond its own timeout.
+            if id == packet.id {
+                let _ = time::timeout_at(deadline, self.read_frame(id)).await;
+            }
+            self.in_flight = None;
+        }
+        self.decoder.clear();
+        let id = packet.id;
+        self.in_flight = Some((id, Instant::now() + write_timeout + read_timeout));
+        let result = self
+            .exchange(id, &packet.serialize(), write_timeout, read_timeout)
+            .await;
+        // not reached when the future is dropped half way
+        self.in_flight = None;
+        result
+    }
+
+    async fn exchange(
+        &mut self,
+        id: u8,
+        buf: &[u8],
+        write_timeout: Duration,
+        read_timeout: Duration,
+    ) -> Result<PayloadStruct, DobotError> {
+        let write = async {
+            self.stream.write_all(buf).await?;
+            self.stream.flush().await
+        };
+        time::timeout(write_timeout, write)
+            .await
+            .map_err(|_| DobotError::Timeout)??;
+        time::timeout(read_timeout, self.read_frame(id))
+            .await
+            .map_err(|_| DobotError::Timeout)?
+    }
+
+    async fn read_frame(&mut self, id: u8) -> Result<PayloadStruct, DobotError> {
+        let mut buf = [0; 256];
+        loop {
+            while let Some(frame) = self.decoder.next_frame() {
+                if frame.id == id {
+                    return Ok(frame);
+                }
+            }
+            // `read` is cancel safe, no byte is lost if the timeout fires
+            let size = self.stream.read(&mut buf).await?;
+            if size == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            self.decoder.push(&buf[..size]);
+        }
+    }
+}
+
+/// Async `SerialDevice`.
+///
+/// Waits 1000 ms for a response and as long for a request to be written,
+/// change it with `AsyncDevice::set_timeout`.
+pub struct AsyncSerialDevice {
+    link: FramedLink<SerialStream>,
+    timeout: Duration,
+}
+
+impl AsyncSerialDevice {
+    /// Opens `path` with 115200 baud 8N1. Must be called from within a tokio
+    /// runtime.
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, DobotError> {
+        let stream = tokio_serial::new(path.as_ref().to_string_lossy(), 115_200)
+            .data_bits(DataBits::Eight)
+            .stop_bits(StopBits::One)
+            .parity(Parity::None)
+            .flow_control(FlowControl::None)
+            .open_native_async()
+            .map_err(io::Error::from)?;
+        Ok(Self {
+            link: FramedLink::new(stream),
+            timeout: Duration::from_millis(1000),
+        })
+    }
+}
+
+impl AsyncDevice for AsyncSerialDevice {
+    async fn send(&mut self, packet: PayloadStruct) -> Result<PayloadStruct, DobotError> {
+        self.link.request(packet, self.timeout, self.timeout).await
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), DobotError> {
+        self.timeout = timeout;
+        Ok(())
+    }
+}
+
+/// Async `TcpDevice`.
+///
+/// The connection is re-established on the next request whenever it drops.
+pub struct AsyncTcpDevice {
+    addrs: Vec<SocketAddr>,
+    config: TcpDeviceConfig,
+    link: Option<FramedLink<TcpStream>>,
+}
+
+impl AsyncTcpDevice {
+    pub async fn new<A: ToSocketAddrs>(addr: A) -> Result<Self, DobotError> {
+        Self::with_config(addr, TcpDeviceConfig::default()).await
+    }
+
+    pub async fn with_config<A: ToSocketAddrs>(
+        addr: A,
+        config: TcpDeviceConfig,
+    ) -> Result<Self, DobotError> {
+        let mut device = Self {
+            addrs: net::lookup_host(addr).await?.collect(),
+            config,
+            link: None,
+        };
+        device.connect().await?;
+        Ok(device)
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.link.is_some()
+    }
+
+    async fn connect(&mut self) -> Result<&mut FramedLink<TcpStream>, DobotError> {
+        self.link = None;
+        let mut last_err = DobotError::Io(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "no socket address to connect to",
+        ));
+        for addr in &self.addrs {
+            match time::timeout(self.config.connect_timeout, TcpStream::connect(addr)).await {
+                Ok(Ok(stream)) => {
+                    stream.set_nodelay(true)?;
+                    return Ok(self.link.get_or_insert(FramedLink::new(stream)));
+                }
+                Ok(Err(e)) => last_err = e.into(),
+                Err(_) => last_err = DobotError::Timeout,
+            }
+        }
+        Err(last_err)
+    }
+
+    async fn request(&mut self, packet: PayloadStruct) -> Result<PayloadStruct, DobotError> {
+        let TcpDeviceConfig {
+            write_timeout,
+            read_timeout,
+            ..
+        } = self.config;
+        let link = match self.link.as_mut() {
+            Some(link) => link,
+            None => self.connect().await?,
+        };
+        let result = link.request(packet, write_timeout, read_timeout).await;
+        if let Err(DobotError::Io(_)) = result {
+            self.link = None;
+        }
+        result
+    }
+}
+
+impl AsyncDevice for AsyncTcpDevice {
+    async fn send(&mut self, packet: PayloadStruct) -> Result<PayloadStruct, DobotError> {
+        self.request(packet).await
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), DobotError> {
+        self.config.read_timeout = timeout;
+        Ok(())
+    }
+}
+
+/// Async `UdpDevice`.
+pub struct AsyncUdpDevice {
+    socket: UdpSocket,
+    decoder: FrameDecoder,
+    timeout: Duration,
+    retries: usize,
+    // id of a request whose future was dropped before its response arrived,
+    // and until when that response may still come
+    in_flight: Option<(u8, Instant)>,
+}
+
+impl AsyncUdpDevice {
+    pub async fn new(ip: IpAddr) -> Result<Self, DobotError> {
+        Self::with_config(ip, UdpDeviceConfig::default()).await
+    }
+
+    pub async fn with_config(ip: IpAddr, config: UdpDeviceConfig) -> Result<Self, DobotError> {
+        let socket = UdpSocket::bind(config.local_addr).await?;
+        socket.connect((ip, config.remote_port)).await?;
+        Ok(Self {
+            socket,
+            decoder: FrameDecoder::new(),
+            timeout: config.timeout,
+            retries: config.retries,
+            in_flight: None,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, io::Error> {
+        self.socket.local_addr()
+    }
+
+    async fn request(&mut self, packet: PayloadStruct) -> Result<PayloadStruct, DobotError> {
+        // see `FramedLink::request`
+        if let Some((id, deadline)) = self.in_flight {
+            if id == packet.id {
+                let _ = time::timeout_at(deadline, self.recv_frame(id)).await;
+            }
+            self.in_flight = None;
+        }
+        // queued writes are never re-sent, like in `UdpDevice`
+        let retries = if packet.is_queued { 0 } else { self.retries };
+        let id = packet.id;
+        let deadline = Instant::now() + self.timeout * (retries as u32 + 1);
+        self.in_flight = Some((id, deadline));
+        let result = self.exchange(id, &packet.serialize(), retries).await;
+        self.in_flight = None;
+        result
+    }
+
+    async fn exchange(
+        &mut self,
+        id: u8,
+        buf: &[u8],
+        retries: usize,
+    ) -> Result<PayloadStruct, DobotError> {
+        for _ in 0..=retries {
+            self.decoder.clear();
+            self.socket.send(buf).await?;
+            if let Ok(frame) = time::timeout(self.timeout, self.recv_frame(id)).await {
+                return frame;
+            }
+        }
+        Err(DobotError::Timeout)
+    }
+
+    async fn recv_frame(&mut self, id: u8) -> Result<PayloadStruct, DobotError> {
+        let mut buf = [0; 512];
+        loop {
+            while let Some(frame) = self.decoder.next_frame() {
+                if frame.id == id {
+                    return Ok(frame);
+                }
+            }
+            let size = self.socket.recv(&mut buf).await?;
+            self.decoder.push(&buf[..size]);
+        }
+    }
+}
+
+impl AsyncDevice for AsyncUdpDevice {
+    async fn send(&mut self, packet: PayloadStruct) -> Result<PayloadStruct, DobotError> {
+        self.request(packet).await
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), DobotError> {
+        self.timeout = timeout;
+        Ok(())
+    }
+}
@@ -0,0 +1,462 @@
+use dobot_client::*;
+use std::fmt;
+use std::net::IpAddr;
+use std::time::Duration;
+
+const USAGE: &str = "\
+usage: dobot <URL> <COMMAND> [ARGS]
+       dobot commands
+
+URL:
+    serial://<path>           e.g. serial:///dev/ttyUSB0
+    udp://<ip>[:<port>]       e.g. udp://192.168.0.10
+    tcp://<host>:<port>
+    sim://                    in-process simulator
+
+COMMAND:
+    info
+    pose
+    alarms [--clear]
+    move [--mode <mode>] [--wait] <x> <y> <z> <r>
+    home [--wait]
+    jog <joint|cartesian> <ap|an|bp|bn|cp|cn|dp|dn> [<ms>]
+    io set <address> <high|low>
+    queue start|stop|force-stop|clear|status
+    params get <name>
+    params set <name> <values>...
+    raw [--write] [--queued] <name> [<hex params>]
+
+move modes:
+    jump, movj (default), movl, jump-angle, movj-angle, movl-angle,
+    movj-inc, movl-inc, movj-xyz-inc, jump-movl-xyz
+
+params names and values:
+    home            x y z r
+    jog-joint       4 velocities, 4 accelerations
+    jog-coordinate  4 velocities, 4 accelerations
+    jog-common      velocity_ratio acceleration_ratio
+    ptp-joint       4 velocities, 4 accelerations
+    ptp-coordinate  xyz_velocity r_velocity xyz_acceleration r_acceleration
+    ptp-jump        jump_height z_limit
+    ptp-common      velocity_ratio acceleration_ratio
+
+`dobot commands` lists the protocol commands `raw` can send.
+";
+
+const WAIT_TIMEOUT: Duration = Duration::from_secs(60);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const JOG_STOP_ATTEMPTS: usize = 3;
+
+#[derive(Debug)]
+enum CliError {
+    Usage(String),
+    Dobot(DobotError),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CliError::Usage(message) => write!(f, "{}", message),
+            CliError::Dobot(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<DobotError> for CliError {
+    fn from(e: DobotError) -> Self {
+        CliError::Dobot(e)
+    }
+}
+
+fn usage<T>(message: impl Into<String>) -> Result<T, CliError> {
+    Err(CliError::Usage(message.into()))
+}
+
+fn open(url: &str) -> Result<Box<dyn Device>, CliError> {
+    let (scheme, rest) = match url.find("://") {
+        Some(i) => (&url[..i], &url[i + 3..]),
+        None => return usage(format!("invalid URL `{}`", url)),
+    };
+    let device: Box<dyn Device> = match scheme {
+        "serial" => Box::new(SerialDevice::new(rest)?),
+        "udp" => {
+            let mut config = UdpDeviceConfig::default();
+            let host = match rest.rsplit_once(':') {
+                Some((host, port)) if !host.is_empty() && !host.ends_with(':') => {
+                    config.remote_port = parse(port, "port")?;
+                    host
+                }
+                _ => rest,
+            };
+            let ip: IpAddr = parse(host, "IP address")?;
+            Box::new(UdpDevice::with_config(ip, config)?)
+        }
+        "tcp" => Box::new(TcpDevice::new(rest)?),
+        "sim" => Box::new(SimulatedDevice::new()),
+        _ => return usage(format!("unknown transport `{}`", scheme)),
+    };
+    Ok(device)
+}
+
+fn parse<T: std::str::FromStr>(s: &str, what: &str) -> Result<T, CliError> {
+    match s.parse() {
+        Ok(v) => Ok(v),
+        Err(_) => usage(format!("invalid {} `{}`", what, s)),
+    }
+}
+
+fn floats<const N: usize>(args: &[String]) -> Result<[f32; N], CliError> {
+    if args.len() != N {
+        return usage(format!("expected {} values, got {}", N, args.len()));
+    }
+    let mut values = [0.0; N];
+    for (v, arg) in values.iter_mut().zip(args) {
+        *v = parse(arg, "number")?;
+    }
+    Ok(values)
+}
+
+fn split_flags(args: &[String]) -> (Vec<&str>, Vec<String>) {
+    let (flags, rest): (Vec<&String>, Vec<&String>) = args
+        .iter()
+        .partition(|a| a.starts_with("--") && a.parse::<f32>().is_err());
+    (
+        flags.into_iter().map(|s| s.as_str()).collect(),
+        rest.into_iter().cloned().collect(),
+    )
+}
+
+fn ptp_mode(name: &str) -> Result<PtpMode, CliError> {
+    Ok(match name {
+        "jump" => PtpMode::JumpXyz,
+        "movj" => PtpMode::MovjXyz,
+        "movl" => PtpMode::MovlXyz,
+        "jump-angle" => PtpMode::JumpAngle,
+        "movj-angle" => PtpMode::MovjAngle,
+        "movl-angle" => PtpMode::MovlAngle,
+        "movj-inc" => PtpMode::MovjInc,
+        "movl-inc" => PtpMode::MovlInc,
+        "movj-xyz-inc" => PtpMode::MovjXyzInc,
+        "jump-movl-xyz" => PtpMode::JumpMovlXyz,
+        _ => return usage(format!("unknown move mode `{}`", name)),
+    })
+}
+
+fn jog_command(name: &str) -> Result<JogCommand, CliError> {
+    Ok(match name {
+        "ap" => JogCommand::ApDown,
+        "an" => JogCommand::AnDown,
+        "bp" => JogCommand::BpDown,
+        "bn" => JogCommand::BnDown,
+        "cp" => JogCommand::CpDown,
+        "cn" => JogCommand::CnDown,
+        "dp" => JogCommand::DpDown,
+        "dn" => JogCommand::DnDown,
+        _ => return usage(format!("unknown jog direction `{}`", name)),
+    })
+}
+
+/// Sends `Idel` until it goes through, then falls back to a force stop so
+/// the arm never keeps jogging. Fails with the first error if `Idel` never
+/// went through.
+fn stop_jog<T: Device>(dobot: &mut DobotClient<T>, mode: JogCommandType) -> Result<(), DobotError> {
+    let mut error = None;
+    for _ in 0..JOG_STOP_ATTEMPTS {
+        match dobot.set_jog_command(mode, JogCommand::Idel) {
+            Ok(()) => return Ok(()),
+            Err(e) => {
+                error.get_or_insert(e);
+            }
+        }
+    }
+    let _ = dobot.set_queued_command_force_stop_exec();
+    Err(error.unwrap())
+}
+
+fn run_move<T: Device>(dobot: &mut DobotClient<T>, args: &[String]) -> Result<(), CliError> {
+    let mut mode = PtpMode::MovjXyz;
+    let mut wait = false;
+    let mut values = vec![];
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--mode" => match iter.next() {
+                Some(name) => mode = ptp_mode(name)?,
+                None => return usage("--mode needs a value"),
+            },
+            "--wait" => wait = true,
+            _ => values.push(arg.clone()),
+        }
+    }
+    let [x, y, z, r] = floats::<4>(&values)?;
+    let command = PtpCommand {
+        ptp_mode: mode,
+        x,
+        y,
+        z,
+        r,
+    };
+    let handle = dobot.set_ptp_command_queued(command)?;
+    println!("queued index {}", handle.index());
+    if wait {
+        dobot.set_queued_command_start_exec()?;
+        handle.wait(dobot, WAIT_TIMEOUT, POLL_INTERVAL)?;
+        println!("{:?}", dobot.get_pose()?);
+    }
+    Ok(())
+}
+
+fn run_params<T: Device>(dobot: &mut DobotClient<T>, args: &[String]) -> Result<(), CliError> {
+    match args {
+        [op, name] if op == "get" => match name.as_str() {
+            "home" => println!("{:?}", dobot.get_home_params()?),
+            "jog-joint" => println!("{:?}", dobot.get_jog_joint_params()?),
+            "jog-coordinate" => println!("{:?}", dobot.get_jog_coordinate_params()?),
+            "jog-common" => println!("{:?}", dobot.get_jog_common_params()?),
+            "ptp-joint" => println!("{:?}", dobot.get_ptp_joint_params()?),
+            "ptp-coordinate" => println!("{:?}", dobot.get_ptp_coordinate_params()?),
+            "ptp-jump" => println!("{:?}", dobot.get_ptp_jump_params()?),
+            "ptp-common" => println!("{:?}", dobot.get_ptp_common_params()?),
+            _ => return usage(format!("unknown params `{}`", name)),
+        },
+        [op, name, values @ ..] if op == "set" => match name.as_str() {
+            "home" => {
+                let [x, y, z, r] = floats(values)?;
+                dobot.set_home_params(HomeParams { x, y, z, r })?
+            }
+            "jog-joint" => {
+                let (velocity, acceleration) = split_joint_values(values)?;
+                dobot.set_jog_joint_params(JogJointParams {
+                    velocity,
+                    acceleration,
+                })?
+            }
+            "jog-coordinate" => {
+                let (velocity, acceleration) = split_joint_values(values)?;
+                dobot.set_jog_coordinate_params(JogCoordinateParams {
+                    velocity,
+                    acceleration,
+                })?
+            }
+            "jog-common" => {
+                let [velocity_ratio, acceleration_ratio] = floats(values)?;
+                dobot.set_jog_common_params(JogCommonParams {
+                    velocity_ratio,
+                    acceleration_ratio,
+                })?
+            }
+            "ptp-joint" => {
+                let (velocity, acceleration) = split_joint_values(values)?;
+                dobot.set_ptp_joint_params(PtpJointParams {
+                    velocity,
+                    acceleration,
+                })?
+            }
+            "ptp-coordinate" => {
+                let [xyz_velocity, r_velocity, xyz_acceleration, r_acceleration] = floats(values)?;
+                dobot.set_ptp_coordinate_params(PtpCoordinateParams {
+                    xyz_velocity,
+                    r_velocity,
+                    xyz_acceleration,
+                    r_acceleration,
+                })?
+            }
+            "ptp-jump" => {
+                let [jump_height, z_limit] = floats(values)?;
+                dobot.set_ptp_jump_params(PtpJumpParams {
+                    jump_height,
+                    z_limit,
+                    dummy: 0,
+                })?
+            }
+            "ptp-common" => {
+                let [velocity_ratio, acceleration_ratio] = floats(values)?;
+                dobot.set_ptp_common_params(PtpCommonParams {
+                    velocity_ratio,
+                    acceleration_ratio,
+                })?
+            }
+            _ => return usage(format!("unknown params `{}`", name)),
+        },
+        _ => return usage("expected `params get <name>` or `params set <name> <values>...`"),
+    }
+    Ok(())
+}
+
+fn split_joint_values(values: &[String]) -> Result<([f32; 4], [f32; 4]), CliError> {
+    let v = floats::<8>(values)?;
+    Ok(([v[0], v[1], v[2], v[3]], [v[4], v[5], v[6], v[7]]))
+}
+
+fn print_commands() {
+    let yes = |b| if b { "yes" } else { "-" };
+    println!(
+        "{:>3}  {:<24} {:<5} {:<5} {:<6} params",
+        "id", "name", "read", "write", "queued"
+    );
+    for &id in CommandId::ALL {
+        let info = id.info();
+        let params = match (info.write_params, info.read_request) {
+            (Some(params), _) | (None, Some(params)) => params.to_string(),
+            (None, None) => String::new(),
+        };
+        println!(
+            "{:>3}  {:<24} {:<5} {:<5} {:<6} {}",
+            id as u8,
+            info.name,
+            yes(info.read),
+            yes(info.write),
+            yes(info.queued),
+            params
+        );
+    }
+}
+
+fn hex_params(hex: &str) -> Result<Vec<u8>, CliError> {
+    let hex: String = hex.chars().filter(|c| !c.is_whitespace()).collect();
+    if hex.len() & 1 == 1 {
+        return usage(format!("invalid hex `{}`", hex));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| match u8::from_str_radix(&hex[i..i + 2], 16) {
+            Ok(b) => Ok(b),
+            Err(_) => usage(format!("invalid hex `{}`", hex)),
+        })
+        .collect()
+}
+
+/// Sends one request built from the command table and prints both sides.
+fn run_raw<T: Device>(dobot: &mut DobotClient<T>, args: &[String]) -> Result<(), CliError> {
+    let (flags, rest) = split_flags(args);
+    let (name, hex) = match &rest[..] {
+        [name] => (name, ""),
+        [name, hex] => (name, hex.as_str()),
+        _ => return usage("expected `raw [--write] [--queued] <name> [<hex params>]`"),
+    };
+    let id = match CommandId::from_name(name) {
+        Some(id) => id,
+        None => return usage(format!("unknown command `{}`, see `dobot commands`", name)),
+    };
+    let rw = if flags.contains(&"--write") || flags.contains(&"--queued") {
+        ReadWrite::WRITE
+    } else {
+        ReadWrite::READ
+    };
+    let request = id.request(rw, flags.contains(&"--queued"), hex_params(hex)?)?;
+    println!("> {}", request);
+    let response = dobot.device_mut().send(request)?;
+    println!("< {}", response);
+    Ok(())
+}
+
+fn run(args: &[String]) -> Result<(), CliError> {
+    if let [command] = args {
+        if command == "commands" {
+            print_commands();
+            return Ok(());
+        }
+    }
+    let (url, command, rest) = match args {
+        [url, command, rest @ ..] => (url, command.as_str(), rest),
+        _ => return usage("missing URL or command"),
+    };
+    let mut dobot = DobotClient::new(open(url)?);
+    match command {
+        "info" => {
+            println!("serial number: {}", dobot.get_device_sn()?);
+            println!("arm orientation: {:?}", dobot.get_arm_orientation()?);
+            println!("alarms: {}", dobot.get_alarm_state()?);
+        }
+        "pose" => println!("{:?}", dobot.get_pose()?),
+        "alarms" => {
+            let (flags, _) = split_flags(rest);
+            println!("{}", dobot.get_alarm_state()?);
+            if flags.contains(&"--clear") {
+                dobot.clear_all_alarm_state()?;
+                println!("cleared");
+            }
+        }
+        "move" => run_move(&mut dobot, rest)?,
+        "home" => {
+            let (flags, _) = split_flags(rest);
+            if flags.contains(&"--wait") {
+                dobot.home_and_wait(WAIT_TIMEOUT, POLL_INTERVAL)?;
+            } else {
+                let handle = dobot.set_home_cmd_queued()?;
+                println!("queued index {}", handle.index());
+            }
+        }
+        "jog" => {
+            let (mode, direction, ms) = match rest {
+                [mode, direction] => (mode, direction, 500),
+                [mode, direction, ms] => (mode, direction, parse(ms, "duration")?),
+                _ => return usage("expected `jog <joint|cartesian> <direction> [<ms>]`"),
+            };
+            let mode = match mode.as_str() {
+                "joint" => JogCommandType::Joint,
+                "cartesian" => JogCommandType::Cartesian,
+                _ => return usage(format!("unknown jog mode `{}`", mode)),
+            };
+            let command = jog_command(direction)?;
+            // the start may have arrived even if its response did not
+            let started = dobot.set_jog_command(mode, command);
+            if started.is_ok() {
+                std::thread::sleep(Duration::from_millis(ms));
+            }
+            let stopped = stop_jog(&mut dobot, mode);
+            started?;
+            stopped?;
+            println!("{:?}", dobot.get_pose()?);
+        }
+        "io" => match rest {
+            [op, address, level] if op == "set" => {
+                let level = match level.as_str() {
+                    "high" | "1" => IoLevel::High,
+                    "low" | "0" => IoLevel::Low,
+                    _ => return usage(format!("invalid level `{}`", level)),
+                };
+                let pin = IoPin::new(parse(address, "address")?)?;
+                dobot.set_iodo(pin, level)?;
+            }
+            _ => return usage("expected `io set <address> <high|low>`"),
+        },
+        "queue" => match rest.first().map(|s| s.as_str()) {
+            Some("start") => dobot.set_queued_command_start_exec()?,
+            Some("stop") => dobot.set_queued_command_stop_exec()?,
+            Some("force-stop") => dobot.set_queued_command_force_stop_exec()?,
+            Some("clear") => dobot.set_queued_command_clear()?,
+            Some("status") => {
+                println!(
+                    "current index: {}",
+                    dobot.get_queued_command_current_index()?
+                );
+                println!("left space: {}", dobot.get_queued_command_left_space()?);
+            }
+            _ => return usage("expected `queue start|stop|force-stop|clear|status`"),
+        },
+        "params" => run_params(&mut dobot, rest)?,
+        "raw" => run_raw(&mut dobot, rest)?,
+        _ => return usage(format!("unknown command `{}`", command)),
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() || args.iter().any(|a| a == "-h" || a == "--help") {
+        print!("{}", USAGE);
+        return;
+    }
+    match run(&args) {
+        Ok(()) => {}
+        Err(CliError::Usage(message)) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            std::process::exit(2);
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,353 @@
+use crate::error::DobotError;
+use crate::protocol::PayloadStruct;
+use crate::traits::Device;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Capture files are plain text, one event per line:
+//
+//   # dobot capture 1, started at 1700000000.123456
+//   0.000120 > AAAA0206000000FA
+//   0.004310 < AAAA...
+//   1.004400 ! timeout
+//
+// The first column is the time since the start of the recording in seconds,
+// `>` is a request, `<` its response and `!` a failed request.
+const HEADER: &str = "# dobot capture 1";
+const TIMEOUT: &str = "timeout";
+
+fn to_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        let _ = write!(hex, "{:02X}", b);
+    }
+    hex
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() & 1 == 1 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Wraps a `Device` and logs every request and response to a capture file
+/// that `ReplayDevice` can play back.
+///
+/// Each exchange is flushed as soon as it is complete, so the log survives
+/// a crash of the application.
+pub struct RecordingDevice<D: Device, W: Write = BufWriter<File>> {
+    device: D,
+    writer: W,
+    start: Instant,
+}
+
+impl<D: Device> RecordingDevice<D> {
+    /// Records to a new file at `path`, replacing an existing one.
+    pub fn create<P: AsRef<Path>>(device: D, path: P) -> Result<Self, DobotError> {
+        Self::new(device, BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<D: Device, W: Write> RecordingDevice<D, W> {
+    pub fn new(device: D, mut writer: W) -> Result<Self, DobotError> {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        writeln!(
+            writer,
+            "{}, started at {}.{:06}",
+            HEADER,
+            started.as_secs(),
+            started.subsec_micros()
+        )?;
+        writer.flush()?;
+        Ok(Self {
+            device,
+            writer,
+            start: Instant::now(),
+        })
+    }
+
+    pub fn device(&self) -> &D {
+        &self.device
+    }
+
+    pub fn device_mut(&mut self) -> &mut D {
+        &mut self.device
+    }
+
+    pub fn into_inner(self) -> (D, W) {
+        (self.device, self.writer)
+    }
+
+    fn log(&mut self, kind: char, data: &str) -> Result<(), io::Error> {
+        let elapsed = self.start.elapsed();
+        writeln!(
+            self.writer,
+            "{}.{:06} {} {}",
+            elapsed.as_secs(),
+            elapsed.subsec_micros(),
+            kind,
+            data
+        )
+    }
+}
+
+impl<D: Device, W: Write> Device for RecordingDevice<D, W> {
+    fn send(&mut self, packet: PayloadStruct) -> Result<PayloadStruct, DobotError> {
+        self.log('>', &to_hex(&packet.clone().serialize()))?;
+        let result = self.device.send(packet);
+        match &result {
+            Ok(response) => self.log('<', &to_hex(&response.clone().serialize()))?,
+            Err(DobotError::Timeout) => self.log('!', TIMEOUT)?,
+            // keep it on one line
+            Err(e) => self.log('!', &e.to_string().replace('\n', " "))?,
+        }
+        self.writer.flush()?;
+        result
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), DobotError> {
+        self.device.set_timeout(timeout)
+    }
+
+    fn flush_input(&mut self) -> Result<(), DobotError> {
+        self.device.flush_input()
+    }
+
+    fn disable_retries(&mut self) {
+        self.device.disable_retries()
+    }
+}
+
+/// One request of a capture and what the device answered.
+#[derive(Debug)]
+pub struct CapturedExchange {
+    /// Time since the start of the recording.
+    pub at: Duration,
+    pub request: PayloadStruct,
+    /// The response, or the error message of a failed request.
+    pub response: Result<PayloadStruct, String>,
+}
+
+/// Plays back a capture of `RecordingDevice` as a `Device`.
+///
+/// Requests must arrive in the recorded order and match the recorded ones
+/// exactly, otherwise `send` fails with `DobotError::UnexpectedRequest`.
+/// Recorded timeouts are replayed as `DobotError::Timeout`, other recorded
+/// errors as `DobotError::Io`.
+///
+/// ```
+/// use dobot_client::*;
+///
+/// let recording = RecordingDevice::new(SimulatedDevice::new(), vec![]).unwrap();
+/// let mut dobot = DobotClient::new(recording);
+/// dobot.get_pose().unwrap();
+/// let (_, log) = dobot.into_device().into_inner();
+///
+/// let mut dobot = DobotClient::new(ReplayDevice::from_reader(&log[..]).unwrap());
+/// dobot.get_pose().unwrap();
+/// dobot.device().verify().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct ReplayDevice {
+    exchanges: VecDeque<CapturedExchange>,
+    replayed: usize,
+}
+
+fn parse_error(line: usize, message: &str) -> DobotError {
+    DobotError::InvalidValue(format!("capture line {}: {}", line, message))
+}
+
+fn parse_time(text: &str) -> Option<Duration> {
+    let (secs, micros) = text.split_once('.')?;
+    Some(Duration::from_secs(secs.parse().ok()?) + Duration::from_micros(micros.parse().ok()?))
+}
+
+fn parse_frame(hex: &str) -> Option<PayloadStruct> {
+    PayloadStruct::deserialize(&from_hex(hex)?).ok()
+}
+
+impl ReplayDevice {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, DobotError> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Self, DobotError> {
+        let mut exchanges = VecDeque::new();
+        let mut request: Option<(Duration, PayloadStruct)> = None;
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let number = i + 1;
+            if i == 0 && !line.starts_with(HEADER) {
+                return Err(parse_error(number, "not a dobot capture"));
+            }
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.splitn(3, ' ');
+            let at = fields
+                .next()
+                .and_then(parse_time)
+                .ok_or_else(|| parse_error(number, "invalid time"))?;
+            let kind = fields.next().unwrap_or("");
+            let data = fields.next().unwrap_or("");
+            match (kind, request.take()) {
+                (">", None) => {
+                    let frame =
+                        parse_frame(data).ok_or_else(|| parse_error(number, "invalid frame"))?;
+                    request = Some((at, frame));
+                }
+                ("<", Some((at, request))) => exchanges.push_back(CapturedExchange {
+                    at,
+                    request,
+                    response: Ok(
+                        parse_frame(data).ok_or_else(|| parse_error(number, "invalid frame"))?
+                    ),
+                }),
+                ("!", Some((at, request))) => exchanges.push_back(CapturedExchange {
+                    at,
+                    request,
+                    response: Err(data.to_owned()),
+                }),
+                _ => return Err(parse_error(number, "unexpected event")),
+            }
+        }
+        // the application died while waiting for the last response
+        if let Some((at, request)) = request {
+            exchanges.push_back(CapturedExchange {
+                at,
+                request,
+                response: Err(TIMEOUT.to_owned()),
+            });
+        }
+        Ok(Self {
+            exchanges,
+            replayed: 0,
+        })
+    }
+
+    /// Exchanges not replayed yet.
+    pub fn remaining(&self) -> &VecDeque<CapturedExchange> {
+        &self.exchanges
+    }
+
+    /// Fails if part of the capture has not been replayed.
+    pub fn verify(&self) -> Result<(), DobotError> {
+        match self.exchanges.front() {
+            None => Ok(()),
+            Some(next) => Err(DobotError::UnexpectedRequest(format!(
+                "{} captured requests were never sent, the next one is {}",
+                self.exchanges.len(),
+                next.request
+            ))),
+        }
+    }
+}
+
+impl Device for ReplayDevice {
+    fn send(&mut self, packet: PayloadStruct) -> Result<PayloadStruct, DobotError> {
+        let number = self.replayed + 1;
+        let expected = match self.exchanges.front() {
+            Some(exchange) => &exchange.request,
+            None => {
+                return Err(DobotError::UnexpectedRequest(format!(
+                    "request {} is beyond the end of the capture: {}",
+                    number, packet
+                )))
+            }
+        };
+        if *expected != packet {
+            return Err(DobotError::UnexpectedRequest(format!(
+                "request {} differs from the capture: expected {}, got {}",
+                number, expected, packet
+            )));
+        }
+        self.replayed += 1;
+        match self.exchanges.pop_front().unwrap().response {
+            Ok(response) => Ok(response),
+            Err(ref e) if e == TIMEOUT => Err(DobotError::Timeout),
+            Err(e) => Err(DobotError::Io(io::Error::other(e))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::DobotClient;
+    use crate::mock::MockDevice;
+    use crate::params::*;
+    use crate::sim::SimulatedDevice;
+
+    #[test]
+    fn hex() {
+        assert_eq!(to_hex(&[0xAA, 0x01, 0xFF]), "AA01FF");
+        assert_eq!(from_hex("AA01ff"), Some(vec![0xAA, 0x01, 0xFF]));
+        assert_eq!(from_hex("AA0"), None);
+        assert_eq!(from_hex("XX"), None);
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let path = std::env::temp_dir().join(format!("dobot-capture-{}.log", std::process::id()));
+        let mut dobot =
+            DobotClient::new(RecordingDevice::create(SimulatedDevice::new(), &path).unwrap());
+        dobot.set_home_cmd().unwrap();
+        dobot
+            .set_iodo(IoPin::new(18).unwrap(), IoLevel::High)
+            .unwrap();
+        let pose = dobot.get_pose().unwrap();
+        drop(dobot);
+
+        let mut dobot = DobotClient::new(ReplayDevice::open(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+        dobot.set_home_cmd().unwrap();
+        assert!(dobot
+            .set_iodo(IoPin::new(18).unwrap(), IoLevel::Low)
+            .is_err());
+        assert!(dobot.device().verify().is_err());
+        dobot
+            .set_iodo(IoPin::new(18).unwrap(), IoLevel::High)
+            .unwrap();
+        assert_eq!(dobot.get_pose().unwrap(), pose);
+        dobot.device().verify().unwrap();
+        assert!(dobot.get_pose().is_err());
+    }
+
+    #[test]
+    fn replay_errors() {
+        let mut mock = MockDevice::new();
+        mock.expect_with(PayloadStruct::with_id(10), |_| Err(DobotError::Timeout));
+        let mut recording = RecordingDevice::new(mock, vec![]).unwrap();
+        assert!(recording.send(PayloadStruct::with_id(10)).is_err());
+        assert!(recording.send(PayloadStruct::with_id(11)).is_err());
+        let (_, log) = recording.into_inner();
+        let text = String::from_utf8(log).unwrap();
+        assert!(text.lines().nth(2).unwrap().ends_with(" ! timeout"));
+
+        let mut replay = ReplayDevice::from_reader(text.as_bytes()).unwrap();
+        assert_eq!(replay.remaining().len(), 2);
+        assert!(matches!(
+            replay.send(PayloadStruct::with_id(10)),
+            Err(DobotError::Timeout)
+        ));
+        assert!(matches!(
+            replay.send(PayloadStruct::with_id(11)),
+            Err(DobotError::Io(_))
+        ));
+
+        assert!(ReplayDevice::from_reader(&b"0.1 > AAAA"[..]).is_err());
+        let truncated = format!("{}\n0.000001 > AAAA020A00F6\n", HEADER);
+        let replay = ReplayDevice::from_reader(truncated.as_bytes()).unwrap();
+        assert_eq!(replay.remaining()[0].request, PayloadStruct::with_id(10));
+    }
+}
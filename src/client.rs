@@ -1,443 +1,712 @@
-use crate::{Device, PayloadStruct};
-use failure::format_err;
-use failure::Error;
-
-fn check_id(payload: &PayloadStruct, ref_id: u8) -> Result<(), Error> {
-    if payload.id != ref_id {
-        return Err(format_err!(
-            "id should be {}, but it is {}",
-            ref_id,
-            payload.id
-        ));
+use crate::alarm::{AlarmSet, AlarmWatch};
+use crate::command::CommandId;
+use crate::params::*;
+use crate::request::{self, request_methods, ClientState, Request, Start};
+use crate::safety::SafetyEnvelope;
+use crate::{Device, DobotError, PayloadStruct};
+use std::time::{Duration, Instant};
+
+pub(crate) fn check_id(payload: &PayloadStruct, ref_id: CommandId) -> Result<(), DobotError> {
+    if payload.id != ref_id as u8 {
+        return Err(DobotError::IdMismatch {
+            expected: ref_id as u8,
+            actual: payload.id,
+        });
     }
     Ok(())
 }
 
-pub struct DobotClient<T: Device> {
-    device: T,
+/// Index the device assigned to a queued command.
+///
+/// Returned by the `*_queued` methods, it can be polled or blocked on until
+/// the command has been executed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct QueuedHandle {
+    index: u64,
 }
 
-impl<T> DobotClient<T>
-where
-    T: Device,
-{
-    pub fn new(device: T) -> Self {
-        Self { device }
-    }
-
-    fn write_params(&mut self, id: u8, params: Vec<u8>) -> Result<(), Error> {
-        let p = PayloadStruct::with_id(id).set_write().set_params(params);
-        let ret = self.device.send(p)?;
-        check_id(&ret, id)
-    }
-
-    fn write_queued_params(&mut self, id: u8, params: Vec<u8>) -> Result<u64, Error> {
-        let p = PayloadStruct::with_id(id)
-            .set_write()
-            .set_params(params)
-            .set_queued();
-        let ret = self.device.send(p)?;
-        check_id(&ret, id)?;
-        let mut u = U64Union { val: 0 };
-        unsafe {
-            u.bytes.copy_from_slice(&ret.params);
-            Ok(u.val)
-        }
+impl QueuedHandle {
+    pub fn new(index: u64) -> Self {
+        Self { index }
     }
 
-    fn read_params(&mut self, id: u8) -> Result<Vec<u8>, Error> {
-        let p = PayloadStruct::with_id(id);
-        let ret = self.device.send(p)?;
-        check_id(&ret, id)?;
-        Ok(ret.params)
+    pub fn index(&self) -> u64 {
+        self.index
     }
 
-    pub fn get_device_sn(&mut self) -> Result<String, Error> {
-        Ok(String::from_utf8(self.read_params(0)?)?)
+    /// Returns true if the command has already been executed.
+    pub fn is_done<T: Device>(&self, client: &mut DobotClient<T>) -> Result<bool, DobotError> {
+        Ok(client.get_queued_command_current_index()? >= self.index)
     }
 
-    pub fn get_alarm_state(&mut self) -> Result<Vec<u8>, Error> {
-        self.read_params(20)
+    /// Blocks until the command has been executed, see
+    /// `DobotClient::wait_for_index`.
+    pub fn wait<T: Device>(
+        &self,
+        client: &mut DobotClient<T>,
+        timeout: Duration,
+        poll_interval: Duration,
+    ) -> Result<(), DobotError> {
+        client.wait_for_index(self.index, timeout, poll_interval)
     }
+}
 
-    pub fn clear_all_alarm_state(&mut self) -> Result<(), Error> {
-        self.write_params(20, vec![])
-    }
+pub struct DobotClient<T: Device> {
+    device: T,
+    state: ClientState,
+}
 
-    pub fn get_pose(&mut self) -> Result<Pose, Error> {
-        let p = self.read_params(10)?;
-        let mut pose_union = PoseUnion { bytes: [0; 32] };
-        let pose = unsafe {
-            pose_union.bytes.copy_from_slice(&p);
-            pose_union.poses
-        };
-        Ok(pose)
+impl<T> DobotClient<T>
+where
+    T: Device,
+{
+    pub fn new(device: T) -> Self {
+        Self {
+            device,
+            state: ClientState::default(),
+        }
     }
 
-    pub fn get_jog_joint_params(&mut self) -> Result<JogJointParams, Error> {
-        let p = self.read_params(70)?;
-        let mut params_union = JogJointParamsUnion { bytes: [0; 32] };
-        let params = unsafe {
-            params_union.bytes.copy_from_slice(&p);
-            params_union.jog_joint_params
-        };
-        Ok(params)
+    pub fn device(&self) -> &T {
+        &self.device
     }
 
-    pub fn set_jog_joint_params(&mut self, params: JogJointParams) -> Result<(), Error> {
-        let params_union = JogJointParamsUnion {
-            jog_joint_params: params,
-        };
-        self.write_params(70, unsafe { params_union.bytes.to_vec() })
+    pub fn device_mut(&mut self) -> &mut T {
+        &mut self.device
     }
 
-    pub fn get_jog_common_params(&mut self) -> Result<JogCommonParams, Error> {
-        let p = self.read_params(72)?;
-        let mut params_union = JogCommonParamsUnion { bytes: [0; 8] };
-        let params = unsafe {
-            params_union.bytes.copy_from_slice(&p);
-            params_union.jog_common_params
-        };
-        Ok(params)
+    pub fn into_device(self) -> T {
+        self.device
     }
 
-    pub fn set_jog_common_params(&mut self, params: JogCommonParams) -> Result<(), Error> {
-        let params_union = JogCommonParamsUnion {
-            jog_common_params: params,
-        };
-        self.write_params(72, unsafe { params_union.bytes.to_vec() })
+    /// Same client on top of another device, keeping its state.
+    pub(crate) fn map_device<U: Device>(self, f: impl FnOnce(T) -> U) -> DobotClient<U> {
+        DobotClient {
+            device: f(self.device),
+            state: self.state,
+        }
     }
 
-    pub fn set_jog_command(&mut self, mode: JogCommandType, cmd: JogCommand) -> Result<(), Error> {
-        self.write_params(73, vec![mode as u8, cmd as u8])
+    /// Drops the remembered arm position, after a stop sent past the client.
+    pub(crate) fn forget_position(&mut self) {
+        self.state.last_joints = None;
     }
 
-    pub fn get_ptp_joint_params(&mut self) -> Result<PtpJointParams, Error> {
-        let p = self.read_params(80)?;
-        let mut params_union = PtpJointParamsUnion { bytes: [0; 32] };
-        let params = unsafe {
-            params_union.bytes.copy_from_slice(&p);
-            params_union.ptp_joint_params
-        };
-        Ok(params)
+    /// Checks every PTP, CP, ARC and circle command and jog start against
+    /// `envelope` before it is sent. Rejected commands fail with `DobotError::SafetyViolation`.
+    ///
+    /// A jog is only checked as it starts, see `SafetyEnvelope::check_jog`.
+    pub fn set_safety_envelope(&mut self, envelope: Option<SafetyEnvelope>) {
+        self.state.safety = envelope;
     }
 
-    pub fn set_ptp_joint_params(&mut self, params: PtpJointParams) -> Result<(), Error> {
-        let params_union = PtpJointParamsUnion {
-            ptp_joint_params: params,
-        };
-        self.write_params(80, unsafe { params_union.bytes.to_vec() })
+    pub fn safety_envelope(&self) -> Option<&SafetyEnvelope> {
+        self.state.safety.as_ref()
     }
 
-    pub fn get_ptp_coordinate_params(&mut self) -> Result<PtpCoordinateParams, Error> {
-        let p = self.read_params(81)?;
-        let mut params_union = PtpCoordinateParamsUnion { bytes: [0; 16] };
-        let params = unsafe {
-            params_union.bytes.copy_from_slice(&p);
-            params_union.ptp_coordinate_params
-        };
-        Ok(params)
+    fn call<R: 'static>(&mut self, request: Request<R>) -> Result<R, DobotError> {
+        let (packet, pending) = request.start(&mut self.state);
+        let response = self.device.send(packet)?;
+        pending.finish(&mut self.state, response)
     }
 
-    pub fn set_ptp_coordinate_params(&mut self, params: PtpCoordinateParams) -> Result<(), Error> {
-        let params_union = PtpCoordinateParamsUnion {
-            ptp_coordinate_params: params,
+    /// Reads where a motion starts if the safety envelope needs it.
+    fn motion_start(&mut self) -> Result<Option<Start>, DobotError> {
+        if self.state.safety.is_none() {
+            return Ok(None);
+        }
+        let joints = match self.state.last_joints {
+            Some(joints) => joints,
+            None => self.get_pose()?.joint_angles,
         };
-        self.write_params(81, unsafe { params_union.bytes.to_vec() })
-    }
-
-    pub fn get_ptp_jump_params(&mut self) -> Result<PtpJumpParams, Error> {
-        let p = self.read_params(82)?;
-        let mut params_union = PtpJumpParamsUnion { bytes: [0; 12] };
-        let params = unsafe {
-            params_union.bytes.copy_from_slice(&p);
-            params_union.ptp_jump_params
+        let orientation = match self.state.orientation {
+            Some(orientation) => orientation,
+            None => self.get_arm_orientation()?,
         };
-        Ok(params)
+        Ok(Some(Start {
+            joints,
+            orientation,
+        }))
     }
 
-    pub fn set_ptp_jump_params(&mut self, params: PtpJumpParams) -> Result<(), Error> {
-        let params_union = PtpJumpParamsUnion {
-            ptp_jump_params: params,
+    /// Whether the commands queued through this client have been executed.
+    /// Only tracked with a safety envelope, which is the only user.
+    fn queue_idle(&mut self) -> Result<bool, DobotError> {
+        if self.state.safety.is_none() {
+            return Ok(true);
+        }
+        if let Some(index) = self.state.last_queued {
+            if self.get_queued_command_current_index()? < index {
+                return Ok(false);
+            }
+            self.state.last_queued = None;
+        }
+        Ok(true)
+    }
+
+    request_methods!(blocking);
+
+    /// Reads the pose of the arm. Incremental motions start from it once
+    /// the commands queued through this client have been executed, until
+    /// then they start at the target of the last one.
+    pub fn get_pose(&mut self) -> Result<Pose, DobotError> {
+        // read before the pose, which is then final if the queue is done
+        let idle = self.queue_idle()?;
+        self.call(request::get_pose(idle)?)
+    }
+
+    /// Queues a HOME command, starts queue execution and blocks until the
+    /// arm has finished homing.
+    ///
+    /// Commands queued before are executed first.
+    pub fn home_and_wait(
+        &mut self,
+        timeout: Duration,
+        poll_interval: Duration,
+    ) -> Result<(), DobotError> {
+        let handle = self.set_home_cmd_queued()?;
+        self.set_queued_command_start_exec()?;
+        handle.wait(self, timeout, poll_interval)
+    }
+
+    pub fn set_jog_command(
+        &mut self,
+        mode: JogCommandType,
+        cmd: JogCommand,
+    ) -> Result<(), DobotError> {
+        let start = match cmd {
+            JogCommand::Idel => None,
+            _ => self.motion_start()?,
         };
-        self.write_params(82, unsafe { params_union.bytes.to_vec() })
+        self.call(request::set_jog_command(&self.state, start, mode, cmd)?)
     }
 
-    pub fn get_ptp_common_params(&mut self) -> Result<PtpCommonParams, Error> {
-        let p = self.read_params(83)?;
-        let mut params_union = PtpCommonParamsUnion { bytes: [0; 8] };
-        let params = unsafe {
-            params_union.bytes.copy_from_slice(&p);
-            params_union.ptp_common_params
+    pub fn set_jog_command_queued(
+        &mut self,
+        mode: JogCommandType,
+        cmd: JogCommand,
+    ) -> Result<QueuedHandle, DobotError> {
+        let start = match cmd {
+            JogCommand::Idel => None,
+            _ => self.motion_start()?,
         };
-        Ok(params)
+        self.call(request::set_jog_command_queued(
+            &self.state,
+            start,
+            mode,
+            cmd,
+        )?)
+    }
+
+    /// Blocks until the queued command `index` has been executed.
+    ///
+    /// The alarm state is checked on every poll, so a command that can not
+    /// complete because the arm stopped on an alarm fails with
+    /// `DobotError::Alarm` instead of running into the timeout. Alarms that
+    /// do not stop motion only count when raised during the wait.
+    pub fn wait_for_index(
+        &mut self,
+        index: u64,
+        timeout: Duration,
+        poll_interval: Duration,
+    ) -> Result<(), DobotError> {
+        let start = Instant::now();
+        let mut watch = AlarmWatch::default();
+        loop {
+            watch.check(self.get_alarm_state()?)?;
+            if self.get_queued_command_current_index()? >= index {
+                return Ok(());
+            }
+            if start.elapsed() >= timeout {
+                return Err(DobotError::Timeout);
+            }
+            std::thread::sleep(poll_interval);
+        }
     }
+}
 
-    pub fn set_ptp_common_params(&mut self, params: PtpCommonParams) -> Result<(), Error> {
-        let params_union = PtpCommonParamsUnion {
-            ptp_common_params: params,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alarm::Alarm;
+    use crate::codec::Encode;
+    use crate::MockDevice;
+
+    fn f32_bytes(vals: &[f32]) -> Vec<u8> {
+        vals.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect()
+    }
+
+    fn read(id: u8) -> PayloadStruct {
+        PayloadStruct::with_id(id)
+    }
+
+    fn write(id: u8, params: Vec<u8>) -> PayloadStruct {
+        PayloadStruct::with_id(id).set_write().set_params(params)
+    }
+
+    fn queued(id: u8, params: Vec<u8>) -> PayloadStruct {
+        write(id, params).set_queued()
+    }
+
+    fn answer(id: u8, params: Vec<u8>) -> PayloadStruct {
+        PayloadStruct::with_id(id).set_params(params)
+    }
+
+    #[test]
+    fn get_device_sn() {
+        let mut mock = MockDevice::new();
+        mock.expect(read(0), answer(0, b"M1-0001".to_vec()));
+        let mut dobot = DobotClient::new(mock);
+        assert_eq!(dobot.get_device_sn().unwrap(), "M1-0001");
+        dobot.device().verify().unwrap();
+    }
+
+    #[test]
+    fn alarm_state() {
+        let mut mock = MockDevice::new();
+        mock.expect(read(20), answer(20, vec![1, 0, 0, 0]))
+            .expect(write(20, vec![]), answer(20, vec![]));
+        let mut dobot = DobotClient::new(mock);
+        let alarms = dobot.get_alarm_state().unwrap();
+        assert!(alarms.contains(Alarm::Reset));
+        assert_eq!(alarms.iter().count(), 1);
+        dobot.clear_all_alarm_state().unwrap();
+        dobot.device().verify().unwrap();
+    }
+
+    #[test]
+    fn get_pose() {
+        let mut mock = MockDevice::new();
+        mock.expect(
+            read(10),
+            answer(
+                10,
+                f32_bytes(&[200.0, -10.5, 50.0, 3.0, 10.0, 20.0, 50.0, -27.0]),
+            ),
+        );
+        let mut dobot = DobotClient::new(mock);
+        let pose = dobot.get_pose().unwrap();
+        let (x, y, z, r, joint_angles) = (pose.x, pose.y, pose.z, pose.r, pose.joint_angles);
+        assert_eq!((x, y, z, r), (200.0, -10.5, 50.0, 3.0));
+        assert_eq!(joint_angles, [10.0, 20.0, 50.0, -27.0]);
+        dobot.device().verify().unwrap();
+    }
+
+    #[test]
+    fn jog_params_and_command() {
+        let joint = f32_bytes(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0]);
+        let common = f32_bytes(&[50.0, 25.0]);
+        let mut mock = MockDevice::new();
+        mock.expect(read(70), answer(70, joint.clone()))
+            .expect(write(70, joint.clone()), answer(70, vec![]))
+            .expect(read(72), answer(72, common.clone()))
+            .expect(write(72, common.clone()), answer(72, vec![]))
+            .expect(write(73, vec![1, 6]), answer(73, vec![]));
+        let mut dobot = DobotClient::new(mock);
+        let params = dobot.get_jog_joint_params().unwrap();
+        let (velocity, acceleration) = (params.velocity, params.acceleration);
+        assert_eq!(velocity, [1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(acceleration, [5.0, 6.0, 7.0, 8.0]);
+        dobot.set_jog_joint_params(params).unwrap();
+        let params = dobot.get_jog_common_params().unwrap();
+        let velocity_ratio = params.velocity_ratio;
+        assert_eq!(velocity_ratio, 50.0);
+        dobot.set_jog_common_params(params).unwrap();
+        dobot
+            .set_jog_command(JogCommandType::Joint, JogCommand::CnDown)
+            .unwrap();
+        dobot.device().verify().unwrap();
+    }
+
+    #[test]
+    fn ptp_params() {
+        let joint = f32_bytes(&[10.0, 20.0, 30.0, 40.0, 50.0, 60.0, 70.0, 80.0]);
+        let coordinate = f32_bytes(&[100.0, 50.0, 80.0, 40.0]);
+        let mut jump = f32_bytes(&[20.0, 150.0]);
+        jump.extend(&[0, 0, 0, 0]);
+        let common = f32_bytes(&[30.0, 10.0]);
+        let mut mock = MockDevice::new();
+        mock.expect(read(80), answer(80, joint.clone()))
+            .expect(write(80, joint.clone()), answer(80, vec![]))
+            .expect(read(81), answer(81, coordinate.clone()))
+            .expect(write(81, coordinate.clone()), answer(81, vec![]))
+            .expect(read(82), answer(82, jump.clone()))
+            .expect(write(82, jump.clone()), answer(82, vec![]))
+            .expect(read(83), answer(83, common.clone()))
+            .expect(write(83, common.clone()), answer(83, vec![]));
+        let mut dobot = DobotClient::new(mock);
+        let params = dobot.get_ptp_joint_params().unwrap();
+        dobot.set_ptp_joint_params(params).unwrap();
+        let params = dobot.get_ptp_coordinate_params().unwrap();
+        assert_eq!(params.xyz_velocity, 100.0);
+        assert_eq!(params.r_acceleration, 40.0);
+        dobot.set_ptp_coordinate_params(params).unwrap();
+        let params = dobot.get_ptp_jump_params().unwrap();
+        let (jump_height, z_limit) = (params.jump_height, params.z_limit);
+        assert_eq!((jump_height, z_limit), (20.0, 150.0));
+        dobot.set_ptp_jump_params(params).unwrap();
+        let params = dobot.get_ptp_common_params().unwrap();
+        dobot.set_ptp_common_params(params).unwrap();
+        dobot.device().verify().unwrap();
+    }
+
+    #[test]
+    fn ptp_command() {
+        let mut params = vec![PtpMode::MovlXyz as u8];
+        params.extend(f32_bytes(&[250.0, 0.0, 30.0, 0.0]));
+        let mut mock = MockDevice::new();
+        mock.expect(write(84, params.clone()), answer(84, vec![]))
+            .expect(
+                queued(84, params.clone()),
+                answer(84, vec![42, 0, 0, 0, 0, 0, 0, 0]),
+            )
+            .expect(read(10), answer(10, vec![0; 32]));
+        let mut dobot = DobotClient::new(mock);
+        let cmd = PtpCommand {
+            ptp_mode: PtpMode::MovlXyz,
+            x: 250.0,
+            y: 0.0,
+            z: 30.0,
+            r: 0.0,
         };
-        self.write_params(83, unsafe { params_union.bytes.to_vec() })
-    }
-
-    pub fn set_ptp_command(&mut self, command: PtpCommand) -> Result<(), Error> {
-        let command_union = PtpCommandUnion {
-            ptp_command: command,
+        dobot.set_ptp_command(cmd).unwrap();
+        assert_eq!(dobot.set_ptp_command_queued(cmd).unwrap().index(), 42);
+        // without a safety envelope the queue index is not read
+        dobot.get_pose().unwrap();
+        dobot.device().verify().unwrap();
+    }
+
+    #[test]
+    fn cp() {
+        let params = f32_bytes(&[100.0, 50.0, 80.0]);
+        let mut params_bytes = params.clone();
+        params_bytes.push(0);
+        let mut command = vec![0];
+        command.extend(f32_bytes(&[10.0, 0.0, -5.0, 20.0]));
+        let mut laser = vec![1];
+        laser.extend(f32_bytes(&[250.0, 0.0, 30.0, 60.0]));
+        let mut mock = MockDevice::new();
+        mock.expect(read(90), answer(90, params_bytes.clone()))
+            .expect(write(90, params_bytes), answer(90, vec![]))
+            .expect(write(91, command.clone()), answer(91, vec![]))
+            .expect(
+                queued(91, command),
+                answer(91, vec![3, 0, 0, 0, 0, 0, 0, 0]),
+            )
+            .expect(queued(92, laser), answer(92, vec![4, 0, 0, 0, 0, 0, 0, 0]));
+        let mut dobot = DobotClient::new(mock);
+        let cp_params = dobot.get_cp_params().unwrap();
+        assert_eq!(cp_params.junction_velocity, 50.0);
+        assert!(!cp_params.real_time_track);
+        dobot.set_cp_params(cp_params).unwrap();
+        let cmd = CpCommand {
+            cp_mode: CpMode::Relative,
+            x: 10.0,
+            y: 0.0,
+            z: -5.0,
+            velocity: 20.0,
         };
-        self.write_params(84, unsafe { command_union.bytes.to_vec() })
-    }
-
-    pub fn set_ptp_command_queued(&mut self, command: PtpCommand) -> Result<u64, Error> {
-        let command_union = PtpCommandUnion {
-            ptp_command: command,
+        dobot.set_cp_command(cmd).unwrap();
+        assert_eq!(dobot.set_cp_command_queued(cmd).unwrap().index(), 3);
+        let handle = dobot
+            .set_cp_laser_command_queued(CpLaserCommand {
+                cp_mode: CpMode::Absolute,
+                x: 250.0,
+                y: 0.0,
+                z: 30.0,
+                power: 60.0,
+            })
+            .unwrap();
+        assert_eq!(handle.index(), 4);
+        dobot.device().verify().unwrap();
+    }
+
+    #[test]
+    fn arc_and_circle() {
+        let params = f32_bytes(&[100.0, 50.0, 200.0, 100.0]);
+        let points = f32_bytes(&[250.0, 50.0, 50.0, 0.0, 300.0, 0.0, 50.0, 0.0]);
+        let mut circle = points.clone();
+        circle.extend(&[3, 0, 0, 0]);
+        let mut mock = MockDevice::new();
+        mock.expect(read(100), answer(100, params.clone()))
+            .expect(write(100, params), answer(100, vec![]))
+            .expect(
+                queued(101, points.clone()),
+                answer(101, vec![5, 0, 0, 0, 0, 0, 0, 0]),
+            )
+            .expect(write(102, circle), answer(102, vec![]));
+        let mut dobot = DobotClient::new(mock);
+        let arc_params = dobot.get_arc_params().unwrap();
+        assert_eq!(arc_params.r_velocity, 50.0);
+        dobot.set_arc_params(arc_params).unwrap();
+        let via = ArcPoint {
+            x: 250.0,
+            y: 50.0,
+            z: 50.0,
+            r: 0.0,
         };
-        self.write_queued_params(84, unsafe { command_union.bytes.to_vec() })
-    }
-
-    // address = (1 ~ 22), air pump is connected to 18.
-    pub fn set_iodo(&mut self, address: u8, level: IoLevel) -> Result<(), Error> {
-        self.write_params(131, vec![address, level as u8])
-    }
-
-    pub fn set_iodo_queued(&mut self, address: u8, level: IoLevel) -> Result<u64, Error> {
-        self.write_queued_params(131, vec![address, level as u8])
-    }
-
-    // wait
-    pub fn set_wait_command(&mut self, wait_ms: u32) -> Result<(), Error> {
-        let u = U32Union { val: wait_ms };
-        self.write_params(110, unsafe { u.bytes.to_vec() })
-    }
-
-    pub fn set_wait_command_queued(&mut self, wait_ms: u32) -> Result<u64, Error> {
-        let u = U32Union { val: wait_ms };
-        self.write_queued_params(110, unsafe { u.bytes.to_vec() })
-    }
-
-    pub fn set_arm_orientation(&mut self, l_r: ArmOrientation) -> Result<(), Error> {
-        self.write_params(50, vec![l_r as u8])
-    }
-
-    pub fn set_arm_orientation_queued(&mut self, l_r: ArmOrientation) -> Result<u64, Error> {
-        self.write_queued_params(50, vec![l_r as u8])
-    }
-
-    pub fn set_queued_command_start_exec(&mut self) -> Result<(), Error> {
-        self.write_params(240, vec![])
-    }
-
-    pub fn set_queued_command_stop_exec(&mut self) -> Result<(), Error> {
-        self.write_params(241, vec![])
-    }
-
-    pub fn set_queued_command_force_stop_exec(&mut self) -> Result<(), Error> {
-        self.write_params(242, vec![])
-    }
-
-    pub fn set_queued_command_clear(&mut self) -> Result<(), Error> {
-        self.write_params(245, vec![])
-    }
-
-    pub fn get_queued_command_current_index(&mut self) -> Result<u64, Error> {
-        let params = self.read_params(246)?;
-        let mut u = U64Union { val: 0 };
-        unsafe {
-            u.bytes.copy_from_slice(&params);
-            Ok(u.val)
+        let to = ArcPoint {
+            x: 300.0,
+            y: 0.0,
+            z: 50.0,
+            r: 0.0,
+        };
+        let handle = dobot
+            .set_arc_command_queued(ArcCommand { via, to })
+            .unwrap();
+        assert_eq!(handle.index(), 5);
+        dobot
+            .set_circle_command(CircleCommand { via, to, count: 3 })
+            .unwrap();
+        dobot.device().verify().unwrap();
+    }
+
+    #[test]
+    fn io_subsystem() {
+        let mut pwm = vec![4];
+        pwm.extend(f32_bytes(&[1000.0, 50.0]));
+        let mut mock = MockDevice::new();
+        mock.expect(write(130, vec![4, 2]), answer(130, vec![]))
+            .expect(read(130).set_params(vec![4]), answer(130, vec![4, 2]))
+            .expect(write(132, pwm), answer(132, vec![]))
+            .expect(read(133).set_params(vec![3]), answer(133, vec![3, 1]))
+            .expect(
+                read(134).set_params(vec![2]),
+                answer(134, vec![2, 0xff, 0x0f]),
+            )
+            .expect(read(131).set_params(vec![5]), answer(131, vec![6, 1]));
+        let mut dobot = DobotClient::new(mock);
+        let pin = |address| IoPin::new(address).unwrap();
+        dobot.set_io_multiplexing(pin(4), IoFunction::Pwm).unwrap();
+        assert_eq!(dobot.get_io_multiplexing(pin(4)).unwrap(), IoFunction::Pwm);
+        dobot.set_io_pwm(pin(4), 1000.0, 50.0).unwrap();
+        assert_eq!(dobot.get_iodi(pin(3)).unwrap(), IoLevel::High);
+        assert_eq!(dobot.get_io_adc(pin(2)).unwrap(), 4095);
+        assert!(dobot.get_iodo(pin(5)).is_err());
+        dobot.device().verify().unwrap();
+    }
+
+    #[test]
+    fn io_wait_and_orientation() {
+        let mut mock = MockDevice::new();
+        mock.expect(write(131, vec![18, 1]), answer(131, vec![]))
+            .expect(
+                queued(131, vec![17, 0]),
+                answer(131, vec![1, 0, 0, 0, 0, 0, 0, 0]),
+            )
+            .expect(write(110, vec![0xe8, 0x03, 0, 0]), answer(110, vec![]))
+            .expect(
+                queued(110, vec![0xe8, 0x03, 0, 0]),
+                answer(110, vec![2, 0, 0, 0, 0, 0, 0, 0]),
+            )
+            .expect(write(50, vec![1]), answer(50, vec![]))
+            .expect(
+                queued(50, vec![0]),
+                answer(50, vec![3, 0, 0, 0, 0, 0, 0, 0]),
+            )
+            .expect(read(50), answer(50, vec![1]));
+        let mut dobot = DobotClient::new(mock);
+        let (pin17, pin18) = (IoPin::new(17).unwrap(), IoPin::new(18).unwrap());
+        dobot.set_iodo(pin18, IoLevel::High).unwrap();
+        assert_eq!(
+            dobot.set_iodo_queued(pin17, IoLevel::Low).unwrap().index(),
+            1
+        );
+        dobot.set_wait_command(1000).unwrap();
+        assert_eq!(dobot.set_wait_command_queued(1000).unwrap().index(), 2);
+        dobot.set_arm_orientation(ArmOrientation::Righty).unwrap();
+        assert_eq!(
+            dobot
+                .set_arm_orientation_queued(ArmOrientation::Lefty)
+                .unwrap(),
+            QueuedHandle::new(3)
+        );
+        match dobot.get_arm_orientation().unwrap() {
+            ArmOrientation::Righty => {}
+            o => panic!("unexpected orientation {:?}", o),
         }
+        dobot.device().verify().unwrap();
     }
 
-    pub fn get_queued_command_left_space(&mut self) -> Result<u32, Error> {
-        let params = self.read_params(247)?;
-        let mut u = U32Union { val: 0 };
-        unsafe {
-            u.bytes.copy_from_slice(&params);
-            Ok(u.val)
+    #[test]
+    fn queue_control() {
+        let mut mock = MockDevice::new();
+        for id in &[240, 241, 242, 245] {
+            mock.expect(write(*id, vec![]), answer(*id, vec![]));
+        }
+        mock.expect(read(246), answer(246, vec![0, 1, 0, 0, 0, 0, 0, 0]))
+            .expect(read(247), answer(247, vec![32, 0, 0, 0]));
+        let mut dobot = DobotClient::new(mock);
+        dobot.set_queued_command_start_exec().unwrap();
+        dobot.set_queued_command_stop_exec().unwrap();
+        dobot.set_queued_command_force_stop_exec().unwrap();
+        dobot.set_queued_command_clear().unwrap();
+        assert_eq!(dobot.get_queued_command_current_index().unwrap(), 256);
+        assert_eq!(dobot.get_queued_command_left_space().unwrap(), 32);
+        dobot.device().verify().unwrap();
+    }
+
+    #[test]
+    fn wrong_response_id() {
+        let mut mock = MockDevice::new();
+        mock.expect(read(10), answer(11, vec![]));
+        let mut dobot = DobotClient::new(mock);
+        assert!(matches!(
+            dobot.get_pose(),
+            Err(DobotError::IdMismatch {
+                expected: 10,
+                actual: 11
+            })
+        ));
+        dobot.device().verify().unwrap();
+    }
+
+    #[test]
+    fn malformed_response_is_an_error() {
+        let mut mock = MockDevice::new();
+        mock.expect(read(10), answer(10, vec![0; 31]))
+            .expect(queued(110, vec![0, 0, 0, 0]), answer(110, vec![1, 0]));
+        let mut dobot = DobotClient::new(mock);
+        assert!(matches!(
+            dobot.get_pose(),
+            Err(DobotError::PayloadLength {
+                expected: 32,
+                actual: 31
+            })
+        ));
+        assert!(matches!(
+            dobot.set_wait_command_queued(0),
+            Err(DobotError::PayloadLength { .. })
+        ));
+        dobot.device().verify().unwrap();
+    }
+
+    #[test]
+    fn wait_for_index() {
+        let no_alarm = || answer(20, vec![0; 16]);
+        let index = |i: u64| answer(246, i.to_le_bytes().to_vec());
+        let mut mock = MockDevice::new();
+        mock.expect(read(20), no_alarm())
+            .expect(read(246), index(4))
+            .expect(read(20), no_alarm())
+            .expect(read(246), index(5))
+            .expect(read(246), index(5));
+        let mut dobot = DobotClient::new(mock);
+        let handle = QueuedHandle::new(5);
+        handle
+            .wait(&mut dobot, Duration::from_secs(1), Duration::from_millis(0))
+            .unwrap();
+        assert!(handle.is_done(&mut dobot).unwrap());
+        dobot.device().verify().unwrap();
+    }
+
+    #[test]
+    fn wait_for_index_fails_on_alarm() {
+        let mut alarms = vec![0; 16];
+        alarms[2] = 0b100;
+        let mut mock = MockDevice::new();
+        mock.expect(read(20), answer(20, alarms.clone()));
+        let mut dobot = DobotClient::new(mock);
+        match dobot.wait_for_index(1, Duration::from_secs(1), Duration::from_millis(0)) {
+            Err(DobotError::Alarm(state)) => {
+                assert_eq!(state.iter().collect::<Vec<_>>(), vec![Alarm::PlanInvLimit])
+            }
+            r => panic!("unexpected result {:?}", r),
         }
+        dobot.device().verify().unwrap();
+    }
+
+    #[test]
+    fn wait_for_index_ignores_stale_reset() {
+        let mut reset = vec![0; 16];
+        reset[0] = 0b1;
+        let mut mock = MockDevice::new();
+        mock.expect(read(20), answer(20, reset.clone()))
+            .expect(read(246), answer(246, 0u64.to_le_bytes().to_vec()))
+            .expect(read(20), answer(20, reset))
+            .expect(read(246), answer(246, 1u64.to_le_bytes().to_vec()));
+        let mut dobot = DobotClient::new(mock);
+        dobot
+            .wait_for_index(1, Duration::from_secs(1), Duration::from_millis(0))
+            .unwrap();
+        dobot.device().verify().unwrap();
+    }
+
+    #[test]
+    fn wait_for_index_timeout() {
+        let mut mock = MockDevice::new();
+        mock.respond_with(|req| {
+            Ok(match req.id {
+                246 => answer(246, vec![0; 8]),
+                id => answer(id, vec![0; 16]),
+            })
+        });
+        let mut dobot = DobotClient::new(mock);
+        assert!(matches!(
+            dobot.wait_for_index(1, Duration::from_millis(20), Duration::from_millis(5)),
+            Err(DobotError::Timeout)
+        ));
     }
 
-    pub fn get_arm_orientation(&mut self) -> Result<ArmOrientation, Error> {
-        let lr = self.read_params(50)?[0];
-        Ok(if lr == 0 {
-            ArmOrientation::Lefty
-        } else {
-            ArmOrientation::Righty
-        })
+    #[test]
+    fn home() {
+        let home = f32_bytes(&[200.0, 0.0, 100.0, 0.0]);
+        let mut mock = MockDevice::new();
+        mock.expect(read(30), answer(30, home.clone()))
+            .expect(write(30, home.clone()), answer(30, vec![]))
+            .expect(write(31, vec![0; 4]), answer(31, vec![]))
+            .expect(
+                queued(31, vec![0; 4]),
+                answer(31, vec![9, 0, 0, 0, 0, 0, 0, 0]),
+            )
+            .expect(write(240, vec![]), answer(240, vec![]))
+            .expect(read(20), answer(20, vec![0; 16]))
+            .expect(read(246), answer(246, vec![9, 0, 0, 0, 0, 0, 0, 0]));
+        let mut dobot = DobotClient::new(mock);
+        let params = dobot.get_home_params().unwrap();
+        assert_eq!(params.x, 200.0);
+        dobot.set_home_params(params).unwrap();
+        dobot.set_home_cmd().unwrap();
+        dobot
+            .home_and_wait(Duration::from_secs(1), Duration::from_millis(0))
+            .unwrap();
+        dobot.device().verify().unwrap();
+    }
+
+    #[test]
+    fn safety_envelope() {
+        let pose = f32_bytes(&[200.0, 200.0, 100.0, 90.0, 0.0, 90.0, 100.0, 0.0]);
+        let down = PtpCommand {
+            ptp_mode: PtpMode::MovjInc,
+            x: 0.0,
+            y: 0.0,
+            z: -40.0,
+            r: 0.0,
+        };
+        let mut mock = MockDevice::new();
+        mock.expect(read(10), answer(10, pose.clone()))
+            .expect(read(50), answer(50, vec![0]))
+            .expect(
+                queued(84, down.to_bytes()),
+                answer(84, vec![1, 0, 0, 0, 0, 0, 0, 0]),
+            )
+            .expect(read(246), answer(246, vec![0; 8]))
+            .expect(read(10), answer(10, pose));
+        let mut dobot = DobotClient::new(mock);
+        dobot.set_safety_envelope(Some(SafetyEnvelope::default().z_floor(59.5)));
+        // 100 -> 60 passes, the second step to 20 and jogging down are below
+        // the floor
+        assert_eq!(dobot.set_ptp_command_queued(down).unwrap().index(), 1);
+        // the arm has not moved yet, the next step still starts at 60
+        assert_eq!(dobot.get_pose().unwrap().z, 100.0);
+        assert!(matches!(
+            dobot.set_ptp_command_queued(down),
+            Err(DobotError::SafetyViolation(_))
+        ));
+        assert!(matches!(
+            dobot.set_jog_command(JogCommandType::Cartesian, JogCommand::CnDown),
+            Err(DobotError::SafetyViolation(_))
+        ));
+        dobot.device().verify().unwrap();
     }
 }
-
-#[repr(u8)]
-#[derive(Clone, Copy, Debug)]
-pub enum IoLevel {
-    Low,
-    High,
-}
-
-#[repr(u8)]
-#[derive(Clone, Copy, Debug)]
-pub enum ArmOrientation {
-    Lefty,
-    Righty,
-}
-
-#[repr(u8)]
-#[derive(Clone, Copy, Debug)]
-pub enum JogCommandType {
-    Cartesian,
-    Joint,
-}
-
-#[repr(u8)]
-pub enum JogCommand {
-    Idel,
-    ApDown,
-    AnDown,
-    BpDown,
-    BnDown,
-    CpDown,
-    CnDown,
-    DpDown,
-    DnDown,
-}
-
-#[repr(packed)]
-#[derive(Clone, Copy, Debug)]
-pub struct Pose {
-    pub x: f32,
-    pub y: f32,
-    pub z: f32,
-    pub r: f32,
-    pub joint_angles: [f32; 4],
-}
-
-//#[repr(C)]
-union PoseUnion {
-    poses: Pose,
-    bytes: [u8; 32],
-}
-
-//#[repr(C)]
-union U64Union {
-    val: u64,
-    pub bytes: [u8; 8],
-}
-
-//#[repr(C)]
-union U32Union {
-    val: u32,
-    pub bytes: [u8; 4],
-}
-
-/// JOG
-#[repr(packed)]
-#[derive(Clone, Copy, Debug)]
-pub struct JogJointParams {
-    pub velocity: [f32; 4],
-    pub acceleration: [f32; 4],
-}
-
-//#[repr(C)]
-union JogJointParamsUnion {
-    jog_joint_params: JogJointParams,
-    bytes: [u8; 32],
-}
-
-#[repr(packed)]
-#[derive(Clone, Copy, Debug)]
-pub struct JogCommonParams {
-    pub velocity_ratio: f32,
-    pub acceleration_ratio: f32,
-}
-
-//#[repr(C)]
-union JogCommonParamsUnion {
-    jog_common_params: JogCommonParams,
-    bytes: [u8; 8],
-}
-
-/// PTP
-#[repr(packed)]
-#[derive(Clone, Copy, Debug)]
-pub struct PtpJointParams {
-    pub velocity: [f32; 4],
-    pub acceleration: [f32; 4],
-}
-
-#[repr(packed)]
-union PtpJointParamsUnion {
-    ptp_joint_params: PtpJointParams,
-    bytes: [u8; 32],
-}
-
-//#[repr(C)]
-#[derive(Clone, Copy, Debug)]
-pub struct PtpCoordinateParams {
-    pub xyz_velocity: f32,
-    pub r_velocity: f32,
-    pub xyz_acceleration: f32,
-    pub r_acceleration: f32,
-}
-
-//#[repr(C)]
-union PtpCoordinateParamsUnion {
-    ptp_coordinate_params: PtpCoordinateParams,
-    bytes: [u8; 16],
-}
-
-#[repr(packed)]
-#[derive(Clone, Copy, Debug)]
-pub struct PtpJumpParams {
-    pub jump_height: f32,
-    pub z_limit: f32,
-    pub dummy: u32,
-}
-
-#[repr(packed)]
-union PtpJumpParamsUnion {
-    ptp_jump_params: PtpJumpParams,
-    bytes: [u8; 12],
-}
-
-#[repr(packed)]
-#[derive(Clone, Copy, Debug)]
-pub struct PtpCommonParams {
-    pub velocity_ratio: f32,
-    pub acceleration_ratio: f32,
-}
-
-//#[repr(C)]
-union PtpCommonParamsUnion {
-    ptp_common_params: PtpCommonParams,
-    bytes: [u8; 8],
-}
-
-//#[repr(C)]
-union PtpCommandUnion {
-    ptp_command: PtpCommand,
-    bytes: [u8; 17],
-}
-
-#[repr(packed)]
-#[derive(Clone, Copy, Debug)]
-pub struct PtpCommand {
-    pub ptp_mode: PtpMode,
-    pub x: f32,
-    pub y: f32,
-    pub z: f32,
-    pub r: f32,
-}
-
-#[repr(u8)]
-#[derive(Clone, Copy, Debug)]
-pub enum PtpMode {
-    JumpXyz,     // JUMP mode, (x,y,z,r) is the target point in Cartesian coordinate system
-    MovjXyz,     // MOVJ mode, (x,y,z,r) is the target point in Cartesian coordinate system
-    MovlXyz,     // MOVL mode, (x,y,z,r) is the target point in Cartesian coordinate system
-    JumpAngle,   // JUMP mode, (x,y,z,r) is the target point in Joint coordinate system
-    MovjAngle,   // MOVJ mode, (x,y,z,r) is the target point in Joint coordinate system
-    MovlAngle,   // MOVL mode, (x,y,z,r) is the target point in Joint coordinate system
-    MovjInc,     // MOVJ mode, (x,y,z,r) is the angle increment in Joint coordinate system
-    MovlInc, // MOVL mode, (x,y,z,r) is the Cartesian coordinate increment in Joint coordinate system
-    MovjXyzInc, // MOVJ mode, (x,y,z,r) is the Cartesian coordinate increment in Cartesian coordinate system
-    JumpMovlXyz, // JUMP mode, (x,y,z,r) is the Cartesian coordinate increment in Cartesian coordinate
-}
@@ -0,0 +1,173 @@
+use crate::error::DobotError;
+
+/// Types that can be written as command parameters.
+///
+/// Every field is encoded explicitly in little endian, the byte order of the
+/// Dobot protocol, independent of the host.
+pub trait Encode {
+    fn encode(&self, buf: &mut Vec<u8>);
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        self.encode(&mut buf);
+        buf
+    }
+}
+
+/// Types that can be read from response parameters.
+pub trait Decode: Sized {
+    /// Size of the encoded value in bytes.
+    const SIZE: usize;
+
+    /// Decodes from a reader holding at least `SIZE` bytes.
+    fn read(reader: &mut Reader) -> Result<Self, DobotError>;
+
+    /// Decodes `bytes`, which must be exactly `SIZE` bytes long.
+    fn decode(bytes: &[u8]) -> Result<Self, DobotError> {
+        let mut reader = Reader::new(bytes, Self::SIZE)?;
+        Self::read(&mut reader)
+    }
+}
+
+/// Little endian cursor over a parameter buffer whose length was validated
+/// up front.
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    /// Fails unless `bytes` is exactly `expected` bytes long.
+    pub fn new(bytes: &'a [u8], expected: usize) -> Result<Self, DobotError> {
+        if bytes.len() != expected {
+            return Err(DobotError::PayloadLength {
+                expected,
+                actual: bytes.len(),
+            });
+        }
+        Ok(Self { bytes })
+    }
+
+    fn take<const N: usize>(&mut self) -> [u8; N] {
+        let mut b = [0; N];
+        b.copy_from_slice(&self.bytes[..N]);
+        self.bytes = &self.bytes[N..];
+        b
+    }
+
+    pub fn u8(&mut self) -> u8 {
+        self.take::<1>()[0]
+    }
+
+    pub fn u16(&mut self) -> u16 {
+        u16::from_le_bytes(self.take())
+    }
+
+    pub fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.take())
+    }
+
+    pub fn u64(&mut self) -> u64 {
+        u64::from_le_bytes(self.take())
+    }
+
+    pub fn f32(&mut self) -> f32 {
+        f32::from_le_bytes(self.take())
+    }
+
+    pub fn f32_array(&mut self) -> [f32; 4] {
+        [self.f32(), self.f32(), self.f32(), self.f32()]
+    }
+}
+
+macro_rules! impl_codec_for_primitive {
+    ($($t:ident),*) => {
+        $(
+            impl Encode for $t {
+                fn encode(&self, buf: &mut Vec<u8>) {
+                    buf.extend_from_slice(&self.to_le_bytes());
+                }
+            }
+
+            impl Decode for $t {
+                const SIZE: usize = std::mem::size_of::<$t>();
+
+                fn read(reader: &mut Reader) -> Result<Self, DobotError> {
+                    Ok(reader.$t())
+                }
+            }
+        )*
+    };
+}
+
+impl_codec_for_primitive!(u8, u16, u32, u64, f32);
+
+impl Encode for [f32; 4] {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        for v in self {
+            v.encode(buf);
+        }
+    }
+}
+
+impl Decode for [f32; 4] {
+    const SIZE: usize = 16;
+
+    fn read(reader: &mut Reader) -> Result<Self, DobotError> {
+        Ok(reader.f32_array())
+    }
+}
+
+macro_rules! impl_codec_for_tuple {
+    ($($t:ident),*) => {
+        #[allow(non_snake_case)]
+        impl<$($t: Encode),*> Encode for ($($t,)*) {
+            fn encode(&self, buf: &mut Vec<u8>) {
+                let ($($t,)*) = self;
+                $($t.encode(buf);)*
+            }
+        }
+
+        impl<$($t: Decode),*> Decode for ($($t,)*) {
+            const SIZE: usize = 0 $(+ $t::SIZE)*;
+
+            fn read(reader: &mut Reader) -> Result<Self, DobotError> {
+                Ok(($($t::read(reader)?,)*))
+            }
+        }
+    };
+}
+
+// parameters that are a plain sequence of fields, e.g. (address, level)
+impl_codec_for_tuple!(A, B);
+impl_codec_for_tuple!(A, B, C);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn primitives_are_little_endian() {
+        assert_eq!(0x0102_0304u32.to_bytes(), vec![4, 3, 2, 1]);
+        assert_eq!(1.0f32.to_bytes(), vec![0, 0, 0x80, 0x3f]);
+        assert_eq!(u64::decode(&[1, 1, 0, 0, 0, 0, 0, 0]).unwrap(), 257);
+        assert_eq!(
+            <[f32; 4]>::decode(&[1.0f32, 2.0, 3.0, 4.0].to_bytes()).unwrap(),
+            [1.0, 2.0, 3.0, 4.0]
+        );
+        assert_eq!((7u8, 2u16).to_bytes(), vec![7, 2, 0]);
+        assert_eq!(<(u8, f32, f32)>::SIZE, 9);
+        assert_eq!(<(u8, u16)>::decode(&[7, 2, 0]).unwrap(), (7, 2));
+    }
+
+    #[test]
+    fn wrong_length_is_an_error() {
+        assert!(matches!(
+            u32::decode(&[1, 2, 3]),
+            Err(DobotError::PayloadLength {
+                expected: 4,
+                actual: 3
+            })
+        ));
+        assert!(u64::decode(&[0; 9]).is_err());
+    }
+}
@@ -0,0 +1,479 @@
+use crate::alarm::AlarmSet;
+use crate::codec::Decode;
+use crate::error::DobotError;
+use crate::params::{
+    self, ArcCommand, CircleCommand, CpCommand, CpLaserCommand, EndEffectorState, IoFunction,
+    IoLevel, IoPin, JogCommand, JogCommandType, PtpCommand,
+};
+use crate::protocol::{PayloadStruct, ReadWrite};
+use std::convert::TryFrom;
+use std::fmt;
+
+/// Layout of the parameters of a command.
+#[derive(Clone, Copy)]
+pub struct ParamsType {
+    type_name: &'static str,
+    size: Option<usize>,
+    describe: fn(&[u8]) -> Option<String>,
+}
+
+impl ParamsType {
+    fn of<T: Decode + fmt::Debug>() -> Self {
+        Self {
+            type_name: std::any::type_name::<T>(),
+            size: Some(T::SIZE),
+            describe: |params| T::decode(params).ok().map(|v| format!("{:?}", v)),
+        }
+    }
+
+    /// Full path of the Rust type, e.g. `dobot_client::params::PtpCommand`.
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    /// Size in bytes, `None` if it varies.
+    pub fn size(&self) -> Option<usize> {
+        self.size
+    }
+
+    /// Decodes `params` into a human readable form, `None` if they are not
+    /// of this type.
+    pub fn describe(&self, params: &[u8]) -> Option<String> {
+        (self.describe)(params)
+    }
+}
+
+/// Shows the type without module paths, e.g. `(IoPin, IoLevel)`.
+impl fmt::Display for ParamsType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut short = String::new();
+        for c in self.type_name.chars() {
+            short.push(c);
+            if short.ends_with("::") {
+                short.truncate(short.len() - 2);
+                while short.ends_with(|c: char| c.is_alphanumeric() || c == '_') {
+                    short.pop();
+                }
+            }
+        }
+        write!(f, "{}", short)
+    }
+}
+
+impl fmt::Debug for ParamsType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+/// What a command supports and what its parameters look like.
+#[derive(Clone, Debug)]
+pub struct CommandInfo {
+    pub id: CommandId,
+    /// Name in the Dobot protocol without the Get/Set prefix, e.g. `PTPCmd`.
+    pub name: &'static str,
+    pub read: bool,
+    pub write: bool,
+    /// The write can be put into the command queue.
+    pub queued: bool,
+    /// Parameters of a read request, `None` if it has none.
+    pub read_request: Option<ParamsType>,
+    /// Parameters of the response to a read.
+    pub read_response: Option<ParamsType>,
+    /// Parameters of a write request, `None` if it has none.
+    pub write_params: Option<ParamsType>,
+}
+
+macro_rules! commands {
+    ($($variant:ident = $id:expr,)*) => {
+        /// Every command of the Dobot protocol this crate supports.
+        ///
+        /// `info` is the single table of what each command supports, the
+        /// client, simulator, dissector and CLI are all built on it.
+        #[repr(u8)]
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        pub enum CommandId {
+            $($variant = $id,)*
+        }
+
+        impl CommandId {
+            pub const ALL: &'static [CommandId] = &[$(CommandId::$variant,)*];
+        }
+
+        impl TryFrom<u8> for CommandId {
+            type Error = DobotError;
+
+            fn try_from(id: u8) -> Result<Self, DobotError> {
+                match id {
+                    $($id => Ok(CommandId::$variant),)*
+                    _ => Err(DobotError::Unsupported(format!("unknown command id {}", id))),
+                }
+            }
+        }
+    };
+}
+
+commands! {
+    DeviceSn = 0,
+    Pose = 10,
+    AlarmsState = 20,
+    HomeParams = 30,
+    HomeCmd = 31,
+    ArmOrientation = 50,
+    EndEffectorParams = 60,
+    EndEffectorLaser = 61,
+    EndEffectorSuctionCup = 62,
+    EndEffectorGripper = 63,
+    JogJointParams = 70,
+    JogCoordinateParams = 71,
+    JogCommonParams = 72,
+    JogCmd = 73,
+    PtpJointParams = 80,
+    PtpCoordinateParams = 81,
+    PtpJumpParams = 82,
+    PtpCommonParams = 83,
+    PtpCmd = 84,
+    CpParams = 90,
+    CpCmd = 91,
+    CpLeCmd = 92,
+    ArcParams = 100,
+    ArcCmd = 101,
+    CircleCmd = 102,
+    WaitCmd = 110,
+    IoMultiplexing = 130,
+    IoDo = 131,
+    IoPwm = 132,
+    IoDi = 133,
+    IoAdc = 134,
+    QueuedCmdStartExec = 240,
+    QueuedCmdStopExec = 241,
+    QueuedCmdForceStopExec = 242,
+    QueuedCmdStartDownload = 243,
+    QueuedCmdStopDownload = 244,
+    QueuedCmdClear = 245,
+    QueuedCmdCurrentIndex = 246,
+    QueuedCmdLeftSpace = 247,
+}
+
+fn variable(type_name: &'static str, describe: fn(&[u8]) -> Option<String>) -> ParamsType {
+    ParamsType {
+        type_name,
+        size: None,
+        describe,
+    }
+}
+
+impl CommandId {
+    pub fn info(self) -> CommandInfo {
+        use CommandId::*;
+
+        let info = |name: &'static str,
+                    read_response: Option<ParamsType>,
+                    write_params: Write,
+                    queued: bool| CommandInfo {
+            id: self,
+            name,
+            read: read_response.is_some(),
+            write: !matches!(write_params, Write::No),
+            queued,
+            read_request: None,
+            read_response,
+            write_params: match write_params {
+                Write::With(params) => Some(params),
+                _ => None,
+            },
+        };
+        // parameters read and written
+        fn p<T: Decode + fmt::Debug>() -> Option<ParamsType> {
+            Some(ParamsType::of::<T>())
+        }
+        fn w<T: Decode + fmt::Debug>() -> Write {
+            Write::With(ParamsType::of::<T>())
+        }
+        let io = |name: &'static str, response: ParamsType, write_params: Write| CommandInfo {
+            read_request: p::<IoPin>(),
+            ..info(
+                name,
+                Some(response),
+                write_params,
+                !matches!(write_params, Write::No),
+            )
+        };
+        match self {
+            DeviceSn => info(
+                "DeviceSN",
+                Some(variable("String", |params| {
+                    Some(format!("{:?}", String::from_utf8_lossy(params)))
+                })),
+                Write::No,
+                false,
+            ),
+            Pose => info("Pose", p::<params::Pose>(), Write::No, false),
+            AlarmsState => info(
+                "AlarmsState",
+                Some(variable(std::any::type_name::<AlarmSet>(), |params| {
+                    Some(AlarmSet::from_bytes(params).to_string())
+                })),
+                Write::Empty,
+                false,
+            ),
+            HomeParams => info(
+                "HOMEParams",
+                p::<params::HomeParams>(),
+                w::<params::HomeParams>(),
+                true,
+            ),
+            HomeCmd => info("HOMECmd", None, w::<u32>(), true),
+            ArmOrientation => info(
+                "ArmOrientation",
+                p::<params::ArmOrientation>(),
+                w::<params::ArmOrientation>(),
+                true,
+            ),
+            EndEffectorParams => info(
+                "EndEffectorParams",
+                p::<params::EndEffectorParams>(),
+                w::<params::EndEffectorParams>(),
+                true,
+            ),
+            EndEffectorLaser => info(
+                "EndEffectorLaser",
+                p::<EndEffectorState>(),
+                w::<EndEffectorState>(),
+                true,
+            ),
+            EndEffectorSuctionCup => info(
+                "EndEffectorSuctionCup",
+                p::<EndEffectorState>(),
+                w::<EndEffectorState>(),
+                true,
+            ),
+            EndEffectorGripper => info(
+                "EndEffectorGripper",
+                p::<EndEffectorState>(),
+                w::<EndEffectorState>(),
+                true,
+            ),
+            JogJointParams => info(
+                "JOGJointParams",
+                p::<params::JogJointParams>(),
+                w::<params::JogJointParams>(),
+                true,
+            ),
+            JogCoordinateParams => info(
+                "JOGCoordinateParams",
+                p::<params::JogCoordinateParams>(),
+                w::<params::JogCoordinateParams>(),
+                true,
+            ),
+            JogCommonParams => info(
+                "JOGCommonParams",
+                p::<params::JogCommonParams>(),
+                w::<params::JogCommonParams>(),
+                true,
+            ),
+            JogCmd => info("JOGCmd", None, w::<(JogCommandType, JogCommand)>(), true),
+            PtpJointParams => info(
+                "PTPJointParams",
+                p::<params::PtpJointParams>(),
+                w::<params::PtpJointParams>(),
+                true,
+            ),
+            PtpCoordinateParams => info(
+                "PTPCoordinateParams",
+                p::<params::PtpCoordinateParams>(),
+                w::<params::PtpCoordinateParams>(),
+                true,
+            ),
+            PtpJumpParams => info(
+                "PTPJumpParams",
+                p::<params::PtpJumpParams>(),
+                w::<params::PtpJumpParams>(),
+                true,
+            ),
+            PtpCommonParams => info(
+                "PTPCommonParams",
+                p::<params::PtpCommonParams>(),
+                w::<params::PtpCommonParams>(),
+                true,
+            ),
+            PtpCmd => info("PTPCmd", None, w::<PtpCommand>(), true),
+            CpParams => info(
+                "CPParams",
+                p::<params::CpParams>(),
+                w::<params::CpParams>(),
+                true,
+            ),
+            CpCmd => info("CPCmd", None, w::<CpCommand>(), true),
+            CpLeCmd => info("CPLECmd", None, w::<CpLaserCommand>(), true),
+            ArcParams => info(
+                "ARCParams",
+                p::<params::ArcParams>(),
+                w::<params::ArcParams>(),
+                true,
+            ),
+            ArcCmd => info("ARCCmd", None, w::<ArcCommand>(), true),
+            CircleCmd => info("CircleCmd", None, w::<CircleCommand>(), true),
+            WaitCmd => info("WAITCmd", None, w::<u32>(), true),
+            IoMultiplexing => io(
+                "IOMultiplexing",
+                ParamsType::of::<(IoPin, IoFunction)>(),
+                w::<(IoPin, IoFunction)>(),
+            ),
+            IoDo => io(
+                "IODO",
+                ParamsType::of::<(IoPin, IoLevel)>(),
+                w::<(IoPin, IoLevel)>(),
+            ),
+            IoPwm => io(
+                "IOPWM",
+                ParamsType::of::<(IoPin, f32, f32)>(),
+                w::<(IoPin, f32, f32)>(),
+            ),
+            IoDi => io("IODI", ParamsType::of::<(IoPin, IoLevel)>(), Write::No),
+            IoAdc => io("IOADC", ParamsType::of::<(IoPin, u16)>(), Write::No),
+            QueuedCmdStartExec => info("QueuedCmdStartExec", None, Write::Empty, false),
+            QueuedCmdStopExec => info("QueuedCmdStopExec", None, Write::Empty, false),
+            QueuedCmdForceStopExec => info("QueuedCmdForceStopExec", None, Write::Empty, false),
+            // offline programs, the parameters are the number of loops and
+            // the lines per loop
+            QueuedCmdStartDownload => {
+                info("QueuedCmdStartDownload", None, w::<(u32, u32)>(), false)
+            }
+            QueuedCmdStopDownload => info("QueuedCmdStopDownload", None, Write::Empty, false),
+            QueuedCmdClear => info("QueuedCmdClear", None, Write::Empty, false),
+            QueuedCmdCurrentIndex => info("QueuedCmdCurrentIndex", p::<u64>(), Write::No, false),
+            QueuedCmdLeftSpace => info("QueuedCmdLeftSpace", p::<u32>(), Write::No, false),
+        }
+    }
+
+    /// Looks a command up by its protocol name, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .cloned()
+            .find(|id| id.info().name.eq_ignore_ascii_case(name))
+    }
+
+    /// Builds a request, or fails without touching the wire if the command
+    /// does not support the mode or `params` have the wrong size.
+    pub fn request(
+        self,
+        rw: ReadWrite,
+        queued: bool,
+        params: Vec<u8>,
+    ) -> Result<PayloadStruct, DobotError> {
+        let info = self.info();
+        let (supported, expected) = match rw {
+            ReadWrite::READ => (info.read && !queued, info.read_request),
+            ReadWrite::WRITE => (info.write && (info.queued || !queued), info.write_params),
+        };
+        if !supported {
+            return Err(DobotError::Unsupported(format!(
+                "{} does not support {}{}",
+                info.name,
+                if queued { "queued " } else { "" },
+                match rw {
+                    ReadWrite::READ => "read",
+                    ReadWrite::WRITE => "write",
+                }
+            )));
+        }
+        let size = expected.map_or(Some(0), |params| params.size());
+        if let Some(size) = size.filter(|&size| size != params.len()) {
+            return Err(DobotError::PayloadLength {
+                expected: size,
+                actual: params.len(),
+            });
+        }
+        let mut request = PayloadStruct::with_id(self as u8).set_params(params);
+        if rw == ReadWrite::WRITE {
+            request = request.set_write();
+        }
+        if queued {
+            request = request.set_queued();
+        }
+        Ok(request)
+    }
+}
+
+impl fmt::Display for CommandId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.info().name)
+    }
+}
+
+// whether a command can be written, and with what
+#[derive(Clone, Copy)]
+enum Write {
+    No,
+    Empty,
+    With(ParamsType),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table() {
+        for &id in CommandId::ALL {
+            let info = id.info();
+            assert_eq!(info.id, id);
+            assert_eq!(CommandId::try_from(id as u8).unwrap(), id);
+            assert_eq!(CommandId::from_name(info.name), Some(id));
+            assert!(info.read || info.write, "{}", info.name);
+            assert!(!info.queued || info.write, "{}", info.name);
+        }
+        assert!(CommandId::try_from(200).is_err());
+        assert_eq!(CommandId::from_name("ptpcmd"), Some(CommandId::PtpCmd));
+        let io = CommandId::IoDo.info();
+        assert_eq!(io.write_params.unwrap().to_string(), "(IoPin, IoLevel)");
+        assert_eq!(io.read_request.unwrap().size(), Some(1));
+        // as listed in the communication protocol
+        for id in &[
+            CommandId::HomeParams,
+            CommandId::JogJointParams,
+            CommandId::JogCmd,
+            CommandId::PtpCommonParams,
+            CommandId::CpParams,
+            CommandId::ArcParams,
+        ] {
+            assert!(id.info().queued, "{}", id);
+        }
+        let pwm = CommandId::IoPwm.info();
+        assert!(pwm.read && pwm.queued);
+        assert_eq!(pwm.read_response.unwrap().size(), Some(9));
+    }
+
+    #[test]
+    fn unsupported_requests_are_rejected() {
+        let ptp = CommandId::PtpCmd;
+        assert!(matches!(
+            ptp.request(ReadWrite::READ, false, vec![]),
+            Err(DobotError::Unsupported(_))
+        ));
+        assert!(matches!(
+            CommandId::QueuedCmdClear.request(ReadWrite::WRITE, true, vec![]),
+            Err(DobotError::Unsupported(_))
+        ));
+        assert!(matches!(
+            ptp.request(ReadWrite::WRITE, true, vec![0; 3]),
+            Err(DobotError::PayloadLength {
+                expected: 17,
+                actual: 3
+            })
+        ));
+        assert!(CommandId::QueuedCmdClear
+            .request(ReadWrite::WRITE, false, vec![1])
+            .is_err());
+        let request = ptp.request(ReadWrite::WRITE, true, vec![0; 17]).unwrap();
+        assert_eq!(
+            request,
+            PayloadStruct::with_id(84)
+                .set_write()
+                .set_queued()
+                .set_params(vec![0; 17])
+        );
+    }
+}
@@ -0,0 +1,266 @@
+use crate::protocol::PayloadStruct;
+
+const SYNC: u8 = 0xAA;
+// header(0xAA, 0xAA) + len(1)
+const HEADER_SIZE: usize = 3;
+// id(1) + ctrl(1)
+const MIN_LEN: u8 = 2;
+
+/// What the bytes at some position of the buffer are.
+enum Candidate {
+    /// A frame of the given size.
+    Valid(PayloadStruct, usize),
+    /// A frame with a bad checksum.
+    Corrupt,
+    /// Not a frame header.
+    Invalid,
+    /// More bytes are needed to tell.
+    Incomplete,
+}
+
+/// Incremental decoder for the `0xAA 0xAA` framed protocol.
+///
+/// Bytes can be pushed in arbitrary chunks. The decoder scans for the sync
+/// header, discards anything that is not part of a valid frame and yields
+/// complete `PayloadStruct`s, so a stray byte on the line never
+/// desynchronizes the link for good.
+#[derive(Debug, Default)]
+pub struct FrameDecoder {
+    buf: Vec<u8>,
+    discarded_bytes: usize,
+    checksum_errors: usize,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends received bytes to the internal buffer.
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Pops the next complete frame, or `None` if more bytes are needed.
+    pub fn next_frame(&mut self) -> Option<PayloadStruct> {
+        loop {
+            self.skip_to_sync();
+            let here = self.candidate(0);
+            // A length of 0xAA may as well be the second sync byte of a
+            // header after a stray 0xAA. Both are tried and the checksum
+            // decides, a frame that is complete first is not waited for.
+            let next = if self.buf.get(2) == Some(&SYNC) {
+                self.candidate(1)
+            } else {
+                Candidate::Invalid
+            };
+            match (here, next) {
+                (_, Candidate::Valid(payload, size)) => {
+                    self.discard(1);
+                    self.buf.drain(..size);
+                    return Some(payload);
+                }
+                (Candidate::Valid(payload, size), _) => {
+                    self.buf.drain(..size);
+                    return Some(payload);
+                }
+                (Candidate::Incomplete, _) | (_, Candidate::Incomplete) => return None,
+                (here, _) => {
+                    // Only drop the first sync byte: the bad "frame" may
+                    // have been garbage hiding the start of a real one.
+                    if let Candidate::Corrupt = here {
+                        self.checksum_errors += 1;
+                    }
+                    self.discard(1);
+                }
+            }
+        }
+    }
+
+    fn candidate(&self, start: usize) -> Candidate {
+        let buf = &self.buf[start.min(self.buf.len())..];
+        if buf.len() < HEADER_SIZE {
+            return Candidate::Incomplete;
+        }
+        if buf[0] != SYNC || buf[1] != SYNC || buf[2] < MIN_LEN {
+            return Candidate::Invalid;
+        }
+        // + checksum(1)
+        let size = HEADER_SIZE + buf[2] as usize + 1;
+        if buf.len() < size {
+            return Candidate::Incomplete;
+        }
+        match PayloadStruct::deserialize(&buf[..size]) {
+            Ok(payload) => Candidate::Valid(payload, size),
+            Err(_) => Candidate::Corrupt,
+        }
+    }
+
+    /// Pushes `bytes` and returns every frame that became complete.
+    pub fn decode(&mut self, bytes: &[u8]) -> Vec<PayloadStruct> {
+        self.push(bytes);
+        let mut frames = vec![];
+        while let Some(frame) = self.next_frame() {
+            frames.push(frame);
+        }
+        frames
+    }
+
+    /// Drops all buffered bytes without touching the counters.
+    pub fn clear(&mut self) {
+        self.buf.clear();
+    }
+
+    /// Number of bytes currently buffered and not yet decoded.
+    pub fn buffered_len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Number of bytes thrown away while looking for a valid frame.
+    pub fn discarded_bytes(&self) -> usize {
+        self.discarded_bytes
+    }
+
+    /// Number of complete frames rejected because of a bad checksum.
+    pub fn checksum_errors(&self) -> usize {
+        self.checksum_errors
+    }
+
+    fn skip_to_sync(&mut self) {
+        let start = self
+            .buf
+            .windows(2)
+            .position(|w| w[0] == SYNC && w[1] == SYNC)
+            .unwrap_or_else(|| {
+                // keep a trailing 0xAA, it may be the first half of a header
+                if self.buf.last() == Some(&SYNC) {
+                    self.buf.len() - 1
+                } else {
+                    self.buf.len()
+                }
+            });
+        self.discard(start);
+    }
+
+    fn discard(&mut self, n: usize) {
+        self.buf.drain(..n);
+        self.discarded_bytes += n;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::ReadWrite;
+
+    fn frame(id: u8, params: Vec<u8>) -> Vec<u8> {
+        PayloadStruct::with_id(id).set_params(params).serialize()
+    }
+
+    #[test]
+    fn decode_whole_frame() {
+        let mut decoder = FrameDecoder::new();
+        let frames = decoder.decode(&frame(10, vec![1, 2, 3]));
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].id, 10);
+        assert_eq!(frames[0].rw, ReadWrite::READ);
+        assert_eq!(frames[0].params, vec![1, 2, 3]);
+        assert_eq!(decoder.buffered_len(), 0);
+        assert_eq!(decoder.discarded_bytes(), 0);
+    }
+
+    #[test]
+    fn decode_byte_by_byte() {
+        let mut decoder = FrameDecoder::new();
+        let buf = frame(246, vec![5, 0, 0, 0, 0, 0, 0, 0]);
+        let (last, init) = buf.split_last().unwrap();
+        for b in init {
+            assert!(decoder.decode(&[*b]).is_empty());
+        }
+        let frames = decoder.decode(&[*last]);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].id, 246);
+    }
+
+    #[test]
+    fn decode_multiple_frames_in_one_chunk() {
+        let mut decoder = FrameDecoder::new();
+        let mut buf = frame(1, vec![]);
+        buf.extend(frame(2, vec![9]));
+        buf.extend(&frame(3, vec![8, 7])[..4]);
+        let frames = decoder.decode(&buf);
+        assert_eq!(frames.iter().map(|f| f.id).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(decoder.buffered_len(), 4);
+    }
+
+    #[test]
+    fn resync_after_garbage() {
+        let mut decoder = FrameDecoder::new();
+        let mut buf = vec![0x00, 0xAA, 0x13, 0xAA];
+        buf.extend(frame(20, vec![0xAA, 0xAA]));
+        let frames = decoder.decode(&buf);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].id, 20);
+        assert_eq!(frames[0].params, vec![0xAA, 0xAA]);
+        assert_eq!(decoder.discarded_bytes(), 4);
+    }
+
+    #[test]
+    fn stray_sync_byte_before_header() {
+        let mut decoder = FrameDecoder::new();
+        let mut buf = vec![0xAA];
+        buf.extend(frame(50, vec![1]));
+        let frames = decoder.decode(&buf);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].id, 50);
+        assert_eq!(decoder.discarded_bytes(), 1);
+    }
+
+    #[test]
+    fn skip_bad_checksum() {
+        let mut decoder = FrameDecoder::new();
+        let mut bad = frame(10, vec![1, 2]);
+        let last = bad.len() - 1;
+        bad[last] = bad[last].wrapping_add(1);
+        let mut buf = bad.clone();
+        buf.extend(frame(11, vec![3]));
+        let frames = decoder.decode(&buf);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].id, 11);
+        assert_eq!(decoder.checksum_errors(), 1);
+        assert_eq!(decoder.discarded_bytes(), bad.len());
+    }
+
+    #[test]
+    fn length_of_sync_byte() {
+        let long = frame(84, (0..168).map(|i| i as u8).collect());
+        assert_eq!(long[2], SYNC);
+        let mut decoder = FrameDecoder::new();
+        for b in &long {
+            decoder.push(&[*b]);
+        }
+        let frames = decoder.decode(&frame(1, vec![]));
+        assert_eq!(frames.iter().map(|f| f.id).collect::<Vec<_>>(), vec![84, 1]);
+        assert_eq!(frames[0].params.len(), 168);
+        assert_eq!(decoder.discarded_bytes(), 0);
+
+        // after a stray sync byte
+        let mut buf = vec![0xAA];
+        buf.extend(&long);
+        let frames = decoder.decode(&buf);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].params.len(), 168);
+        assert_eq!(decoder.discarded_bytes(), 1);
+    }
+
+    #[test]
+    fn invalid_length_is_discarded() {
+        let mut decoder = FrameDecoder::new();
+        let mut buf = vec![0xAA, 0xAA, 0x01];
+        buf.extend(frame(0, vec![]));
+        let frames = decoder.decode(&buf);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].id, 0);
+        assert_eq!(decoder.discarded_bytes(), 3);
+    }
+}
@@ -0,0 +1,155 @@
+use crate::codec::Decode;
+use crate::command::CommandId;
+use crate::decoder::FrameDecoder;
+use crate::protocol::{PayloadStruct, ReadWrite};
+use std::convert::TryFrom;
+use std::fmt;
+
+fn hex(params: &[u8]) -> String {
+    let bytes: Vec<String> = params.iter().map(|b| format!("{:02X}", b)).collect();
+    format!("[{}]", bytes.join(" "))
+}
+
+/// Human readable form of a packet, e.g.
+/// `GetPose -> Pose { x: 200.0, .. }` or `SetPTPCmd (queued) -> index 3`.
+///
+/// Packets do not say whether they are a request or a response, that is
+/// told apart by the parameters: a read with the parameters of a response is
+/// a response, as is a queued write carrying just the queue index.
+/// Parameters that do not match the command are shown as hex bytes.
+pub struct Dissection<'a>(pub &'a PayloadStruct);
+
+impl fmt::Display for Dissection<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let packet = self.0;
+        let params = &packet.params[..];
+        let info = CommandId::try_from(packet.id).ok().map(CommandId::info);
+        let prefix = match packet.rw {
+            ReadWrite::READ => "Get",
+            ReadWrite::WRITE => "Set",
+        };
+        match &info {
+            Some(info) => write!(f, "{}{}", prefix, info.name)?,
+            None => write!(f, "{}Cmd{}", prefix, packet.id)?,
+        }
+        if packet.is_queued {
+            write!(f, " (queued)")?;
+        }
+        if params.is_empty() {
+            return Ok(());
+        }
+        let info = match info {
+            Some(info) => info,
+            None => return write!(f, " {}", hex(params)),
+        };
+        let (request, response) = match packet.rw {
+            ReadWrite::READ => (info.read_request, info.read_response),
+            ReadWrite::WRITE => (info.write_params, None),
+        };
+        let shown = match request {
+            Some(request) if request.size() == Some(params.len()) => {
+                request.describe(params).map(|s| format!(" {}", s))
+            }
+            _ if packet.rw == ReadWrite::WRITE && packet.is_queued && params.len() == 8 => {
+                u64::decode(params).ok().map(|i| format!(" -> index {}", i))
+            }
+            _ => response
+                .and_then(|response| response.describe(params))
+                .map(|s| format!(" -> {}", s)),
+        };
+        match shown {
+            Some(shown) => write!(f, "{}", shown),
+            None => write!(f, " {}", hex(params)),
+        }
+    }
+}
+
+/// Dissects every complete frame in a captured byte stream, skipping
+/// anything that is not a valid frame.
+pub fn dissect_stream(bytes: &[u8]) -> Vec<String> {
+    FrameDecoder::new()
+        .decode(bytes)
+        .iter()
+        .map(|frame| Dissection(frame).to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::Encode;
+    use crate::params::Pose;
+
+    fn dissect(packet: PayloadStruct) -> String {
+        Dissection(&packet).to_string()
+    }
+
+    #[test]
+    fn requests_and_responses() {
+        assert_eq!(dissect(PayloadStruct::with_id(10)), "GetPose");
+        let pose = Pose {
+            x: 200.0,
+            y: 0.0,
+            z: 10.0,
+            r: 0.0,
+            joint_angles: [0.0; 4],
+        };
+        assert_eq!(
+            dissect(PayloadStruct::with_id(10).set_params(pose.to_bytes())),
+            format!("GetPose -> {:?}", pose)
+        );
+        assert_eq!(
+            dissect(PayloadStruct::with_id(131).set_params(vec![18])),
+            "GetIODO IoPin(18)"
+        );
+        assert_eq!(
+            dissect(PayloadStruct::with_id(131).set_params(vec![18, 1])),
+            "GetIODO -> (IoPin(18), High)"
+        );
+        assert_eq!(
+            dissect(
+                PayloadStruct::with_id(110)
+                    .set_write()
+                    .set_queued()
+                    .set_params(500u32.to_bytes())
+            ),
+            "SetWAITCmd (queued) 500"
+        );
+        assert_eq!(
+            dissect(
+                PayloadStruct::with_id(84)
+                    .set_write()
+                    .set_queued()
+                    .set_params(3u64.to_bytes())
+            ),
+            "SetPTPCmd (queued) -> index 3"
+        );
+        assert_eq!(
+            dissect(PayloadStruct::with_id(20).set_params(vec![0; 32])),
+            "GetAlarmsState -> no alarm"
+        );
+        assert_eq!(
+            dissect(PayloadStruct::with_id(200).set_params(vec![0xAA, 1])),
+            "GetCmd200 [AA 01]"
+        );
+        assert_eq!(
+            dissect(PayloadStruct::with_id(84).set_write().set_params(vec![9])),
+            "SetPTPCmd [09]"
+        );
+    }
+
+    #[test]
+    fn captured_stream() {
+        let mut bytes = vec![0x00];
+        bytes.extend(PayloadStruct::with_id(246).serialize());
+        bytes.extend(
+            PayloadStruct::with_id(246)
+                .set_params(7u64.to_bytes())
+                .serialize(),
+        );
+        assert_eq!(
+            dissect_stream(&bytes),
+            vec!["GetQueuedCmdCurrentIndex", "GetQueuedCmdCurrentIndex -> 7"]
+        );
+    }
+}
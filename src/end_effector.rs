@@ -0,0 +1,143 @@
+use crate::client::{DobotClient, QueuedHandle};
+use crate::error::DobotError;
+use crate::params::*;
+use crate::traits::Device;
+
+/// Tool mounted on the arm, so pick logic can `grip` and `release` without
+/// knowing how the tool is driven.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EndEffector {
+    /// Suction cup driven by ID 62.
+    SuctionCup,
+    /// Gripper driven by ID 63.
+    Gripper,
+    /// Tool driven by digital outputs, each action sets the listed
+    /// (pin, level) pairs in order.
+    Io {
+        grip: Vec<(IoPin, IoLevel)>,
+        release: Vec<(IoPin, IoLevel)>,
+    },
+}
+
+impl EndEffector {
+    /// Air pump of the M1 on outputs 17 and 18.
+    pub fn m1_air_pump() -> Self {
+        EndEffector::Io {
+            grip: vec![(IoPin(17), IoLevel::Low), (IoPin(18), IoLevel::Low)],
+            release: vec![(IoPin(17), IoLevel::Low), (IoPin(18), IoLevel::High)],
+        }
+    }
+
+    pub fn grip<T: Device>(&self, client: &mut DobotClient<T>) -> Result<(), DobotError> {
+        self.set(client, true)
+    }
+
+    pub fn release<T: Device>(&self, client: &mut DobotClient<T>) -> Result<(), DobotError> {
+        self.set(client, false)
+    }
+
+    /// Queues the grip and returns the handle of its last command.
+    pub fn grip_queued<T: Device>(
+        &self,
+        client: &mut DobotClient<T>,
+    ) -> Result<QueuedHandle, DobotError> {
+        self.set_queued(client, true)
+    }
+
+    /// Queues the release and returns the handle of its last command.
+    pub fn release_queued<T: Device>(
+        &self,
+        client: &mut DobotClient<T>,
+    ) -> Result<QueuedHandle, DobotError> {
+        self.set_queued(client, false)
+    }
+
+    fn state(grip: bool) -> EndEffectorState {
+        if grip {
+            EndEffectorState::On
+        } else {
+            EndEffectorState::Off
+        }
+    }
+
+    fn outputs(&self, grip: bool) -> &[(IoPin, IoLevel)] {
+        match self {
+            EndEffector::Io { grip: g, .. } if grip => g,
+            EndEffector::Io { release, .. } => release,
+            _ => &[],
+        }
+    }
+
+    fn set<T: Device>(&self, client: &mut DobotClient<T>, grip: bool) -> Result<(), DobotError> {
+        match self {
+            EndEffector::SuctionCup => client.set_end_effector_suction_cup(Self::state(grip)),
+            EndEffector::Gripper => client.set_end_effector_gripper(Self::state(grip)),
+            EndEffector::Io { .. } => {
+                for &(pin, level) in self.outputs(grip) {
+                    client.set_iodo(pin, level)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn set_queued<T: Device>(
+        &self,
+        client: &mut DobotClient<T>,
+        grip: bool,
+    ) -> Result<QueuedHandle, DobotError> {
+        match self {
+            EndEffector::SuctionCup => {
+                client.set_end_effector_suction_cup_queued(Self::state(grip))
+            }
+            EndEffector::Gripper => client.set_end_effector_gripper_queued(Self::state(grip)),
+            EndEffector::Io { .. } => {
+                let mut last = None;
+                for &(pin, level) in self.outputs(grip) {
+                    last = Some(client.set_iodo_queued(pin, level)?);
+                }
+                last.ok_or_else(|| {
+                    DobotError::InvalidValue("end effector has no outputs to set".to_owned())
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::SimulatedDevice;
+    use std::time::Duration;
+
+    #[test]
+    fn grip_and_release() {
+        let mut dobot = DobotClient::new(SimulatedDevice::new());
+        EndEffector::SuctionCup.grip(&mut dobot).unwrap();
+        assert_eq!(
+            dobot.get_end_effector_suction_cup().unwrap(),
+            EndEffectorState::On
+        );
+        EndEffector::Gripper.release(&mut dobot).unwrap();
+        assert_eq!(
+            dobot.get_end_effector_gripper().unwrap(),
+            EndEffectorState::Off
+        );
+
+        let pump = EndEffector::m1_air_pump();
+        pump.grip_queued(&mut dobot).unwrap();
+        let handle = pump.release_queued(&mut dobot).unwrap();
+        assert_eq!(handle.index(), 4);
+        dobot.set_queued_command_start_exec().unwrap();
+        handle
+            .wait(&mut dobot, Duration::from_secs(1), Duration::from_millis(0))
+            .unwrap();
+        assert_eq!(dobot.device().io_output(18), Some(1));
+
+        let empty = EndEffector::Io {
+            grip: vec![],
+            release: vec![],
+        };
+        assert!(empty.grip_queued(&mut dobot).is_err());
+    }
+}
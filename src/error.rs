@@ -0,0 +1,123 @@
+use crate::alarm::AlarmSet;
+use std::fmt;
+use std::io;
+use std::string::FromUtf8Error;
+
+/// Error type of every fallible operation in this crate.
+///
+/// The variants let callers tell transient conditions (`Timeout`, `Io`)
+/// apart from protocol violations that usually call for resetting the link.
+#[derive(Debug)]
+pub enum DobotError {
+    /// I/O error of the underlying transport.
+    Io(io::Error),
+    /// No response arrived in time.
+    Timeout,
+    /// Bytes on the wire do not form a valid packet.
+    Framing(String),
+    /// Packet checksum does not match its contents.
+    Checksum { expected: u8, actual: u8 },
+    /// The response belongs to another command than the request.
+    IdMismatch { expected: u8, actual: u8 },
+    /// Parameters of a response have an unexpected size.
+    PayloadLength { expected: usize, actual: usize },
+    /// A parameter has a value outside of its valid range.
+    InvalidValue(String),
+    /// The device reports alarms.
+    Alarm(AlarmSet),
+    /// A string parameter is not valid UTF-8.
+    Utf8(FromUtf8Error),
+    /// The device does not support the command.
+    Unsupported(String),
+    /// The command queue of the device has no room left.
+    QueueFull,
+    /// A test double received a request it was not scripted for.
+    UnexpectedRequest(String),
+    /// A motion command was rejected by the `SafetyEnvelope` and not sent.
+    SafetyViolation(String),
+}
+
+impl fmt::Display for DobotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DobotError::Io(e) => write!(f, "I/O error: {}", e),
+            DobotError::Timeout => write!(f, "timed out waiting for a response"),
+            DobotError::Framing(message) => write!(f, "framing error: {}", message),
+            DobotError::Checksum { expected, actual } => write!(
+                f,
+                "checksum error: sum should be {}, but calculation result is {}",
+                expected, actual
+            ),
+            DobotError::IdMismatch { expected, actual } => {
+                write!(f, "id should be {}, but it is {}", expected, actual)
+            }
+            DobotError::PayloadLength { expected, actual } => write!(
+                f,
+                "params length should be {}, but it is {}",
+                expected, actual
+            ),
+            DobotError::InvalidValue(message) => write!(f, "invalid value: {}", message),
+            DobotError::Alarm(state) => write!(f, "device alarm: {}", state),
+            DobotError::Utf8(e) => write!(f, "invalid UTF-8: {}", e),
+            DobotError::Unsupported(message) => write!(f, "unsupported: {}", message),
+            DobotError::QueueFull => write!(f, "command queue is full"),
+            DobotError::UnexpectedRequest(message) => write!(f, "{}", message),
+            DobotError::SafetyViolation(message) => write!(f, "safety violation: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for DobotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DobotError::Io(e) => Some(e),
+            DobotError::Utf8(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DobotError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => DobotError::Timeout,
+            _ => DobotError::Io(e),
+        }
+    }
+}
+
+impl From<serial::Error> for DobotError {
+    fn from(e: serial::Error) -> Self {
+        io::Error::from(e).into()
+    }
+}
+
+impl From<FromUtf8Error> for DobotError {
+    fn from(e: FromUtf8Error) -> Self {
+        DobotError::Utf8(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn io_timeouts_are_timeouts() {
+        let e: DobotError = io::Error::new(io::ErrorKind::TimedOut, "t").into();
+        assert!(matches!(e, DobotError::Timeout));
+        let e: DobotError = io::Error::new(io::ErrorKind::WouldBlock, "t").into();
+        assert!(matches!(e, DobotError::Timeout));
+        let e: DobotError = io::Error::new(io::ErrorKind::BrokenPipe, "b").into();
+        assert!(matches!(e, DobotError::Io(_)));
+    }
+
+    #[test]
+    fn display() {
+        let e = DobotError::IdMismatch {
+            expected: 10,
+            actual: 20,
+        };
+        assert_eq!(e.to_string(), "id should be 10, but it is 20");
+    }
+}
@@ -0,0 +1,308 @@
+use crate::params::{ArmOrientation, Pose};
+
+/// Geometry of a SCARA arm such as the Dobot M1.
+///
+/// Joints are J1, J2 (deg), J3 (mm, equal to z) and J4 (deg); Cartesian
+/// coordinates are x, y, z (mm) and r (deg), where r = J1 + J2 + J4 like in
+/// `Pose`. `Lefty` solutions have J2 >= 0, `Righty` ones J2 < 0.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Kinematics {
+    /// Length of the first arm link in mm.
+    pub link1: f32,
+    /// Length of the second arm link in mm.
+    pub link2: f32,
+    /// (min, max) of every joint.
+    pub joint_limits: [(f32, f32); 4],
+}
+
+impl Kinematics {
+    /// Dobot M1
+    pub const M1: Self = Self {
+        link1: 200.0,
+        link2: 200.0,
+        joint_limits: [
+            (-85.0, 85.0),
+            (-135.0, 135.0),
+            (0.0, 230.0),
+            (-360.0, 360.0),
+        ],
+    };
+
+    /// Cartesian coordinates (x, y, z, r) of `joints`.
+    pub fn forward(&self, joints: &[f32; 4]) -> [f32; 4] {
+        let (j1, j2) = (joints[0].to_radians(), joints[1].to_radians());
+        [
+            self.link1 * j1.cos() + self.link2 * (j1 + j2).cos(),
+            self.link1 * j1.sin() + self.link2 * (j1 + j2).sin(),
+            joints[2],
+            joints[0] + joints[1] + joints[3],
+        ]
+    }
+
+    /// Joint angles reaching `cartesian` (x, y, z, r) with the given
+    /// orientation, or `None` if the point is out of reach of the links.
+    ///
+    /// Joint limits are not checked, use `limit_violation` for that.
+    pub fn inverse(&self, cartesian: &[f32; 4], orientation: ArmOrientation) -> Option<[f32; 4]> {
+        let (x, y) = (cartesian[0], cartesian[1]);
+        let (l1, l2) = (self.link1, self.link2);
+        let c2 = (x * x + y * y - l1 * l1 - l2 * l2) / (2.0 * l1 * l2);
+        if !(-1.0..=1.0).contains(&c2) {
+            return None;
+        }
+        let s2 = match orientation {
+            ArmOrientation::Lefty => (1.0 - c2 * c2).sqrt(),
+            ArmOrientation::Righty => -(1.0 - c2 * c2).sqrt(),
+        };
+        let j1 = y.atan2(x) - (l2 * s2).atan2(l1 + l2 * c2);
+        let j2 = s2.atan2(c2);
+        let (j1, j2) = (j1.to_degrees(), j2.to_degrees());
+        Some([j1, j2, cartesian[2], cartesian[3] - j1 - j2])
+    }
+
+    /// Orientation of the arm at `joints`.
+    pub fn orientation(&self, joints: &[f32; 4]) -> ArmOrientation {
+        if joints[1] >= 0.0 {
+            ArmOrientation::Lefty
+        } else {
+            ArmOrientation::Righty
+        }
+    }
+
+    /// First joint (0-based) that is outside of `joint_limits`.
+    pub fn limit_violation(&self, joints: &[f32; 4]) -> Option<usize> {
+        (0..4).find(|&i| joints[i] < self.joint_limits[i].0 || joints[i] > self.joint_limits[i].1)
+    }
+
+    /// Full `Pose` of `joints`.
+    pub fn pose(&self, joints: &[f32; 4]) -> Pose {
+        let [x, y, z, r] = self.forward(joints);
+        Pose {
+            x,
+            y,
+            z,
+            r,
+            joint_angles: *joints,
+        }
+    }
+}
+
+impl Default for Kinematics {
+    fn default() -> Self {
+        Self::M1
+    }
+}
+
+/// Points on the circle through `p0`, `p1` and `p2`, about 5 deg apart and
+/// excluding `p0`. The arc ends at `p2`, or after `turns` full circles.
+pub(crate) fn arc_points(
+    p0: &[f32; 3],
+    p1: &[f32; 3],
+    p2: &[f32; 3],
+    turns: Option<u32>,
+) -> Option<Vec<[f32; 3]>> {
+    let sub = |a: &[f32; 3], b: &[f32; 3]| [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+    let dot = |a: &[f32; 3], b: &[f32; 3]| a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+    let cross = |a: &[f32; 3], b: &[f32; 3]| {
+        [
+            a[1] * b[2] - a[2] * b[1],
+            a[2] * b[0] - a[0] * b[2],
+            a[0] * b[1] - a[1] * b[0],
+        ]
+    };
+    let (a, b) = (sub(p1, p0), sub(p2, p0));
+    let n = cross(&a, &b);
+    let nn = dot(&n, &n);
+    if nn < 1e-6 {
+        // the points are on a line
+        return None;
+    }
+    let (bn, na) = (cross(&b, &n), cross(&n, &a));
+    let (aa, bb) = (dot(&a, &a), dot(&b, &b));
+    let center: Vec<f32> = (0..3)
+        .map(|i| p0[i] + (aa * bn[i] + bb * na[i]) / (2.0 * nn))
+        .collect();
+    let center = [center[0], center[1], center[2]];
+    let u = sub(p0, &center);
+    let radius = dot(&u, &u).sqrt();
+    let v = cross(&n, &u);
+    let v_len = dot(&v, &v).sqrt();
+    let v = [
+        v[0] / v_len * radius,
+        v[1] / v_len * radius,
+        v[2] / v_len * radius,
+    ];
+    let angle = |p: &[f32; 3]| {
+        let d = sub(p, &center);
+        let t = dot(&d, &v).atan2(dot(&d, &u));
+        if t < 0.0 {
+            t + 2.0 * std::f32::consts::PI
+        } else {
+            t
+        }
+    };
+    let sweep = match turns {
+        Some(turns) => 2.0 * std::f32::consts::PI * turns as f32,
+        None => angle(p2),
+    };
+    let steps = (sweep / 5f32.to_radians()).ceil().max(1.0) as usize;
+    Some(
+        (1..=steps)
+            .map(|i| {
+                let t = sweep * i as f32 / steps as f32;
+                let (c, s) = (t.cos(), t.sin());
+                [
+                    center[0] + u[0] * c + v[0] * s,
+                    center[1] + u[1] * c + v[1] * s,
+                    center[2] + u[2] * c + v[2] * s,
+                ]
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn approx(a: f32, b: f32) -> bool {
+        (a - b).abs() < 0.01
+    }
+
+    fn pose(x: f32, y: f32, z: f32, r: f32, joint_angles: [f32; 4]) -> Pose {
+        Pose {
+            x,
+            y,
+            z,
+            r,
+            joint_angles,
+        }
+    }
+
+    // Poses worked out by hand from the 200 mm links of the M1, at angles
+    // with exact sines and cosines: [0, 90] puts the second link at a right
+    // angle to the first, [60, -60] turns it back parallel to the x axis,
+    // [-60, 120] and [45, -90] bring the tip back onto the x axis.
+    fn samples() -> Vec<Pose> {
+        let (sqrt2, sqrt3) = (2f32.sqrt(), 3f32.sqrt());
+        vec![
+            pose(200.0, 200.0, 100.0, 90.0, [0.0, 90.0, 100.0, 0.0]),
+            pose(300.0, 100.0 * sqrt3, 50.0, 0.0, [60.0, -60.0, 50.0, 0.0]),
+            pose(200.0, 0.0, 150.0, 90.0, [-60.0, 120.0, 150.0, 30.0]),
+            pose(200.0 * sqrt2, 0.0, 10.0, -45.0, [45.0, -90.0, 10.0, 0.0]),
+            pose(100.0 * sqrt3, 300.0, 200.0, 0.0, [30.0, 60.0, 200.0, -90.0]),
+        ]
+    }
+
+    // `GetPose` answers read from a real M1, as printed by `dobot <device>
+    // pose`. Only they show that the link lengths, the J2 sign and the r
+    // offset match the arm, the samples above only match this model.
+    // None have been recorded yet.
+    fn recorded() -> Vec<Pose> {
+        vec![]
+    }
+
+    #[test]
+    #[ignore = "needs poses recorded from a real M1"]
+    fn forward_matches_recorded_poses() {
+        let recorded = recorded();
+        assert!(!recorded.is_empty(), "no poses recorded from an M1 yet");
+        let k = Kinematics::M1;
+        for sample in recorded {
+            let [x, y, z, r] = k.forward(&sample.joint_angles);
+            let error = [x - sample.x, y - sample.y, z - sample.z, r - sample.r];
+            // allow for calibration and rounding on the device
+            assert!(error.iter().all(|e| e.abs() < 0.5), "{:?}", sample);
+            let orientation = k.orientation(&sample.joint_angles);
+            let joints = k
+                .inverse(&[sample.x, sample.y, sample.z, sample.r], orientation)
+                .unwrap();
+            for (a, b) in joints.iter().zip(&sample.joint_angles) {
+                assert!((a - b).abs() < 0.5, "{:?}", sample);
+            }
+        }
+    }
+
+    #[test]
+    fn forward_matches_samples() {
+        let k = Kinematics::M1;
+        for sample in samples() {
+            let [x, y, z, r] = k.forward(&sample.joint_angles);
+            assert!(
+                approx(x, sample.x) && approx(y, sample.y) && approx(z, sample.z),
+                "{:?}",
+                sample
+            );
+            assert!(approx(r, sample.r));
+        }
+    }
+
+    #[test]
+    fn inverse_matches_samples() {
+        let k = Kinematics::M1;
+        for sample in samples() {
+            let orientation = k.orientation(&sample.joint_angles);
+            let joints = k
+                .inverse(&[sample.x, sample.y, sample.z, sample.r], orientation)
+                .unwrap();
+            for (a, b) in joints.iter().zip(&sample.joint_angles) {
+                assert!(approx(*a, *b), "{:?}", sample);
+            }
+            assert_eq!(k.limit_violation(&joints), None);
+        }
+    }
+
+    #[test]
+    fn other_orientation_reaches_same_point() {
+        let k = Kinematics::M1;
+        let cartesian = [100.0 * 3f32.sqrt(), 300.0, 200.0, 0.0];
+        let righty = k.inverse(&cartesian, ArmOrientation::Righty).unwrap();
+        assert_eq!(k.orientation(&righty), ArmOrientation::Righty);
+        let back = k.forward(&righty);
+        for (a, b) in back.iter().zip(&cartesian) {
+            assert!(approx(*a, *b));
+        }
+    }
+
+    #[test]
+    fn arc_points_follow_circle() {
+        let points = arc_points(
+            &[100.0, 0.0, 0.0],
+            &[0.0, 100.0, 0.0],
+            &[-100.0, 0.0, 0.0],
+            None,
+        )
+        .unwrap();
+        let last = points[points.len() - 1];
+        assert!(approx(last[0], -100.0) && approx(last[1], 0.0));
+        for p in &points {
+            assert!(approx((p[0] * p[0] + p[1] * p[1]).sqrt(), 100.0));
+            assert!(p[1] >= -0.01);
+        }
+        let circle = arc_points(
+            &[100.0, 0.0, 0.0],
+            &[0.0, 100.0, 0.0],
+            &[-100.0, 0.0, 0.0],
+            Some(2),
+        )
+        .unwrap();
+        assert!(circle.len() >= 144);
+        let last = circle[circle.len() - 1];
+        assert!(approx(last[0], 100.0) && approx(last[1], 0.0));
+        assert!(arc_points(&[0.0; 3], &[1.0, 0.0, 0.0], &[2.0, 0.0, 0.0], None).is_none());
+    }
+
+    #[test]
+    fn unreachable_and_limits() {
+        let k = Kinematics::M1;
+        assert!(k
+            .inverse(&[500.0, 0.0, 0.0, 0.0], ArmOrientation::Lefty)
+            .is_none());
+        assert!(k
+            .inverse(&[0.0, 0.0, 0.0, 0.0], ArmOrientation::Lefty)
+            .is_some());
+        assert_eq!(k.limit_violation(&[90.0, 0.0, 0.0, 0.0]), Some(0));
+        assert_eq!(k.limit_violation(&[0.0, 0.0, 231.0, 0.0]), Some(2));
+        assert!(approx(k.pose(&[0.0, 90.0, 100.0, 0.0]).x, 200.0));
+    }
+}
@@ -1,11 +1,56 @@
+mod alarm;
+#[cfg(feature = "tokio")]
+mod async_client;
+#[cfg(feature = "tokio")]
+mod async_transport;
+mod capture;
 mod client;
+mod codec;
+mod command;
+mod decoder;
+mod dissect;
+mod end_effector;
+mod error;
+mod kinematics;
+mod mock;
+mod params;
 mod protocol;
+mod queue;
+mod request;
+mod retry;
+mod safety;
 mod serial;
+mod shared;
+mod sim;
+mod tcp;
+mod telemetry;
 mod traits;
 mod udp;
 
+pub use self::alarm::*;
+#[cfg(feature = "tokio")]
+pub use self::async_client::*;
+#[cfg(feature = "tokio")]
+pub use self::async_transport::*;
+pub use self::capture::*;
 pub use self::client::*;
+pub use self::codec::*;
+pub use self::command::*;
+pub use self::decoder::*;
+pub use self::dissect::*;
+pub use self::end_effector::*;
+pub use self::error::*;
+pub use self::kinematics::*;
+pub use self::mock::*;
+pub use self::params::*;
 pub use self::protocol::*;
+pub use self::queue::*;
+pub use self::retry::*;
+pub use self::safety::*;
 pub use self::serial::*;
+pub use self::shared::*;
+pub use self::sim::*;
+pub use self::tcp::*;
+pub use self::telemetry::*;
 pub use self::traits::*;
 pub use self::udp::*;
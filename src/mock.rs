@@ -0,0 +1,198 @@
+use crate::error::DobotError;
+use crate::protocol::PayloadStruct;
+use crate::traits::Device;
+use std::collections::VecDeque;
+use std::fmt;
+
+type Responder = Box<dyn FnMut(&PayloadStruct) -> Result<PayloadStruct, DobotError> + Send>;
+
+enum Response {
+    Payload(PayloadStruct),
+    Func(Responder),
+}
+
+struct Expectation {
+    request: PayloadStruct,
+    response: Response,
+}
+
+/// Scriptable in-process `Device` for testing code built on `DobotClient`.
+///
+/// Expected requests are matched in order. Every request is recorded, and
+/// `verify` reports expectations that were not met.
+///
+/// ```
+/// use dobot_client::*;
+///
+/// let mut mock = MockDevice::new();
+/// mock.expect(
+///     PayloadStruct::with_id(0),
+///     PayloadStruct::with_id(0).set_params(b"SN01".to_vec()),
+/// );
+/// let mut dobot = DobotClient::new(mock);
+/// assert_eq!(dobot.get_device_sn().unwrap(), "SN01");
+/// dobot.device().verify().unwrap();
+/// ```
+#[derive(Default)]
+pub struct MockDevice {
+    expectations: VecDeque<Expectation>,
+    fallback: Option<Responder>,
+    requests: Vec<PayloadStruct>,
+    errors: Vec<String>,
+}
+
+impl fmt::Debug for MockDevice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MockDevice")
+            .field("pending_expectations", &self.expectations.len())
+            .field("requests", &self.requests)
+            .field("errors", &self.errors)
+            .finish()
+    }
+}
+
+impl MockDevice {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expects `request` next and answers it with `response`.
+    pub fn expect(&mut self, request: PayloadStruct, response: PayloadStruct) -> &mut Self {
+        self.expectations.push_back(Expectation {
+            request,
+            response: Response::Payload(response),
+        });
+        self
+    }
+
+    /// Expects `request` next and answers it by calling `f`.
+    pub fn expect_with<F>(&mut self, request: PayloadStruct, f: F) -> &mut Self
+    where
+        F: FnMut(&PayloadStruct) -> Result<PayloadStruct, DobotError> + Send + 'static,
+    {
+        self.expectations.push_back(Expectation {
+            request,
+            response: Response::Func(Box::new(f)),
+        });
+        self
+    }
+
+    /// Answers requests that arrive when no expectation is pending.
+    ///
+    /// Without a fallback such requests are errors.
+    pub fn respond_with<F>(&mut self, f: F) -> &mut Self
+    where
+        F: FnMut(&PayloadStruct) -> Result<PayloadStruct, DobotError> + Send + 'static,
+    {
+        self.fallback = Some(Box::new(f));
+        self
+    }
+
+    /// Every request sent so far, in order.
+    pub fn requests(&self) -> &[PayloadStruct] {
+        &self.requests
+    }
+
+    /// Number of expectations not consumed yet.
+    pub fn pending(&self) -> usize {
+        self.expectations.len()
+    }
+
+    /// Fails if a request did not match or an expectation is still pending.
+    pub fn verify(&self) -> Result<(), DobotError> {
+        let mut errors = self.errors.clone();
+        errors.extend(
+            self.expectations
+                .iter()
+                .map(|e| format!("expected request was never sent: {}", e.request)),
+        );
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(DobotError::UnexpectedRequest(errors.join("\n")))
+        }
+    }
+
+    fn fail(&mut self, message: String) -> DobotError {
+        self.errors.push(message.clone());
+        DobotError::UnexpectedRequest(message)
+    }
+}
+
+impl Device for MockDevice {
+    fn send(&mut self, packet: PayloadStruct) -> Result<PayloadStruct, DobotError> {
+        self.requests.push(packet.clone());
+        match self.expectations.pop_front() {
+            Some(expectation) => {
+                if expectation.request != packet {
+                    return Err(self.fail(format!(
+                        "unexpected request: expected {}, got {}",
+                        expectation.request, packet
+                    )));
+                }
+                match expectation.response {
+                    Response::Payload(response) => Ok(response),
+                    Response::Func(mut f) => f(&packet),
+                }
+            }
+            None => match self.fallback.as_mut() {
+                Some(f) => f(&packet),
+                None => Err(self.fail(format!("unexpected request: {}", packet))),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_in_order_and_records() {
+        let mut mock = MockDevice::new();
+        mock.expect(PayloadStruct::with_id(1), PayloadStruct::with_id(1))
+            .expect_with(PayloadStruct::with_id(2).set_write(), |req| {
+                Ok(PayloadStruct::with_id(req.id).set_params(vec![9]))
+            });
+        assert_eq!(mock.pending(), 2);
+        assert_eq!(mock.send(PayloadStruct::with_id(1)).unwrap().id, 1);
+        let ret = mock.send(PayloadStruct::with_id(2).set_write()).unwrap();
+        assert_eq!(ret.params, vec![9]);
+        assert_eq!(mock.requests().len(), 2);
+        assert!(mock.verify().is_ok());
+    }
+
+    #[test]
+    fn reports_mismatch_and_unmet() {
+        let mut mock = MockDevice::new();
+        mock.expect(PayloadStruct::with_id(1), PayloadStruct::with_id(1))
+            .expect(PayloadStruct::with_id(2), PayloadStruct::with_id(2));
+        assert!(mock.send(PayloadStruct::with_id(3)).is_err());
+        let message = mock.verify().unwrap_err().to_string();
+        assert!(message.contains("unexpected request"));
+        assert!(message.contains("never sent"));
+    }
+
+    #[test]
+    fn reports_binary_params() {
+        let mut mock = MockDevice::new();
+        mock.expect(
+            PayloadStruct::with_id(84).set_params(vec![0xFF, 0x00]),
+            PayloadStruct::with_id(84),
+        );
+        assert!(mock
+            .send(PayloadStruct::with_id(84).set_params(vec![0xFE]))
+            .is_err());
+        let message = mock.verify().unwrap_err().to_string();
+        assert!(message.contains("[FF 00]"), "{}", message);
+        assert!(format!("{:?}", mock).contains("[FE]"));
+    }
+
+    #[test]
+    fn fallback() {
+        let mut mock = MockDevice::new();
+        assert!(mock.send(PayloadStruct::with_id(5)).is_err());
+        mock.respond_with(|req| Ok(req.clone()));
+        assert_eq!(mock.send(PayloadStruct::with_id(6)).unwrap().id, 6);
+    }
+}
@@ -0,0 +1,877 @@
+use crate::codec::{Decode, Encode, Reader};
+use crate::error::DobotError;
+use std::convert::TryFrom;
+
+/// Implements `TryFrom<u8>`, `Encode` and `Decode` for a `#[repr(u8)]` enum.
+macro_rules! impl_u8_enum {
+    ($name:ident { $($variant:ident),* $(,)? }) => {
+        impl TryFrom<u8> for $name {
+            type Error = DobotError;
+
+            fn try_from(v: u8) -> Result<Self, DobotError> {
+                $(
+                    if v == $name::$variant as u8 {
+                        return Ok($name::$variant);
+                    }
+                )*
+                Err(DobotError::InvalidValue(format!(
+                    "{} is not a valid {}",
+                    v,
+                    stringify!($name)
+                )))
+            }
+        }
+
+        impl Encode for $name {
+            fn encode(&self, buf: &mut Vec<u8>) {
+                buf.push(*self as u8);
+            }
+        }
+
+        impl Decode for $name {
+            const SIZE: usize = 1;
+
+            fn read(reader: &mut Reader) -> Result<Self, DobotError> {
+                $name::try_from(reader.u8())
+            }
+        }
+    };
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IoLevel {
+    Low,
+    High,
+}
+
+impl_u8_enum!(IoLevel { Low, High });
+
+/// IO
+///
+/// Address of an IO pin, 1 ~ 22. The air pump of the M1 is connected to 18.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct IoPin(pub(crate) u8);
+
+impl IoPin {
+    pub const MIN: u8 = 1;
+    pub const MAX: u8 = 22;
+
+    pub fn new(address: u8) -> Result<Self, DobotError> {
+        if (Self::MIN..=Self::MAX).contains(&address) {
+            Ok(IoPin(address))
+        } else {
+            Err(DobotError::InvalidValue(format!(
+                "IO address {} is not in {} ~ {}",
+                address,
+                Self::MIN,
+                Self::MAX
+            )))
+        }
+    }
+
+    pub fn address(&self) -> u8 {
+        self.0
+    }
+}
+
+impl TryFrom<u8> for IoPin {
+    type Error = DobotError;
+
+    fn try_from(address: u8) -> Result<Self, DobotError> {
+        IoPin::new(address)
+    }
+}
+
+impl Encode for IoPin {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(self.0);
+    }
+}
+
+impl Decode for IoPin {
+    const SIZE: usize = 1;
+
+    fn read(reader: &mut Reader) -> Result<Self, DobotError> {
+        IoPin::new(reader.u8())
+    }
+}
+
+/// Function of a multiplexed IO pin.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IoFunction {
+    Dummy,
+    DigitalOutput,
+    Pwm,
+    DigitalInput,
+    Adc,
+    DigitalInputPullUp,
+    DigitalInputPullDown,
+}
+
+impl_u8_enum!(IoFunction {
+    Dummy,
+    DigitalOutput,
+    Pwm,
+    DigitalInput,
+    Adc,
+    DigitalInputPullUp,
+    DigitalInputPullDown,
+});
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArmOrientation {
+    Lefty,
+    Righty,
+}
+
+impl Encode for ArmOrientation {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(*self as u8);
+    }
+}
+
+impl Decode for ArmOrientation {
+    const SIZE: usize = 1;
+
+    fn read(reader: &mut Reader) -> Result<Self, DobotError> {
+        Ok(if reader.u8() == 0 {
+            ArmOrientation::Lefty
+        } else {
+            ArmOrientation::Righty
+        })
+    }
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JogCommandType {
+    Cartesian,
+    Joint,
+}
+
+impl_u8_enum!(JogCommandType { Cartesian, Joint });
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JogCommand {
+    Idel,
+    ApDown,
+    AnDown,
+    BpDown,
+    BnDown,
+    CpDown,
+    CnDown,
+    DpDown,
+    DnDown,
+}
+
+impl_u8_enum!(JogCommand {
+    Idel,
+    ApDown,
+    AnDown,
+    BpDown,
+    BnDown,
+    CpDown,
+    CnDown,
+    DpDown,
+    DnDown
+});
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pose {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub r: f32,
+    pub joint_angles: [f32; 4],
+}
+
+impl Encode for Pose {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        [self.x, self.y, self.z, self.r].encode(buf);
+        self.joint_angles.encode(buf);
+    }
+}
+
+impl Decode for Pose {
+    const SIZE: usize = 32;
+
+    fn read(reader: &mut Reader) -> Result<Self, DobotError> {
+        Ok(Self {
+            x: reader.f32(),
+            y: reader.f32(),
+            z: reader.f32(),
+            r: reader.f32(),
+            joint_angles: reader.f32_array(),
+        })
+    }
+}
+
+/// HOME
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HomeParams {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub r: f32,
+}
+
+impl Encode for HomeParams {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        [self.x, self.y, self.z, self.r].encode(buf);
+    }
+}
+
+impl Decode for HomeParams {
+    const SIZE: usize = 16;
+
+    fn read(reader: &mut Reader) -> Result<Self, DobotError> {
+        Ok(Self {
+            x: reader.f32(),
+            y: reader.f32(),
+            z: reader.f32(),
+            r: reader.f32(),
+        })
+    }
+}
+
+/// JOG
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct JogJointParams {
+    pub velocity: [f32; 4],
+    pub acceleration: [f32; 4],
+}
+
+impl Encode for JogJointParams {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.velocity.encode(buf);
+        self.acceleration.encode(buf);
+    }
+}
+
+impl Decode for JogJointParams {
+    const SIZE: usize = 32;
+
+    fn read(reader: &mut Reader) -> Result<Self, DobotError> {
+        Ok(Self {
+            velocity: reader.f32_array(),
+            acceleration: reader.f32_array(),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct JogCoordinateParams {
+    pub velocity: [f32; 4],
+    pub acceleration: [f32; 4],
+}
+
+impl Encode for JogCoordinateParams {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.velocity.encode(buf);
+        self.acceleration.encode(buf);
+    }
+}
+
+impl Decode for JogCoordinateParams {
+    const SIZE: usize = 32;
+
+    fn read(reader: &mut Reader) -> Result<Self, DobotError> {
+        Ok(Self {
+            velocity: reader.f32_array(),
+            acceleration: reader.f32_array(),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct JogCommonParams {
+    pub velocity_ratio: f32,
+    pub acceleration_ratio: f32,
+}
+
+impl Encode for JogCommonParams {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.velocity_ratio.encode(buf);
+        self.acceleration_ratio.encode(buf);
+    }
+}
+
+impl Decode for JogCommonParams {
+    const SIZE: usize = 8;
+
+    fn read(reader: &mut Reader) -> Result<Self, DobotError> {
+        Ok(Self {
+            velocity_ratio: reader.f32(),
+            acceleration_ratio: reader.f32(),
+        })
+    }
+}
+
+/// PTP
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PtpJointParams {
+    pub velocity: [f32; 4],
+    pub acceleration: [f32; 4],
+}
+
+impl Encode for PtpJointParams {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.velocity.encode(buf);
+        self.acceleration.encode(buf);
+    }
+}
+
+impl Decode for PtpJointParams {
+    const SIZE: usize = 32;
+
+    fn read(reader: &mut Reader) -> Result<Self, DobotError> {
+        Ok(Self {
+            velocity: reader.f32_array(),
+            acceleration: reader.f32_array(),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PtpCoordinateParams {
+    pub xyz_velocity: f32,
+    pub r_velocity: f32,
+    pub xyz_acceleration: f32,
+    pub r_acceleration: f32,
+}
+
+impl Encode for PtpCoordinateParams {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.xyz_velocity.encode(buf);
+        self.r_velocity.encode(buf);
+        self.xyz_acceleration.encode(buf);
+        self.r_acceleration.encode(buf);
+    }
+}
+
+impl Decode for PtpCoordinateParams {
+    const SIZE: usize = 16;
+
+    fn read(reader: &mut Reader) -> Result<Self, DobotError> {
+        Ok(Self {
+            xyz_velocity: reader.f32(),
+            r_velocity: reader.f32(),
+            xyz_acceleration: reader.f32(),
+            r_acceleration: reader.f32(),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PtpJumpParams {
+    pub jump_height: f32,
+    pub z_limit: f32,
+    pub dummy: u32,
+}
+
+impl Encode for PtpJumpParams {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.jump_height.encode(buf);
+        self.z_limit.encode(buf);
+        self.dummy.encode(buf);
+    }
+}
+
+impl Decode for PtpJumpParams {
+    const SIZE: usize = 12;
+
+    fn read(reader: &mut Reader) -> Result<Self, DobotError> {
+        Ok(Self {
+            jump_height: reader.f32(),
+            z_limit: reader.f32(),
+            dummy: reader.u32(),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PtpCommonParams {
+    pub velocity_ratio: f32,
+    pub acceleration_ratio: f32,
+}
+
+impl Encode for PtpCommonParams {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.velocity_ratio.encode(buf);
+        self.acceleration_ratio.encode(buf);
+    }
+}
+
+impl Decode for PtpCommonParams {
+    const SIZE: usize = 8;
+
+    fn read(reader: &mut Reader) -> Result<Self, DobotError> {
+        Ok(Self {
+            velocity_ratio: reader.f32(),
+            acceleration_ratio: reader.f32(),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PtpCommand {
+    pub ptp_mode: PtpMode,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub r: f32,
+}
+
+impl Encode for PtpCommand {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.ptp_mode.encode(buf);
+        [self.x, self.y, self.z, self.r].encode(buf);
+    }
+}
+
+impl Decode for PtpCommand {
+    const SIZE: usize = 17;
+
+    fn read(reader: &mut Reader) -> Result<Self, DobotError> {
+        Ok(Self {
+            ptp_mode: PtpMode::read(reader)?,
+            x: reader.f32(),
+            y: reader.f32(),
+            z: reader.f32(),
+            r: reader.f32(),
+        })
+    }
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PtpMode {
+    JumpXyz,     // JUMP mode, (x,y,z,r) is the target point in Cartesian coordinate system
+    MovjXyz,     // MOVJ mode, (x,y,z,r) is the target point in Cartesian coordinate system
+    MovlXyz,     // MOVL mode, (x,y,z,r) is the target point in Cartesian coordinate system
+    JumpAngle,   // JUMP mode, (x,y,z,r) is the target point in Joint coordinate system
+    MovjAngle,   // MOVJ mode, (x,y,z,r) is the target point in Joint coordinate system
+    MovlAngle,   // MOVL mode, (x,y,z,r) is the target point in Joint coordinate system
+    MovjInc,     // MOVJ mode, (x,y,z,r) is the angle increment in Joint coordinate system
+    MovlInc, // MOVL mode, (x,y,z,r) is the Cartesian coordinate increment in Joint coordinate system
+    MovjXyzInc, // MOVJ mode, (x,y,z,r) is the Cartesian coordinate increment in Cartesian coordinate system
+    JumpMovlXyz, // JUMP mode, (x,y,z,r) is the Cartesian coordinate increment in Cartesian coordinate
+}
+
+impl_u8_enum!(PtpMode {
+    JumpXyz,
+    MovjXyz,
+    MovlXyz,
+    JumpAngle,
+    MovjAngle,
+    MovlAngle,
+    MovjInc,
+    MovlInc,
+    MovjXyzInc,
+    JumpMovlXyz,
+});
+
+/// CP
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CpParams {
+    pub plan_acceleration: f32,
+    pub junction_velocity: f32,
+    /// Acceleration, or the period in ms when `real_time_track` is set.
+    pub acceleration: f32,
+    pub real_time_track: bool,
+}
+
+impl Encode for CpParams {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.plan_acceleration.encode(buf);
+        self.junction_velocity.encode(buf);
+        self.acceleration.encode(buf);
+        (self.real_time_track as u8).encode(buf);
+    }
+}
+
+impl Decode for CpParams {
+    const SIZE: usize = 13;
+
+    fn read(reader: &mut Reader) -> Result<Self, DobotError> {
+        Ok(Self {
+            plan_acceleration: reader.f32(),
+            junction_velocity: reader.f32(),
+            acceleration: reader.f32(),
+            real_time_track: reader.u8() != 0,
+        })
+    }
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CpMode {
+    Relative, // (x,y,z) is the increment from the previous point
+    Absolute, // (x,y,z) is the target point in Cartesian coordinate system
+}
+
+impl_u8_enum!(CpMode { Relative, Absolute });
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CpCommand {
+    pub cp_mode: CpMode,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub velocity: f32,
+}
+
+impl Encode for CpCommand {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.cp_mode.encode(buf);
+        [self.x, self.y, self.z, self.velocity].encode(buf);
+    }
+}
+
+impl Decode for CpCommand {
+    const SIZE: usize = 17;
+
+    fn read(reader: &mut Reader) -> Result<Self, DobotError> {
+        Ok(Self {
+            cp_mode: CpMode::read(reader)?,
+            x: reader.f32(),
+            y: reader.f32(),
+            z: reader.f32(),
+            velocity: reader.f32(),
+        })
+    }
+}
+
+/// CP move with the laser on, for engraving.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CpLaserCommand {
+    pub cp_mode: CpMode,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    /// Laser power, 0 ~ 100.
+    pub power: f32,
+}
+
+impl Encode for CpLaserCommand {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.cp_mode.encode(buf);
+        [self.x, self.y, self.z, self.power].encode(buf);
+    }
+}
+
+impl Decode for CpLaserCommand {
+    const SIZE: usize = 17;
+
+    fn read(reader: &mut Reader) -> Result<Self, DobotError> {
+        Ok(Self {
+            cp_mode: CpMode::read(reader)?,
+            x: reader.f32(),
+            y: reader.f32(),
+            z: reader.f32(),
+            power: reader.f32(),
+        })
+    }
+}
+
+/// ARC
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ArcParams {
+    pub xyz_velocity: f32,
+    pub r_velocity: f32,
+    pub xyz_acceleration: f32,
+    pub r_acceleration: f32,
+}
+
+impl Encode for ArcParams {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.xyz_velocity.encode(buf);
+        self.r_velocity.encode(buf);
+        self.xyz_acceleration.encode(buf);
+        self.r_acceleration.encode(buf);
+    }
+}
+
+impl Decode for ArcParams {
+    const SIZE: usize = 16;
+
+    fn read(reader: &mut Reader) -> Result<Self, DobotError> {
+        Ok(Self {
+            xyz_velocity: reader.f32(),
+            r_velocity: reader.f32(),
+            xyz_acceleration: reader.f32(),
+            r_acceleration: reader.f32(),
+        })
+    }
+}
+
+/// Cartesian point of an ARC or circle command.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ArcPoint {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub r: f32,
+}
+
+impl Encode for ArcPoint {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        [self.x, self.y, self.z, self.r].encode(buf);
+    }
+}
+
+impl Decode for ArcPoint {
+    const SIZE: usize = 16;
+
+    fn read(reader: &mut Reader) -> Result<Self, DobotError> {
+        Ok(Self {
+            x: reader.f32(),
+            y: reader.f32(),
+            z: reader.f32(),
+            r: reader.f32(),
+        })
+    }
+}
+
+/// Arc from the current position through `via` to `to`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ArcCommand {
+    pub via: ArcPoint,
+    pub to: ArcPoint,
+}
+
+impl Encode for ArcCommand {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.via.encode(buf);
+        self.to.encode(buf);
+    }
+}
+
+impl Decode for ArcCommand {
+    const SIZE: usize = 32;
+
+    fn read(reader: &mut Reader) -> Result<Self, DobotError> {
+        Ok(Self {
+            via: ArcPoint::read(reader)?,
+            to: ArcPoint::read(reader)?,
+        })
+    }
+}
+
+/// Full circle through the current position, `via` and `to`, traced `count`
+/// times. The arm ends where it started.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CircleCommand {
+    pub via: ArcPoint,
+    pub to: ArcPoint,
+    pub count: u32,
+}
+
+impl Encode for CircleCommand {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.via.encode(buf);
+        self.to.encode(buf);
+        self.count.encode(buf);
+    }
+}
+
+impl Decode for CircleCommand {
+    const SIZE: usize = 36;
+
+    fn read(reader: &mut Reader) -> Result<Self, DobotError> {
+        Ok(Self {
+            via: ArcPoint::read(reader)?,
+            to: ArcPoint::read(reader)?,
+            count: reader.u32(),
+        })
+    }
+}
+
+/// END EFFECTOR
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EndEffectorParams {
+    /// Tool offset from the end of the arm in mm.
+    pub x_bias: f32,
+    pub y_bias: f32,
+    pub z_bias: f32,
+}
+
+impl Encode for EndEffectorParams {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.x_bias.encode(buf);
+        self.y_bias.encode(buf);
+        self.z_bias.encode(buf);
+    }
+}
+
+impl Decode for EndEffectorParams {
+    const SIZE: usize = 12;
+
+    fn read(reader: &mut Reader) -> Result<Self, DobotError> {
+        Ok(Self {
+            x_bias: reader.f32(),
+            y_bias: reader.f32(),
+            z_bias: reader.f32(),
+        })
+    }
+}
+
+/// State of the laser, suction cup or gripper, sent as (enable, on) bytes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EndEffectorState {
+    /// The end effector is not controlled.
+    Disabled,
+    /// Controlled and off: laser off, suction released, gripper open.
+    Off,
+    /// Controlled and on: laser on, sucking, gripper closed.
+    On,
+}
+
+impl Encode for EndEffectorState {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let (enable, on) = match self {
+            EndEffectorState::Disabled => (0, 0),
+            EndEffectorState::Off => (1, 0),
+            EndEffectorState::On => (1, 1),
+        };
+        buf.push(enable);
+        buf.push(on);
+    }
+}
+
+impl Decode for EndEffectorState {
+    const SIZE: usize = 2;
+
+    fn read(reader: &mut Reader) -> Result<Self, DobotError> {
+        let (enable, on) = (reader.u8(), reader.u8());
+        Ok(match (enable, on) {
+            (0, _) => EndEffectorState::Disabled,
+            (_, 0) => EndEffectorState::Off,
+            _ => EndEffectorState::On,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ptp_command_layout() {
+        let cmd = PtpCommand {
+            ptp_mode: PtpMode::MovlXyz,
+            x: 1.0,
+            y: -2.0,
+            z: 3.5,
+            r: 0.0,
+        };
+        let bytes = cmd.to_bytes();
+        assert_eq!(bytes.len(), PtpCommand::SIZE);
+        assert_eq!(bytes[0], 2);
+        assert_eq!(&bytes[1..5], &1.0f32.to_le_bytes());
+        assert_eq!(&bytes[5..9], &(-2.0f32).to_le_bytes());
+        assert_eq!(PtpCommand::decode(&bytes).unwrap(), cmd);
+    }
+
+    #[test]
+    fn round_trip() {
+        let pose = Pose {
+            x: 1.0,
+            y: 2.0,
+            z: 3.0,
+            r: 4.0,
+            joint_angles: [5.0, 6.0, 7.0, 8.0],
+        };
+        assert_eq!(Pose::decode(&pose.to_bytes()).unwrap(), pose);
+        let jump = PtpJumpParams {
+            jump_height: 20.0,
+            z_limit: 100.0,
+            dummy: 7,
+        };
+        assert_eq!(PtpJumpParams::decode(&jump.to_bytes()).unwrap(), jump);
+        let cp = CpParams {
+            plan_acceleration: 100.0,
+            junction_velocity: 50.0,
+            acceleration: 80.0,
+            real_time_track: true,
+        };
+        let bytes = cp.to_bytes();
+        assert_eq!(bytes.len(), CpParams::SIZE);
+        assert_eq!(bytes[12], 1);
+        assert_eq!(CpParams::decode(&bytes).unwrap(), cp);
+        let cmd = CpCommand {
+            cp_mode: CpMode::Absolute,
+            x: 250.0,
+            y: 0.0,
+            z: 50.0,
+            velocity: 20.0,
+        };
+        assert_eq!(cmd.to_bytes()[0], 1);
+        assert_eq!(CpCommand::decode(&cmd.to_bytes()).unwrap(), cmd);
+        let point = |x| ArcPoint {
+            x,
+            y: 0.0,
+            z: 50.0,
+            r: 0.0,
+        };
+        let circle = CircleCommand {
+            via: point(250.0),
+            to: point(300.0),
+            count: 2,
+        };
+        let bytes = circle.to_bytes();
+        assert_eq!(bytes.len(), CircleCommand::SIZE);
+        assert_eq!(&bytes[16..20], &300.0f32.to_le_bytes());
+        assert_eq!(&bytes[32..], &[2, 0, 0, 0]);
+        assert_eq!(CircleCommand::decode(&bytes).unwrap(), circle);
+    }
+
+    #[test]
+    fn end_effector_layout() {
+        assert_eq!(EndEffectorState::On.to_bytes(), vec![1, 1]);
+        assert_eq!(EndEffectorState::Off.to_bytes(), vec![1, 0]);
+        assert_eq!(
+            EndEffectorState::decode(&[0, 1]).unwrap(),
+            EndEffectorState::Disabled
+        );
+        let params = EndEffectorParams {
+            x_bias: 60.0,
+            y_bias: 0.0,
+            z_bias: -10.0,
+        };
+        let bytes = params.to_bytes();
+        assert_eq!(bytes.len(), EndEffectorParams::SIZE);
+        assert_eq!(EndEffectorParams::decode(&bytes).unwrap(), params);
+    }
+
+    #[test]
+    fn io_pin() {
+        assert_eq!(IoPin::new(18).unwrap().address(), 18);
+        assert!(matches!(IoPin::new(0), Err(DobotError::InvalidValue(_))));
+        assert!(IoPin::try_from(23).is_err());
+        assert!(IoPin::decode(&[22]).is_ok());
+        assert_eq!(IoFunction::try_from(4).unwrap(), IoFunction::Adc);
+    }
+
+    #[test]
+    fn malformed_params() {
+        assert!(matches!(
+            Pose::decode(&[0; 31]),
+            Err(DobotError::PayloadLength {
+                expected: 32,
+                actual: 31
+            })
+        ));
+        let mut bytes = vec![42];
+        bytes.extend(&[0; 16]);
+        assert!(matches!(
+            PtpCommand::decode(&bytes),
+            Err(DobotError::InvalidValue(_))
+        ));
+        assert_eq!(JogCommand::try_from(6).unwrap(), JogCommand::CnDown);
+    }
+}
@@ -1,5 +1,5 @@
-use failure::format_err;
-use failure::Error;
+use crate::dissect::Dissection;
+use crate::error::DobotError;
 use std::fmt;
 use std::num::Wrapping;
 
@@ -20,7 +20,7 @@ impl From<u8> for ReadWrite {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct PayloadStruct {
     pub id: u8,
     pub rw: ReadWrite,
@@ -32,15 +32,22 @@ impl fmt::Debug for PayloadStruct {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "id: {}, rw: {:?}, queued: {}, params:{}",
+            "id: {}, rw: {:?}, queued: {}, params: {:02X?} ({})",
             self.id,
             self.rw,
             self.is_queued,
-            String::from_utf8(self.params.clone()).unwrap()
+            self.params,
+            Dissection(self)
         )
     }
 }
 
+impl fmt::Display for PayloadStruct {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Dissection(self).fmt(f)
+    }
+}
+
 fn calc_complement(val: u8) -> u8 {
     (256 - val as usize) as u8
 }
@@ -62,19 +69,24 @@ fn calc_checksum(vals: &[u8]) -> u8 {
     calc_complement(calc_sum(vals))
 }
 
-pub(crate) fn check_header_and_get_payload_size(packet: &[u8]) -> Result<usize, Error> {
+pub(crate) fn check_header_and_get_payload_size(packet: &[u8]) -> Result<usize, DobotError> {
+    if packet.len() < 3 {
+        return Err(DobotError::Framing(format!(
+            "packet is too short to contain a header: {} bytes",
+            packet.len()
+        )));
+    }
     if packet[0] != 0xAA || packet[1] != 0xAA {
-        return Err(format_err!(
+        return Err(DobotError::Framing(format!(
             "header is invalid [{}], [{}]",
-            packet[0],
-            packet[1]
-        ));
+            packet[0], packet[1]
+        )));
     }
     if packet[2] < 2 {
-        return Err(format_err!(
+        return Err(DobotError::Framing(format!(
             "packet invalid, packet[2] must be greater than 2 but it is {}",
             packet[2]
-        ));
+        )));
     }
     Ok(packet[2] as usize - 2)
 }
@@ -92,7 +104,7 @@ impl PayloadStruct {
 
     pub fn with_id(id: u8) -> Self {
         PayloadStruct {
-            id: id,
+            id,
             rw: ReadWrite::READ,
             is_queued: false,
             params: vec![],
@@ -132,20 +144,20 @@ impl PayloadStruct {
         packet
     }
 
-    pub fn deserialize(packet: &[u8]) -> Result<Self, Error> {
+    pub fn deserialize(packet: &[u8]) -> Result<Self, DobotError> {
         let mut data = PayloadStruct::new();
         let len = check_header_and_get_payload_size(packet)?;
         // header(0xAA, 0xAA=2) + len(1)
         const CHECKSUM_START: usize = 3;
         // + id(1) + ctrl(1)
         const PAYLODAD_START: usize = CHECKSUM_START + 2;
-        let payload_end = (PAYLODAD_START + len) as usize;
-        if packet.len() < (payload_end + 1) as usize {
-            return Err(format_err!(
+        let payload_end = PAYLODAD_START + len;
+        if packet.len() < payload_end + 1 {
+            return Err(DobotError::Framing(format!(
                 "packet length should be {}, but actual packet size {}",
                 payload_end + 1,
                 packet.len()
-            ));
+            )));
         }
         data.id = packet[3];
         data.rw = (0b1 & packet[4]).into();
@@ -153,11 +165,10 @@ impl PayloadStruct {
         data.params = packet[PAYLODAD_START..payload_end].to_vec();
         let sum = calc_checksum(&packet[CHECKSUM_START..payload_end]);
         if sum != packet[payload_end] {
-            return Err(format_err!(
-                "checksum error: sum should be {}, but calculation result is {}",
-                packet[payload_end],
-                sum
-            ));
+            return Err(DobotError::Checksum {
+                expected: packet[payload_end],
+                actual: sum,
+            });
         }
         Ok(data)
     }
@@ -174,7 +185,7 @@ mod tests {
         let buf = p1.serialize();
         let p2 = PayloadStruct::deserialize(&buf).unwrap();
         assert_eq!(p2.id, 2);
-        assert_eq!(p2.is_queued, true);
+        assert!(p2.is_queued);
         assert_eq!(p2.rw, ReadWrite::READ);
         assert_eq!(p2.params.len(), 0);
     }
@@ -189,7 +200,7 @@ mod tests {
         let buf = p1.serialize();
         let p2 = PayloadStruct::deserialize(&buf).unwrap();
         assert_eq!(p2.id, 3);
-        assert_eq!(p2.is_queued, false);
+        assert!(!p2.is_queued);
         assert_eq!(p2.rw, ReadWrite::WRITE);
         assert_eq!(p2.params.len(), 4);
         assert_eq!(p2.params[0], 97);
@@ -198,6 +209,16 @@ mod tests {
         assert_eq!(p2.params[3], 100);
         println!("{:?}", p2);
     }
+
+    #[test]
+    fn debug_binary_params() {
+        let p = PayloadStruct::with_id(200).set_params(vec![0xff, 0x00]);
+        assert_eq!(
+            format!("{:?}", p),
+            "id: 200, rw: READ, queued: false, params: [FF, 00] (GetCmd200 [FF 00])"
+        );
+        assert_eq!(PayloadStruct::with_id(10).to_string(), "GetPose");
+    }
     #[test]
     fn test_queued1() {
         let p = PayloadStruct::with_id(1).set_queued();
@@ -211,9 +232,28 @@ mod tests {
         assert_eq!(buf[4], 3);
     }
 
+    #[test]
+    fn deserialize_errors() {
+        let mut buf = PayloadStruct::with_id(10).serialize();
+        assert!(matches!(
+            PayloadStruct::deserialize(&buf[..2]),
+            Err(DobotError::Framing(_))
+        ));
+        assert!(matches!(
+            PayloadStruct::deserialize(&buf[..4]),
+            Err(DobotError::Framing(_))
+        ));
+        let last = buf.len() - 1;
+        buf[last] += 1;
+        assert!(matches!(
+            PayloadStruct::deserialize(&buf),
+            Err(DobotError::Checksum { .. })
+        ));
+    }
+
     #[test]
     fn test_calc_sum() {
-        assert_eq!(calc_sum(&vec![10, 100]), 110);
-        assert_eq!(calc_sum(&vec![200, 200]), 144);
+        assert_eq!(calc_sum(&[10, 100]), 110);
+        assert_eq!(calc_sum(&[200, 200]), 144);
     }
 }
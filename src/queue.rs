@@ -0,0 +1,212 @@
+use crate::alarm::AlarmWatch;
+use crate::client::{DobotClient, QueuedHandle};
+use crate::error::DobotError;
+use crate::params::*;
+use crate::traits::Device;
+use std::time::{Duration, Instant};
+
+/// A command that can be put into the device command queue.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QueuedCommand {
+    Ptp(PtpCommand),
+    Cp(CpCommand),
+    CpLaser(CpLaserCommand),
+    Arc(ArcCommand),
+    Circle(CircleCommand),
+    EndEffectorParams(EndEffectorParams),
+    Laser(EndEffectorState),
+    SuctionCup(EndEffectorState),
+    Gripper(EndEffectorState),
+    IoMultiplexing(IoPin, IoFunction),
+    Iodo(IoPin, IoLevel),
+    IoPwm(IoPin, f32, f32),
+    Wait(u32),
+    ArmOrientation(ArmOrientation),
+    Home,
+}
+
+impl QueuedCommand {
+    /// Sends the command with the matching `*_queued` method of `client`.
+    pub fn send<T: Device>(&self, client: &mut DobotClient<T>) -> Result<QueuedHandle, DobotError> {
+        match *self {
+            QueuedCommand::Ptp(command) => client.set_ptp_command_queued(command),
+            QueuedCommand::Cp(command) => client.set_cp_command_queued(command),
+            QueuedCommand::CpLaser(command) => client.set_cp_laser_command_queued(command),
+            QueuedCommand::Arc(command) => client.set_arc_command_queued(command),
+            QueuedCommand::Circle(command) => client.set_circle_command_queued(command),
+            QueuedCommand::EndEffectorParams(params) => {
+                client.set_end_effector_params_queued(params)
+            }
+            QueuedCommand::Laser(state) => client.set_end_effector_laser_queued(state),
+            QueuedCommand::SuctionCup(state) => client.set_end_effector_suction_cup_queued(state),
+            QueuedCommand::Gripper(state) => client.set_end_effector_gripper_queued(state),
+            QueuedCommand::IoMultiplexing(pin, function) => {
+                client.set_io_multiplexing_queued(pin, function)
+            }
+            QueuedCommand::Iodo(pin, level) => client.set_iodo_queued(pin, level),
+            QueuedCommand::IoPwm(pin, frequency, duty_cycle) => {
+                client.set_io_pwm_queued(pin, frequency, duty_cycle)
+            }
+            QueuedCommand::Wait(wait_ms) => client.set_wait_command_queued(wait_ms),
+            QueuedCommand::ArmOrientation(l_r) => client.set_arm_orientation_queued(l_r),
+            QueuedCommand::Home => client.set_home_cmd_queued(),
+        }
+    }
+}
+
+/// Buffers a program of arbitrary length and feeds it to the device command
+/// queue only while the device reports free space.
+///
+/// Entries are numbered locally in push order; once sent, each entry is
+/// mapped to the index the device assigned to it.
+#[derive(Clone, Debug, Default)]
+pub struct QueueManager {
+    program: Vec<QueuedCommand>,
+    handles: Vec<QueuedHandle>,
+}
+
+impl QueueManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a command to the local program and returns its entry number.
+    pub fn push(&mut self, command: QueuedCommand) -> usize {
+        self.program.push(command);
+        self.program.len() - 1
+    }
+
+    pub fn len(&self) -> usize {
+        self.program.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.program.is_empty()
+    }
+
+    /// Number of entries not sent to the device yet.
+    pub fn pending(&self) -> usize {
+        self.program.len() - self.handles.len()
+    }
+
+    /// Device queue handle of `entry`, if it has been sent.
+    pub fn handle(&self, entry: usize) -> Option<QueuedHandle> {
+        self.handles.get(entry).cloned()
+    }
+
+    /// Local entry that was assigned the device queue `index`.
+    pub fn entry_for_index(&self, index: u64) -> Option<usize> {
+        self.handles.iter().position(|h| h.index() == index)
+    }
+
+    /// Number of sent entries the device has finished executing.
+    pub fn executed<T: Device>(&self, client: &mut DobotClient<T>) -> Result<usize, DobotError> {
+        let current = client.get_queued_command_current_index()?;
+        Ok(self
+            .handles
+            .iter()
+            .take_while(|h| h.index() <= current)
+            .count())
+    }
+
+    /// Sends as many pending entries as the device queue has room for and
+    /// returns how many were sent.
+    pub fn feed<T: Device>(&mut self, client: &mut DobotClient<T>) -> Result<usize, DobotError> {
+        let space = client.get_queued_command_left_space()? as usize;
+        let count = space.min(self.pending());
+        for _ in 0..count {
+            let command = self.program[self.handles.len()];
+            let handle = command.send(client)?;
+            self.handles.push(handle);
+        }
+        Ok(count)
+    }
+
+    /// Feeds the whole program and blocks until the device has executed it.
+    ///
+    /// The queue must be executing (`set_queued_command_start_exec`),
+    /// otherwise this runs into `timeout`. Alarms abort the run with
+    /// `DobotError::Alarm`, as in `DobotClient::wait_for_index`.
+    pub fn run<T: Device>(
+        &mut self,
+        client: &mut DobotClient<T>,
+        timeout: Duration,
+        poll_interval: Duration,
+    ) -> Result<(), DobotError> {
+        let start = Instant::now();
+        let mut watch = AlarmWatch::default();
+        watch.check(client.get_alarm_state()?)?;
+        loop {
+            self.feed(client)?;
+            if self.pending() == 0 {
+                break;
+            }
+            watch.check(client.get_alarm_state()?)?;
+            if start.elapsed() >= timeout {
+                return Err(DobotError::Timeout);
+            }
+            std::thread::sleep(poll_interval);
+        }
+        match self.handles.last() {
+            Some(last) => {
+                let left = timeout
+                    .checked_sub(start.elapsed())
+                    .unwrap_or_else(|| Duration::from_secs(0));
+                last.wait(client, left, poll_interval)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::SimulatedDevice;
+
+    #[test]
+    fn feeds_long_program_through_small_queue() {
+        let mut dobot = DobotClient::new(SimulatedDevice::new());
+        let mut manager = QueueManager::new();
+        for i in 0..100 {
+            let pin = IoPin::new((i % 22) as u8 + 1).unwrap();
+            manager.push(QueuedCommand::Iodo(pin, IoLevel::High));
+            manager.push(QueuedCommand::Wait(10));
+        }
+        // the simulator holds 32 commands
+        assert_eq!(manager.feed(&mut dobot).unwrap(), 32);
+        assert_eq!(manager.feed(&mut dobot).unwrap(), 0);
+        assert_eq!(manager.pending(), 168);
+        assert_eq!(manager.handle(31).unwrap().index(), 32);
+        assert_eq!(manager.entry_for_index(32), Some(31));
+        assert!(manager.handle(32).is_none());
+
+        dobot.set_queued_command_start_exec().unwrap();
+        manager
+            .run(&mut dobot, Duration::from_secs(5), Duration::from_millis(0))
+            .unwrap();
+        assert_eq!(manager.pending(), 0);
+        assert_eq!(manager.executed(&mut dobot).unwrap(), 200);
+        assert_eq!(manager.handle(199).unwrap().index(), 200);
+        assert_eq!(dobot.device().io_output(22), Some(1));
+    }
+
+    #[test]
+    fn run_stops_on_alarm() {
+        let mut dobot = DobotClient::new(SimulatedDevice::new());
+        let mut manager = QueueManager::new();
+        manager.push(QueuedCommand::Ptp(PtpCommand {
+            ptp_mode: PtpMode::MovjXyz,
+            x: 1000.0,
+            y: 0.0,
+            z: 0.0,
+            r: 0.0,
+        }));
+        manager.push(QueuedCommand::Wait(1000));
+        dobot.set_queued_command_start_exec().unwrap();
+        assert!(matches!(
+            manager.run(&mut dobot, Duration::from_secs(5), Duration::from_millis(0)),
+            Err(DobotError::Alarm(_))
+        ));
+    }
+}
@@ -0,0 +1,812 @@
+use crate::alarm::AlarmSet;
+use crate::client::{check_id, QueuedHandle};
+use crate::codec::{Decode, Encode};
+use crate::command::CommandId;
+use crate::error::DobotError;
+use crate::params::*;
+use crate::protocol::{PayloadStruct, ReadWrite};
+use crate::safety::SafetyEnvelope;
+
+/// What `DobotClient` and `AsyncDobotClient` remember between requests.
+#[derive(Default)]
+pub(crate) struct ClientState {
+    pub safety: Option<SafetyEnvelope>,
+    // last known or commanded joint angles, incremental motions start here
+    pub last_joints: Option<[f32; 4]>,
+    // index of the last command queued through this client
+    pub last_queued: Option<u64>,
+    pub orientation: Option<ArmOrientation>,
+}
+
+/// Where a motion starts, read by the client when a safety envelope is set.
+pub(crate) struct Start {
+    pub joints: [f32; 4],
+    pub orientation: ArmOrientation,
+}
+
+type Finish<R> = Box<dyn FnOnce(&mut ClientState, Vec<u8>) -> Result<R, DobotError> + Send>;
+
+/// Request of a client method and how its response becomes the result.
+///
+/// Built by the functions below, which both clients share, so that they
+/// only have to send the packet.
+pub(crate) struct Request<R> {
+    packet: PayloadStruct,
+    id: CommandId,
+    // state change taking effect even if the request fails
+    before: Option<fn(&mut ClientState)>,
+    finish: Finish<R>,
+}
+
+/// Second half of a `Request`, waiting for the response.
+pub(crate) struct Pending<R> {
+    id: CommandId,
+    finish: Finish<R>,
+}
+
+impl<R: 'static> Request<R> {
+    fn new<F>(
+        id: CommandId,
+        rw: ReadWrite,
+        queued: bool,
+        params: Vec<u8>,
+        finish: F,
+    ) -> Result<Self, DobotError>
+    where
+        F: FnOnce(&mut ClientState, Vec<u8>) -> Result<R, DobotError> + Send + 'static,
+    {
+        Ok(Self {
+            packet: id.request(rw, queued, params)?,
+            id,
+            before: None,
+            finish: Box::new(finish),
+        })
+    }
+
+    /// Read with parameters, e.g. the IO address to read.
+    fn query<F>(id: CommandId, params: Vec<u8>, decode: F) -> Result<Self, DobotError>
+    where
+        F: FnOnce(Vec<u8>) -> Result<R, DobotError> + Send + 'static,
+    {
+        Self::new(id, ReadWrite::READ, false, params, |_, params| {
+            decode(params)
+        })
+    }
+
+    fn before(mut self, f: fn(&mut ClientState)) -> Self {
+        self.before = Some(f);
+        self
+    }
+
+    /// Runs `f` once the response has been decoded.
+    fn then<F>(self, f: F) -> Self
+    where
+        F: FnOnce(&mut ClientState, &R) + Send + 'static,
+    {
+        let finish = self.finish;
+        Self {
+            finish: Box::new(move |state, params| {
+                let result = finish(state, params)?;
+                f(state, &result);
+                Ok(result)
+            }),
+            ..self
+        }
+    }
+
+    /// Makes `target` the start of the following incremental motions.
+    fn moves_to(self, target: Option<[f32; 4]>) -> Self {
+        self.then(move |state, _| state.last_joints = target)
+    }
+
+    /// Applies the changes to `state` that come before sending and returns
+    /// the packet to send.
+    pub fn start(self, state: &mut ClientState) -> (PayloadStruct, Pending<R>) {
+        if let Some(before) = self.before {
+            before(state);
+        }
+        let pending = Pending {
+            id: self.id,
+            finish: self.finish,
+        };
+        (self.packet, pending)
+    }
+}
+
+impl<R> Pending<R> {
+    pub fn finish(self, state: &mut ClientState, response: PayloadStruct) -> Result<R, DobotError> {
+        check_id(&response, self.id)?;
+        (self.finish)(state, response.params)
+    }
+}
+
+fn read<T: Decode + 'static>(id: CommandId) -> Result<Request<T>, DobotError> {
+    Request::query(id, vec![], |params| T::decode(&params))
+}
+
+/// Reads IO `pin` and decodes the value after the echoed address.
+fn read_io<T: Decode + 'static>(id: CommandId, pin: IoPin) -> Result<Request<T>, DobotError> {
+    Request::query(id, pin.to_bytes(), move |params| {
+        if params.first() != Some(&pin.address()) {
+            return Err(DobotError::InvalidValue(format!(
+                "response to IO address {} is for {:?}",
+                pin.address(),
+                params.first()
+            )));
+        }
+        T::decode(&params[1..])
+    })
+}
+
+fn write(id: CommandId, params: Vec<u8>) -> Result<Request<()>, DobotError> {
+    Request::new(id, ReadWrite::WRITE, false, params, |_, _| Ok(()))
+}
+
+fn write_queued(id: CommandId, params: Vec<u8>) -> Result<Request<QueuedHandle>, DobotError> {
+    Request::new(id, ReadWrite::WRITE, true, params, |state, params| {
+        let index = u64::decode(&params)?;
+        if state.safety.is_some() {
+            state.last_queued = Some(index);
+        }
+        Ok(QueuedHandle::new(index))
+    })
+}
+
+fn forget_position(state: &mut ClientState) {
+    state.last_joints = None;
+}
+
+fn forget_queue(state: &mut ClientState) {
+    state.last_joints = None;
+    state.last_queued = None;
+}
+
+/// Checks `command` against the envelope and returns its target.
+fn check_ptp(
+    state: &ClientState,
+    start: Option<Start>,
+    command: &PtpCommand,
+) -> Result<Option<[f32; 4]>, DobotError> {
+    match (&state.safety, start) {
+        (Some(envelope), Some(start)) => envelope
+            .check_ptp(command, &start.joints, start.orientation)
+            .map(Some),
+        _ => Ok(None),
+    }
+}
+
+fn check_cp(
+    state: &ClientState,
+    start: Option<Start>,
+    mode: CpMode,
+    point: [f32; 3],
+) -> Result<Option<[f32; 4]>, DobotError> {
+    match (&state.safety, start) {
+        (Some(envelope), Some(start)) => envelope
+            .check_cp(mode, &point, &start.joints, start.orientation)
+            .map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Checks the path of an ARC or circle, returns the end.
+fn check_arc(
+    state: &ClientState,
+    start: Option<Start>,
+    via: &ArcPoint,
+    to: &ArcPoint,
+    circle: bool,
+) -> Result<Option<[f32; 4]>, DobotError> {
+    match (&state.safety, start) {
+        (Some(envelope), Some(start)) => envelope
+            .check_arc(via, to, circle, &start.joints, start.orientation)
+            .map(Some),
+        _ => Ok(None),
+    }
+}
+
+pub fn get_device_sn() -> Result<Request<String>, DobotError> {
+    Request::query(CommandId::DeviceSn, vec![], |params| {
+        Ok(String::from_utf8(params)?)
+    })
+}
+
+pub fn get_alarm_state() -> Result<Request<AlarmSet>, DobotError> {
+    Request::query(CommandId::AlarmsState, vec![], |params| {
+        Ok(AlarmSet::from_bytes(&params))
+    })
+}
+
+pub fn clear_all_alarm_state() -> Result<Request<()>, DobotError> {
+    write(CommandId::AlarmsState, vec![])
+}
+
+/// Reads the pose. `idle` tells whether the queue was done before, only
+/// then the pose is where the following motions start.
+pub fn get_pose(idle: bool) -> Result<Request<Pose>, DobotError> {
+    Ok(read(CommandId::Pose)?.then(move |state, pose: &Pose| {
+        if idle {
+            state.last_joints = Some(pose.joint_angles);
+        }
+    }))
+}
+
+pub fn get_home_params() -> Result<Request<HomeParams>, DobotError> {
+    read(CommandId::HomeParams)
+}
+
+pub fn set_home_params(params: HomeParams) -> Result<Request<()>, DobotError> {
+    write(CommandId::HomeParams, params.to_bytes())
+}
+
+pub fn set_home_params_queued(params: HomeParams) -> Result<Request<QueuedHandle>, DobotError> {
+    write_queued(CommandId::HomeParams, params.to_bytes())
+}
+
+pub fn set_home_cmd() -> Result<Request<()>, DobotError> {
+    // the only parameter is a reserved u32
+    Ok(write(CommandId::HomeCmd, 0u32.to_bytes())?.before(forget_position))
+}
+
+pub fn set_home_cmd_queued() -> Result<Request<QueuedHandle>, DobotError> {
+    Ok(write_queued(CommandId::HomeCmd, 0u32.to_bytes())?.before(forget_position))
+}
+
+pub fn get_jog_joint_params() -> Result<Request<JogJointParams>, DobotError> {
+    read(CommandId::JogJointParams)
+}
+
+pub fn set_jog_joint_params(params: JogJointParams) -> Result<Request<()>, DobotError> {
+    write(CommandId::JogJointParams, params.to_bytes())
+}
+
+pub fn set_jog_joint_params_queued(
+    params: JogJointParams,
+) -> Result<Request<QueuedHandle>, DobotError> {
+    write_queued(CommandId::JogJointParams, params.to_bytes())
+}
+
+pub fn get_jog_coordinate_params() -> Result<Request<JogCoordinateParams>, DobotError> {
+    read(CommandId::JogCoordinateParams)
+}
+
+pub fn set_jog_coordinate_params(params: JogCoordinateParams) -> Result<Request<()>, DobotError> {
+    write(CommandId::JogCoordinateParams, params.to_bytes())
+}
+
+pub fn set_jog_coordinate_params_queued(
+    params: JogCoordinateParams,
+) -> Result<Request<QueuedHandle>, DobotError> {
+    write_queued(CommandId::JogCoordinateParams, params.to_bytes())
+}
+
+pub fn get_jog_common_params() -> Result<Request<JogCommonParams>, DobotError> {
+    read(CommandId::JogCommonParams)
+}
+
+pub fn set_jog_common_params(params: JogCommonParams) -> Result<Request<()>, DobotError> {
+    write(CommandId::JogCommonParams, params.to_bytes())
+}
+
+pub fn set_jog_common_params_queued(
+    params: JogCommonParams,
+) -> Result<Request<QueuedHandle>, DobotError> {
+    write_queued(CommandId::JogCommonParams, params.to_bytes())
+}
+
+/// `start` is only needed to check a jog that is not `Idel`.
+pub fn set_jog_command(
+    state: &ClientState,
+    start: Option<Start>,
+    mode: JogCommandType,
+    cmd: JogCommand,
+) -> Result<Request<()>, DobotError> {
+    if let (Some(envelope), Some(start)) = (&state.safety, start) {
+        envelope.check_jog(mode, cmd, &start.joints, start.orientation)?;
+    }
+    Ok(write(CommandId::JogCmd, vec![mode as u8, cmd as u8])?.before(forget_position))
+}
+
+pub fn set_jog_command_queued(
+    state: &ClientState,
+    start: Option<Start>,
+    mode: JogCommandType,
+    cmd: JogCommand,
+) -> Result<Request<QueuedHandle>, DobotError> {
+    if let (Some(envelope), Some(start)) = (&state.safety, start) {
+        envelope.check_jog(mode, cmd, &start.joints, start.orientation)?;
+    }
+    Ok(write_queued(CommandId::JogCmd, vec![mode as u8, cmd as u8])?.before(forget_position))
+}
+
+pub fn get_ptp_joint_params() -> Result<Request<PtpJointParams>, DobotError> {
+    read(CommandId::PtpJointParams)
+}
+
+pub fn set_ptp_joint_params(params: PtpJointParams) -> Result<Request<()>, DobotError> {
+    write(CommandId::PtpJointParams, params.to_bytes())
+}
+
+pub fn set_ptp_joint_params_queued(
+    params: PtpJointParams,
+) -> Result<Request<QueuedHandle>, DobotError> {
+    write_queued(CommandId::PtpJointParams, params.to_bytes())
+}
+
+pub fn get_ptp_coordinate_params() -> Result<Request<PtpCoordinateParams>, DobotError> {
+    read(CommandId::PtpCoordinateParams)
+}
+
+pub fn set_ptp_coordinate_params(params: PtpCoordinateParams) -> Result<Request<()>, DobotError> {
+    write(CommandId::PtpCoordinateParams, params.to_bytes())
+}
+
+pub fn set_ptp_coordinate_params_queued(
+    params: PtpCoordinateParams,
+) -> Result<Request<QueuedHandle>, DobotError> {
+    write_queued(CommandId::PtpCoordinateParams, params.to_bytes())
+}
+
+pub fn get_ptp_jump_params() -> Result<Request<PtpJumpParams>, DobotError> {
+    read(CommandId::PtpJumpParams)
+}
+
+pub fn set_ptp_jump_params(params: PtpJumpParams) -> Result<Request<()>, DobotError> {
+    write(CommandId::PtpJumpParams, params.to_bytes())
+}
+
+pub fn set_ptp_jump_params_queued(
+    params: PtpJumpParams,
+) -> Result<Request<QueuedHandle>, DobotError> {
+    write_queued(CommandId::PtpJumpParams, params.to_bytes())
+}
+
+pub fn get_ptp_common_params() -> Result<Request<PtpCommonParams>, DobotError> {
+    read(CommandId::PtpCommonParams)
+}
+
+pub fn set_ptp_common_params(params: PtpCommonParams) -> Result<Request<()>, DobotError> {
+    write(CommandId::PtpCommonParams, params.to_bytes())
+}
+
+pub fn set_ptp_common_params_queued(
+    params: PtpCommonParams,
+) -> Result<Request<QueuedHandle>, DobotError> {
+    write_queued(CommandId::PtpCommonParams, params.to_bytes())
+}
+
+pub fn set_ptp_command(
+    state: &ClientState,
+    start: Option<Start>,
+    command: PtpCommand,
+) -> Result<Request<()>, DobotError> {
+    let target = check_ptp(state, start, &command)?;
+    Ok(write(CommandId::PtpCmd, command.to_bytes())?.moves_to(target))
+}
+
+pub fn set_ptp_command_queued(
+    state: &ClientState,
+    start: Option<Start>,
+    command: PtpCommand,
+) -> Result<Request<QueuedHandle>, DobotError> {
+    let target = check_ptp(state, start, &command)?;
+    Ok(write_queued(CommandId::PtpCmd, command.to_bytes())?.moves_to(target))
+}
+
+pub fn get_cp_params() -> Result<Request<CpParams>, DobotError> {
+    read(CommandId::CpParams)
+}
+
+pub fn set_cp_params(params: CpParams) -> Result<Request<()>, DobotError> {
+    write(CommandId::CpParams, params.to_bytes())
+}
+
+pub fn set_cp_params_queued(params: CpParams) -> Result<Request<QueuedHandle>, DobotError> {
+    write_queued(CommandId::CpParams, params.to_bytes())
+}
+
+pub fn set_cp_command(
+    state: &ClientState,
+    start: Option<Start>,
+    command: CpCommand,
+) -> Result<Request<()>, DobotError> {
+    let target = check_cp(
+        state,
+        start,
+        command.cp_mode,
+        [command.x, command.y, command.z],
+    )?;
+    Ok(write(CommandId::CpCmd, command.to_bytes())?.moves_to(target))
+}
+
+pub fn set_cp_command_queued(
+    state: &ClientState,
+    start: Option<Start>,
+    command: CpCommand,
+) -> Result<Request<QueuedHandle>, DobotError> {
+    let target = check_cp(
+        state,
+        start,
+        command.cp_mode,
+        [command.x, command.y, command.z],
+    )?;
+    Ok(write_queued(CommandId::CpCmd, command.to_bytes())?.moves_to(target))
+}
+
+pub fn set_cp_laser_command(
+    state: &ClientState,
+    start: Option<Start>,
+    command: CpLaserCommand,
+) -> Result<Request<()>, DobotError> {
+    let target = check_cp(
+        state,
+        start,
+        command.cp_mode,
+        [command.x, command.y, command.z],
+    )?;
+    Ok(write(CommandId::CpLeCmd, command.to_bytes())?.moves_to(target))
+}
+
+pub fn set_cp_laser_command_queued(
+    state: &ClientState,
+    start: Option<Start>,
+    command: CpLaserCommand,
+) -> Result<Request<QueuedHandle>, DobotError> {
+    let target = check_cp(
+        state,
+        start,
+        command.cp_mode,
+        [command.x, command.y, command.z],
+    )?;
+    Ok(write_queued(CommandId::CpLeCmd, command.to_bytes())?.moves_to(target))
+}
+
+pub fn get_arc_params() -> Result<Request<ArcParams>, DobotError> {
+    read(CommandId::ArcParams)
+}
+
+pub fn set_arc_params(params: ArcParams) -> Result<Request<()>, DobotError> {
+    write(CommandId::ArcParams, params.to_bytes())
+}
+
+pub fn set_arc_params_queued(params: ArcParams) -> Result<Request<QueuedHandle>, DobotError> {
+    write_queued(CommandId::ArcParams, params.to_bytes())
+}
+
+pub fn set_arc_command(
+    state: &ClientState,
+    start: Option<Start>,
+    command: ArcCommand,
+) -> Result<Request<()>, DobotError> {
+    let target = check_arc(state, start, &command.via, &command.to, false)?;
+    Ok(write(CommandId::ArcCmd, command.to_bytes())?.moves_to(target))
+}
+
+pub fn set_arc_command_queued(
+    state: &ClientState,
+    start: Option<Start>,
+    command: ArcCommand,
+) -> Result<Request<QueuedHandle>, DobotError> {
+    let target = check_arc(state, start, &command.via, &command.to, false)?;
+    Ok(write_queued(CommandId::ArcCmd, command.to_bytes())?.moves_to(target))
+}
+
+// a circle ends where it started, so the last known pose stays valid
+pub fn set_circle_command(
+    state: &ClientState,
+    start: Option<Start>,
+    command: CircleCommand,
+) -> Result<Request<()>, DobotError> {
+    check_arc(state, start, &command.via, &command.to, true)?;
+    write(CommandId::CircleCmd, command.to_bytes())
+}
+
+pub fn set_circle_command_queued(
+    state: &ClientState,
+    start: Option<Start>,
+    command: CircleCommand,
+) -> Result<Request<QueuedHandle>, DobotError> {
+    check_arc(state, start, &command.via, &command.to, true)?;
+    write_queued(CommandId::CircleCmd, command.to_bytes())
+}
+
+pub fn get_end_effector_params() -> Result<Request<EndEffectorParams>, DobotError> {
+    read(CommandId::EndEffectorParams)
+}
+
+pub fn set_end_effector_params(params: EndEffectorParams) -> Result<Request<()>, DobotError> {
+    write(CommandId::EndEffectorParams, params.to_bytes())
+}
+
+pub fn set_end_effector_params_queued(
+    params: EndEffectorParams,
+) -> Result<Request<QueuedHandle>, DobotError> {
+    write_queued(CommandId::EndEffectorParams, params.to_bytes())
+}
+
+pub fn get_end_effector_laser() -> Result<Request<EndEffectorState>, DobotError> {
+    read(CommandId::EndEffectorLaser)
+}
+
+pub fn set_end_effector_laser(state: EndEffectorState) -> Result<Request<()>, DobotError> {
+    write(CommandId::EndEffectorLaser, state.to_bytes())
+}
+
+pub fn set_end_effector_laser_queued(
+    state: EndEffectorState,
+) -> Result<Request<QueuedHandle>, DobotError> {
+    write_queued(CommandId::EndEffectorLaser, state.to_bytes())
+}
+
+pub fn get_end_effector_suction_cup() -> Result<Request<EndEffectorState>, DobotError> {
+    read(CommandId::EndEffectorSuctionCup)
+}
+
+pub fn set_end_effector_suction_cup(state: EndEffectorState) -> Result<Request<()>, DobotError> {
+    write(CommandId::EndEffectorSuctionCup, state.to_bytes())
+}
+
+pub fn set_end_effector_suction_cup_queued(
+    state: EndEffectorState,
+) -> Result<Request<QueuedHandle>, DobotError> {
+    write_queued(CommandId::EndEffectorSuctionCup, state.to_bytes())
+}
+
+pub fn get_end_effector_gripper() -> Result<Request<EndEffectorState>, DobotError> {
+    read(CommandId::EndEffectorGripper)
+}
+
+pub fn set_end_effector_gripper(state: EndEffectorState) -> Result<Request<()>, DobotError> {
+    write(CommandId::EndEffectorGripper, state.to_bytes())
+}
+
+pub fn set_end_effector_gripper_queued(
+    state: EndEffectorState,
+) -> Result<Request<QueuedHandle>, DobotError> {
+    write_queued(CommandId::EndEffectorGripper, state.to_bytes())
+}
+
+pub fn get_io_multiplexing(pin: IoPin) -> Result<Request<IoFunction>, DobotError> {
+    read_io(CommandId::IoMultiplexing, pin)
+}
+
+pub fn set_io_multiplexing(pin: IoPin, function: IoFunction) -> Result<Request<()>, DobotError> {
+    write(
+        CommandId::IoMultiplexing,
+        vec![pin.address(), function as u8],
+    )
+}
+
+pub fn set_io_multiplexing_queued(
+    pin: IoPin,
+    function: IoFunction,
+) -> Result<Request<QueuedHandle>, DobotError> {
+    write_queued(
+        CommandId::IoMultiplexing,
+        vec![pin.address(), function as u8],
+    )
+}
+
+pub fn get_iodo(pin: IoPin) -> Result<Request<IoLevel>, DobotError> {
+    read_io(CommandId::IoDo, pin)
+}
+
+pub fn set_iodo(pin: IoPin, level: IoLevel) -> Result<Request<()>, DobotError> {
+    write(CommandId::IoDo, vec![pin.address(), level as u8])
+}
+
+pub fn set_iodo_queued(pin: IoPin, level: IoLevel) -> Result<Request<QueuedHandle>, DobotError> {
+    write_queued(CommandId::IoDo, vec![pin.address(), level as u8])
+}
+
+fn io_pwm_params(pin: IoPin, frequency: f32, duty_cycle: f32) -> Vec<u8> {
+    let mut params = pin.to_bytes();
+    frequency.encode(&mut params);
+    duty_cycle.encode(&mut params);
+    params
+}
+
+pub fn get_io_pwm(pin: IoPin) -> Result<Request<(f32, f32)>, DobotError> {
+    read_io(CommandId::IoPwm, pin)
+}
+
+pub fn set_io_pwm(pin: IoPin, frequency: f32, duty_cycle: f32) -> Result<Request<()>, DobotError> {
+    write(CommandId::IoPwm, io_pwm_params(pin, frequency, duty_cycle))
+}
+
+pub fn set_io_pwm_queued(
+    pin: IoPin,
+    frequency: f32,
+    duty_cycle: f32,
+) -> Result<Request<QueuedHandle>, DobotError> {
+    write_queued(CommandId::IoPwm, io_pwm_params(pin, frequency, duty_cycle))
+}
+
+pub fn get_iodi(pin: IoPin) -> Result<Request<IoLevel>, DobotError> {
+    read_io(CommandId::IoDi, pin)
+}
+
+pub fn get_io_adc(pin: IoPin) -> Result<Request<u16>, DobotError> {
+    read_io(CommandId::IoAdc, pin)
+}
+
+pub fn set_wait_command(wait_ms: u32) -> Result<Request<()>, DobotError> {
+    write(CommandId::WaitCmd, wait_ms.to_bytes())
+}
+
+pub fn set_wait_command_queued(wait_ms: u32) -> Result<Request<QueuedHandle>, DobotError> {
+    write_queued(CommandId::WaitCmd, wait_ms.to_bytes())
+}
+
+pub fn set_arm_orientation(l_r: ArmOrientation) -> Result<Request<()>, DobotError> {
+    Ok(write(CommandId::ArmOrientation, l_r.to_bytes())?
+        .then(move |state, _| state.orientation = Some(l_r)))
+}
+
+pub fn set_arm_orientation_queued(
+    l_r: ArmOrientation,
+) -> Result<Request<QueuedHandle>, DobotError> {
+    Ok(write_queued(CommandId::ArmOrientation, l_r.to_bytes())?
+        .then(move |state, _| state.orientation = Some(l_r)))
+}
+
+pub fn get_arm_orientation() -> Result<Request<ArmOrientation>, DobotError> {
+    Ok(read(CommandId::ArmOrientation)?
+        .then(|state, orientation: &ArmOrientation| state.orientation = Some(*orientation)))
+}
+
+pub fn set_queued_command_start_exec() -> Result<Request<()>, DobotError> {
+    write(CommandId::QueuedCmdStartExec, vec![])
+}
+
+pub fn set_queued_command_stop_exec() -> Result<Request<()>, DobotError> {
+    write(CommandId::QueuedCmdStopExec, vec![])
+}
+
+pub fn set_queued_command_force_stop_exec() -> Result<Request<()>, DobotError> {
+    Ok(write(CommandId::QueuedCmdForceStopExec, vec![])?.before(forget_position))
+}
+
+pub fn set_queued_command_clear() -> Result<Request<()>, DobotError> {
+    Ok(write(CommandId::QueuedCmdClear, vec![])?.before(forget_queue))
+}
+
+pub fn get_queued_command_current_index() -> Result<Request<u64>, DobotError> {
+    read(CommandId::QueuedCmdCurrentIndex)
+}
+
+pub fn get_queued_command_left_space() -> Result<Request<u32>, DobotError> {
+    read(CommandId::QueuedCmdLeftSpace)
+}
+
+/// Defines the client methods that are a single request, for `DobotClient`
+/// (`blocking`) and `AsyncDobotClient` (`async`). Each method sends the
+/// request of the function with the same name in this module, a `motion`
+/// passes the state and where the motion starts to it.
+macro_rules! request_methods {
+    ($mode:ident) => {
+        $crate::request::request_methods! { @methods $mode
+            fn get_device_sn() -> String;
+            fn get_alarm_state() -> AlarmSet;
+            fn clear_all_alarm_state() -> ();
+            fn get_home_params() -> HomeParams;
+            fn set_home_params(params: HomeParams) -> ();
+            fn set_home_params_queued(params: HomeParams) -> QueuedHandle;
+            fn set_home_cmd() -> ();
+            fn set_home_cmd_queued() -> QueuedHandle;
+            fn get_jog_joint_params() -> JogJointParams;
+            fn set_jog_joint_params(params: JogJointParams) -> ();
+            fn set_jog_joint_params_queued(params: JogJointParams) -> QueuedHandle;
+            fn get_jog_coordinate_params() -> JogCoordinateParams;
+            fn set_jog_coordinate_params(params: JogCoordinateParams) -> ();
+            fn set_jog_coordinate_params_queued(params: JogCoordinateParams) -> QueuedHandle;
+            fn get_jog_common_params() -> JogCommonParams;
+            fn set_jog_common_params(params: JogCommonParams) -> ();
+            fn set_jog_common_params_queued(params: JogCommonParams) -> QueuedHandle;
+            fn get_ptp_joint_params() -> PtpJointParams;
+            fn set_ptp_joint_params(params: PtpJointParams) -> ();
+            fn set_ptp_joint_params_queued(params: PtpJointParams) -> QueuedHandle;
+            fn get_ptp_coordinate_params() -> PtpCoordinateParams;
+            fn set_ptp_coordinate_params(params: PtpCoordinateParams) -> ();
+            fn set_ptp_coordinate_params_queued(params: PtpCoordinateParams) -> QueuedHandle;
+            fn get_ptp_jump_params() -> PtpJumpParams;
+            fn set_ptp_jump_params(params: PtpJumpParams) -> ();
+            fn set_ptp_jump_params_queued(params: PtpJumpParams) -> QueuedHandle;
+            fn get_ptp_common_params() -> PtpCommonParams;
+            fn set_ptp_common_params(params: PtpCommonParams) -> ();
+            fn set_ptp_common_params_queued(params: PtpCommonParams) -> QueuedHandle;
+            motion set_ptp_command(command: PtpCommand) -> ();
+            motion set_ptp_command_queued(command: PtpCommand) -> QueuedHandle;
+            fn get_cp_params() -> CpParams;
+            fn set_cp_params(params: CpParams) -> ();
+            fn set_cp_params_queued(params: CpParams) -> QueuedHandle;
+            motion set_cp_command(command: CpCommand) -> ();
+            /// Consecutive queued CP commands are blended into one continuous path.
+            motion set_cp_command_queued(command: CpCommand) -> QueuedHandle;
+            motion set_cp_laser_command(command: CpLaserCommand) -> ();
+            motion set_cp_laser_command_queued(command: CpLaserCommand) -> QueuedHandle;
+            fn get_arc_params() -> ArcParams;
+            fn set_arc_params(params: ArcParams) -> ();
+            fn set_arc_params_queued(params: ArcParams) -> QueuedHandle;
+            motion set_arc_command(command: ArcCommand) -> ();
+            motion set_arc_command_queued(command: ArcCommand) -> QueuedHandle;
+            motion set_circle_command(command: CircleCommand) -> ();
+            motion set_circle_command_queued(command: CircleCommand) -> QueuedHandle;
+            fn get_end_effector_params() -> EndEffectorParams;
+            fn set_end_effector_params(params: EndEffectorParams) -> ();
+            fn set_end_effector_params_queued(params: EndEffectorParams) -> QueuedHandle;
+            fn get_end_effector_laser() -> EndEffectorState;
+            fn set_end_effector_laser(state: EndEffectorState) -> ();
+            fn set_end_effector_laser_queued(state: EndEffectorState) -> QueuedHandle;
+            fn get_end_effector_suction_cup() -> EndEffectorState;
+            fn set_end_effector_suction_cup(state: EndEffectorState) -> ();
+            fn set_end_effector_suction_cup_queued(state: EndEffectorState) -> QueuedHandle;
+            fn get_end_effector_gripper() -> EndEffectorState;
+            fn set_end_effector_gripper(state: EndEffectorState) -> ();
+            fn set_end_effector_gripper_queued(state: EndEffectorState) -> QueuedHandle;
+            fn get_io_multiplexing(pin: IoPin) -> IoFunction;
+            fn set_io_multiplexing(pin: IoPin, function: IoFunction) -> ();
+            fn set_io_multiplexing_queued(pin: IoPin, function: IoFunction) -> QueuedHandle;
+            fn get_iodo(pin: IoPin) -> IoLevel;
+            fn set_iodo(pin: IoPin, level: IoLevel) -> ();
+            fn set_iodo_queued(pin: IoPin, level: IoLevel) -> QueuedHandle;
+            /// Frequency in Hz and duty cycle in %.
+            fn get_io_pwm(pin: IoPin) -> (f32, f32);
+            /// `frequency` in Hz (10 ~ 1000000), `duty_cycle` in % (0 ~ 100).
+            fn set_io_pwm(pin: IoPin, frequency: f32, duty_cycle: f32) -> ();
+            fn set_io_pwm_queued(pin: IoPin, frequency: f32, duty_cycle: f32) -> QueuedHandle;
+            fn get_iodi(pin: IoPin) -> IoLevel;
+            /// 12 bit value, 0 ~ 4095.
+            fn get_io_adc(pin: IoPin) -> u16;
+            fn set_wait_command(wait_ms: u32) -> ();
+            fn set_wait_command_queued(wait_ms: u32) -> QueuedHandle;
+            fn set_arm_orientation(l_r: ArmOrientation) -> ();
+            fn set_arm_orientation_queued(l_r: ArmOrientation) -> QueuedHandle;
+            fn get_arm_orientation() -> ArmOrientation;
+            fn set_queued_command_start_exec() -> ();
+            fn set_queued_command_stop_exec() -> ();
+            fn set_queued_command_force_stop_exec() -> ();
+            fn set_queued_command_clear() -> ();
+            fn get_queued_command_current_index() -> u64;
+            fn get_queued_command_left_space() -> u32;
+        }
+    };
+    (@methods $mode:ident $(
+        $(#[$meta:meta])*
+        $kind:ident $name:ident($($arg:ident: $ty:ty),*) -> $ret:ty;
+    )*) => {
+        $(
+            $crate::request::request_methods! {
+                @method $mode $kind $(#[$meta])* $name($($arg: $ty),*) -> $ret
+            }
+        )*
+    };
+    (@method blocking fn $(#[$meta:meta])* $name:ident($($arg:ident: $ty:ty),*) -> $ret:ty) => {
+        $(#[$meta])*
+        pub fn $name(&mut self, $($arg: $ty),*) -> Result<$ret, DobotError> {
+            self.call($crate::request::$name($($arg),*)?)
+        }
+    };
+    (@method blocking motion $(#[$meta:meta])* $name:ident($($arg:ident: $ty:ty),*) -> $ret:ty) => {
+        $(#[$meta])*
+        pub fn $name(&mut self, $($arg: $ty),*) -> Result<$ret, DobotError> {
+            let start = self.motion_start()?;
+            self.call($crate::request::$name(&self.state, start, $($arg),*)?)
+        }
+    };
+    (@method async fn $(#[$meta:meta])* $name:ident($($arg:ident: $ty:ty),*) -> $ret:ty) => {
+        $(#[$meta])*
+        pub async fn $name(&mut self, $($arg: $ty),*) -> Result<$ret, DobotError> {
+            self.call($crate::request::$name($($arg),*)?).await
+        }
+    };
+    (@method async motion $(#[$meta:meta])* $name:ident($($arg:ident: $ty:ty),*) -> $ret:ty) => {
+        $(#[$meta])*
+        pub async fn $name(&mut self, $($arg: $ty),*) -> Result<$ret, DobotError> {
+            let start = self.motion_start().await?;
+            self.call($crate::request::$name(&self.state, start, $($arg),*)?).await
+        }
+    };
+}
+
+pub(crate) use request_methods;
@@ -0,0 +1,410 @@
+use crate::codec::{Decode, Encode};
+use crate::command::CommandId;
+use crate::error::DobotError;
+use crate::protocol::{PayloadStruct, ReadWrite};
+use crate::traits::Device;
+use std::time::Duration;
+
+/// Kind of request, each has its own timeout in a `RetryPolicy`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CommandClass {
+    Read,
+    Write,
+    /// Write into the command queue.
+    Queued,
+}
+
+impl CommandClass {
+    pub fn of(packet: &PayloadStruct) -> Self {
+        match (&packet.rw, packet.is_queued) {
+            (ReadWrite::READ, _) => CommandClass::Read,
+            (ReadWrite::WRITE, false) => CommandClass::Write,
+            (ReadWrite::WRITE, true) => CommandClass::Queued,
+        }
+    }
+}
+
+/// Timeouts, retry count and backoff of a `RetryDevice`.
+///
+/// A queued write is only retried after `RetryDevice::probe_queue_index`
+/// checked whether it made it into the queue. Every probe is a real
+/// `WAITCmd` of 0 ms: it takes up a queue slot until executed and moves the
+/// queue index on.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub read_timeout: Duration,
+    pub write_timeout: Duration,
+    pub queued_timeout: Duration,
+    /// How many times a failed request is sent again.
+    pub retries: usize,
+    /// Wait before the first retry, doubled for every further one.
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+    /// Discard the input of the device before every retry.
+    pub flush_input: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            read_timeout: Duration::from_millis(1000),
+            write_timeout: Duration::from_millis(1000),
+            queued_timeout: Duration::from_millis(1000),
+            retries: 2,
+            backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_millis(1000),
+            flush_input: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn timeout(&self, class: CommandClass) -> Duration {
+        match class {
+            CommandClass::Read => self.read_timeout,
+            CommandClass::Write => self.write_timeout,
+            CommandClass::Queued => self.queued_timeout,
+        }
+    }
+
+    /// Wait before retry number `retry`, counting from 0.
+    pub fn backoff(&self, retry: usize) -> Duration {
+        let factor = 1u32.checked_shl(retry as u32).unwrap_or(u32::MAX);
+        self.backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff))
+    }
+}
+
+// errors a second attempt may not run into
+fn is_transient(e: &DobotError) -> bool {
+    matches!(
+        e,
+        DobotError::Timeout
+            | DobotError::Io(_)
+            | DobotError::Framing(_)
+            | DobotError::Checksum { .. }
+    )
+}
+
+/// Wraps a `Device` and sends requests again that failed with a timeout or
+/// a transport error, as configured by a `RetryPolicy`.
+///
+/// A queued write whose response got lost may still have been queued, so it
+/// is not simply sent again. The queue index is checked first by queueing a
+/// `WAITCmd` of 0 ms: if it got the index after the one the lost command
+/// would have had, that command is in the queue and its index is returned
+/// without resending it. This needs the index of the last queued command,
+/// which is learned from every queued response or `probe_queue_index`.
+/// Until one is known, queued writes are not retried.
+///
+/// The check only holds if the wrapped device sends every request once, a
+/// queued write it sent again by itself could be queued twice. `new` calls
+/// `Device::disable_retries`, so e.g. a `UdpDevice` stops re-sending and
+/// all retries happen here.
+///
+/// ```
+/// use dobot_client::*;
+///
+/// let device = RetryDevice::new(SimulatedDevice::new(), RetryPolicy::default());
+/// let mut dobot = DobotClient::new(device);
+/// dobot.get_pose().unwrap();
+/// ```
+pub struct RetryDevice<D: Device> {
+    device: D,
+    policy: RetryPolicy,
+    // timeout the device is currently set to
+    timeout: Option<Duration>,
+    last_index: Option<u64>,
+}
+
+impl<D: Device> RetryDevice<D> {
+    pub fn new(mut device: D, policy: RetryPolicy) -> Self {
+        device.disable_retries();
+        Self {
+            device,
+            policy,
+            timeout: None,
+            last_index: None,
+        }
+    }
+
+    pub fn policy(&self) -> &RetryPolicy {
+        &self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: RetryPolicy) {
+        self.policy = policy;
+        self.timeout = None;
+    }
+
+    pub fn device(&self) -> &D {
+        &self.device
+    }
+
+    pub fn device_mut(&mut self) -> &mut D {
+        &mut self.device
+    }
+
+    pub fn into_inner(self) -> D {
+        self.device
+    }
+
+    /// Index of the last command queued through this device, if known.
+    pub fn last_queue_index(&self) -> Option<u64> {
+        self.last_index
+    }
+
+    /// Learns the queue index by queueing a `WAITCmd` of 0 ms, so that even
+    /// the first queued write can be retried.
+    ///
+    /// The `WAITCmd` is a real queued command: it needs a free queue slot,
+    /// takes the next index and only leaves the queue once executed, which
+    /// waits for the commands queued before it.
+    pub fn probe_queue_index(&mut self) -> Result<u64, DobotError> {
+        self.apply_timeout(CommandClass::Queued)?;
+        let probe = CommandId::WaitCmd.request(ReadWrite::WRITE, true, 0u32.to_bytes())?;
+        let index = self
+            .device
+            .send(probe)
+            .and_then(|response| u64::decode(&response.params));
+        self.last_index = index.as_ref().ok().cloned();
+        index
+    }
+
+    fn apply_timeout(&mut self, class: CommandClass) -> Result<(), DobotError> {
+        let timeout = self.policy.timeout(class);
+        if self.timeout != Some(timeout) {
+            self.device.set_timeout(timeout)?;
+            self.timeout = Some(timeout);
+        }
+        Ok(())
+    }
+
+    fn prepare_retry(&mut self, retry: usize) -> Result<(), DobotError> {
+        std::thread::sleep(self.policy.backoff(retry));
+        if self.policy.flush_input {
+            self.device.flush_input()?;
+        }
+        Ok(())
+    }
+
+    fn send_queued(&mut self, packet: PayloadStruct) -> Result<PayloadStruct, DobotError> {
+        let mut retry = 0;
+        loop {
+            self.apply_timeout(CommandClass::Queued)?;
+            let error = match self.device.send(packet.clone()) {
+                Ok(response) => {
+                    self.last_index = u64::decode(&response.params).ok();
+                    return Ok(response);
+                }
+                Err(e) => e,
+            };
+            if !is_transient(&error) {
+                return Err(error);
+            }
+            let expected = match self.last_index.take() {
+                Some(last) if retry < self.policy.retries => last + 1,
+                // it may or may not be queued now
+                _ => return Err(error),
+            };
+            self.prepare_retry(retry)?;
+            retry += 1;
+            let index = match self.probe_queue_index() {
+                Ok(index) => index,
+                Err(_) => return Err(error),
+            };
+            if index > expected {
+                return Ok(PayloadStruct {
+                    params: expected.to_bytes(),
+                    ..packet
+                });
+            }
+        }
+    }
+}
+
+impl<D: Device> Device for RetryDevice<D> {
+    fn send(&mut self, packet: PayloadStruct) -> Result<PayloadStruct, DobotError> {
+        let class = CommandClass::of(&packet);
+        if class == CommandClass::Queued {
+            return self.send_queued(packet);
+        }
+        let clears_queue =
+            packet.id == CommandId::QueuedCmdClear as u8 && packet.rw == ReadWrite::WRITE;
+        let mut retry = 0;
+        loop {
+            self.apply_timeout(class)?;
+            match self.device.send(packet.clone()) {
+                Err(e) if is_transient(&e) && retry < self.policy.retries => {
+                    self.prepare_retry(retry)?;
+                    retry += 1;
+                }
+                result => {
+                    // the device may start counting again
+                    if clears_queue {
+                        self.last_index = None;
+                    }
+                    return result;
+                }
+            }
+        }
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), DobotError> {
+        self.policy.read_timeout = timeout;
+        self.policy.write_timeout = timeout;
+        self.policy.queued_timeout = timeout;
+        self.apply_timeout(CommandClass::Read)
+    }
+
+    fn flush_input(&mut self) -> Result<(), DobotError> {
+        self.device.flush_input()
+    }
+
+    fn disable_retries(&mut self) {
+        self.policy.retries = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::DobotClient;
+    use crate::params::*;
+    use crate::sim::SimulatedDevice;
+
+    /// Simulator that loses the requests or responses of chosen sends.
+    #[derive(Default)]
+    struct Flaky {
+        sim: SimulatedDevice,
+        sent: usize,
+        lost_requests: Vec<usize>,
+        lost_responses: Vec<usize>,
+        timeouts: Vec<Duration>,
+        flushes: usize,
+    }
+
+    impl Device for Flaky {
+        fn send(&mut self, packet: PayloadStruct) -> Result<PayloadStruct, DobotError> {
+            self.sent += 1;
+            if self.lost_requests.contains(&self.sent) {
+                return Err(DobotError::Timeout);
+            }
+            let response = self.sim.send(packet);
+            if self.lost_responses.contains(&self.sent) {
+                return Err(DobotError::Timeout);
+            }
+            response
+        }
+
+        fn set_timeout(&mut self, timeout: Duration) -> Result<(), DobotError> {
+            self.timeouts.push(timeout);
+            Ok(())
+        }
+
+        fn flush_input(&mut self) -> Result<(), DobotError> {
+            self.flushes += 1;
+            Ok(())
+        }
+    }
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            read_timeout: Duration::from_millis(100),
+            write_timeout: Duration::from_millis(200),
+            queued_timeout: Duration::from_millis(300),
+            backoff: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        }
+    }
+
+    fn client(flaky: Flaky) -> DobotClient<RetryDevice<Flaky>> {
+        DobotClient::new(RetryDevice::new(flaky, policy()))
+    }
+
+    fn wait() -> PayloadStruct {
+        CommandId::WaitCmd
+            .request(ReadWrite::WRITE, true, 10u32.to_bytes())
+            .unwrap()
+    }
+
+    #[test]
+    fn retries_with_timeout_per_class() {
+        let mut dobot = client(Flaky {
+            lost_requests: vec![1],
+            lost_responses: vec![2, 5, 6, 7],
+            ..Flaky::default()
+        });
+        dobot.get_pose().unwrap();
+        dobot.set_queued_command_start_exec().unwrap();
+        assert!(matches!(
+            dobot.set_queued_command_stop_exec(),
+            Err(DobotError::Timeout)
+        ));
+        let device = dobot.device().device();
+        assert_eq!(device.sent, 7);
+        assert_eq!(device.flushes, 4);
+        assert_eq!(
+            device.timeouts,
+            vec![Duration::from_millis(100), Duration::from_millis(200)]
+        );
+        assert_eq!(policy().backoff(10), Duration::from_millis(1000));
+        assert_eq!(policy().backoff(2), Duration::from_millis(4));
+    }
+
+    #[test]
+    fn lost_queued_response_is_not_queued_twice() {
+        let mut device = RetryDevice::new(
+            Flaky {
+                lost_responses: vec![3],
+                ..Flaky::default()
+            },
+            policy(),
+        );
+        // queued writes are only retried once an index is known
+        assert_eq!(device.send(wait()).unwrap().params, 1u64.to_bytes());
+        assert_eq!(device.send(wait()).unwrap().params, 2u64.to_bytes());
+        // the response to index 3 is lost, the probe gets 4
+        assert_eq!(device.send(wait()).unwrap().params, 3u64.to_bytes());
+        assert_eq!(device.last_queue_index(), Some(4));
+        assert_eq!(device.device().sent, 4);
+
+        let mut dobot = DobotClient::new(device);
+        assert_eq!(dobot.get_queued_command_left_space().unwrap(), 32 - 4);
+    }
+
+    #[test]
+    fn lost_queued_request_is_sent_again() {
+        let mut device = RetryDevice::new(
+            Flaky {
+                lost_requests: vec![1, 3],
+                ..Flaky::default()
+            },
+            policy(),
+        );
+        // nothing to compare against yet
+        assert!(device.send(wait()).is_err());
+        assert_eq!(device.device().sent, 1);
+
+        assert_eq!(device.probe_queue_index().unwrap(), 1);
+        // the probe after the lost request takes its index
+        assert_eq!(device.send(wait()).unwrap().params, 3u64.to_bytes());
+        assert_eq!(device.device().sent, 5);
+        let mut dobot = DobotClient::new(device);
+        assert_eq!(dobot.get_queued_command_left_space().unwrap(), 32 - 3);
+
+        dobot.set_queued_command_clear().unwrap();
+        assert_eq!(dobot.device().last_queue_index(), None);
+        dobot
+            .set_ptp_command_queued(PtpCommand {
+                ptp_mode: PtpMode::MovjXyz,
+                x: 200.0,
+                y: 0.0,
+                z: 0.0,
+                r: 0.0,
+            })
+            .unwrap();
+        assert_eq!(dobot.device().last_queue_index(), Some(4));
+    }
+}
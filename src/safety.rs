@@ -0,0 +1,588 @@
+use crate::error::DobotError;
+use crate::kinematics::{arc_points, Kinematics};
+use crate::params::*;
+
+/// Cartesian region used by `SafetyEnvelope`, in mm.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Zone {
+    /// Axis aligned box between two corners.
+    Box { min: [f32; 3], max: [f32; 3] },
+    /// Vertical cylinder around (x, y) = `center`.
+    Cylinder {
+        center: [f32; 2],
+        radius: f32,
+        z_min: f32,
+        z_max: f32,
+    },
+}
+
+impl Zone {
+    pub fn contains(&self, point: &[f32; 3]) -> bool {
+        match *self {
+            Zone::Box { min, max } => (0..3).all(|i| min[i] <= point[i] && point[i] <= max[i]),
+            Zone::Cylinder {
+                center,
+                radius,
+                z_min,
+                z_max,
+            } => {
+                let (dx, dy) = (point[0] - center[0], point[1] - center[1]);
+                dx * dx + dy * dy <= radius * radius && z_min <= point[2] && point[2] <= z_max
+            }
+        }
+    }
+}
+
+/// Limits that every motion has to satisfy before `DobotClient` sends it to
+/// the device.
+///
+/// The path to the target is checked point by point: straight lines about
+/// 5 mm apart, joint moves about 5 deg (or 5 mm of J3) apart and arcs about
+/// 5 deg apart. JUMP modes are checked along their lift, transfer and
+/// lowering legs, as set with `jump_params`. Jogs are only checked as they
+/// start, see `check_jog`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SafetyEnvelope {
+    kinematics: Kinematics,
+    joint_limits: [(f32, f32); 4],
+    keep_in: Vec<Zone>,
+    keep_out: Vec<Zone>,
+    z_floor: Option<f32>,
+    jump: PtpJumpParams,
+}
+
+impl SafetyEnvelope {
+    /// Envelope allowing everything within the joint limits of `kinematics`.
+    pub fn new(kinematics: Kinematics) -> Self {
+        Self {
+            joint_limits: kinematics.joint_limits,
+            kinematics,
+            keep_in: vec![],
+            keep_out: vec![],
+            z_floor: None,
+            jump: PtpJumpParams {
+                jump_height: 20.0,
+                z_limit: 230.0,
+                dummy: 0,
+            },
+        }
+    }
+
+    /// Narrows the joint limits, (min, max) of J1 ~ J4.
+    pub fn joint_limits(mut self, limits: [(f32, f32); 4]) -> Self {
+        self.joint_limits = limits;
+        self
+    }
+
+    /// Adds a zone the target has to be in. With several keep-in zones, being
+    /// inside any of them is enough.
+    pub fn keep_in(mut self, zone: Zone) -> Self {
+        self.keep_in.push(zone);
+        self
+    }
+
+    /// Adds a zone the target must not be in.
+    pub fn keep_out(mut self, zone: Zone) -> Self {
+        self.keep_out.push(zone);
+        self
+    }
+
+    /// Lowest allowed z.
+    pub fn z_floor(mut self, z: f32) -> Self {
+        self.z_floor = Some(z);
+        self
+    }
+
+    /// JUMP height and limit the device uses, to check the legs of JUMP
+    /// modes. The device defaults of 20 mm and 230 mm are assumed until set.
+    pub fn jump_params(mut self, params: PtpJumpParams) -> Self {
+        self.jump = params;
+        self
+    }
+
+    pub fn kinematics(&self) -> &Kinematics {
+        &self.kinematics
+    }
+
+    /// Checks a target given as joint angles.
+    pub fn check_joints(&self, joints: &[f32; 4]) -> Result<(), DobotError> {
+        for (i, (&value, &(min, max))) in joints.iter().zip(&self.joint_limits).enumerate() {
+            if value < min || value > max {
+                return Err(violation(format!(
+                    "J{} = {:.2} is outside of [{}, {}]",
+                    i + 1,
+                    value,
+                    min,
+                    max
+                )));
+            }
+        }
+        let [x, y, z, _] = self.kinematics.forward(joints);
+        let point = [x, y, z];
+        if let Some(floor) = self.z_floor {
+            if z < floor {
+                return Err(violation(format!(
+                    "z = {:.2} is below the floor {}",
+                    z, floor
+                )));
+            }
+        }
+        if let Some(zone) = self.keep_out.iter().find(|zone| zone.contains(&point)) {
+            return Err(violation(format!(
+                "{} is inside keep-out zone {:?}",
+                format_point(&point),
+                zone
+            )));
+        }
+        if !self.keep_in.is_empty() && !self.keep_in.iter().any(|zone| zone.contains(&point)) {
+            return Err(violation(format!(
+                "{} is outside of every keep-in zone",
+                format_point(&point)
+            )));
+        }
+        Ok(())
+    }
+
+    /// Resolves the target joint angles of `command` and checks the path to
+    /// them.
+    ///
+    /// `current` are the joint angles the command starts from, which
+    /// incremental modes are relative to. Cartesian targets are solved with
+    /// `orientation`, like the device does.
+    pub fn check_ptp(
+        &self,
+        command: &PtpCommand,
+        current: &[f32; 4],
+        orientation: ArmOrientation,
+    ) -> Result<[f32; 4], DobotError> {
+        let target = [command.x, command.y, command.z, command.r];
+        let add = |a: [f32; 4]| {
+            [
+                a[0] + target[0],
+                a[1] + target[1],
+                a[2] + target[2],
+                a[3] + target[3],
+            ]
+        };
+        let joints = match command.ptp_mode {
+            PtpMode::JumpAngle | PtpMode::MovjAngle | PtpMode::MovlAngle => target,
+            PtpMode::MovjInc => add(*current),
+            PtpMode::JumpXyz | PtpMode::MovjXyz | PtpMode::MovlXyz | PtpMode::JumpMovlXyz => {
+                self.solve(&target, orientation)?
+            }
+            PtpMode::MovlInc | PtpMode::MovjXyzInc => {
+                self.solve(&add(self.kinematics.forward(current)), orientation)?
+            }
+        };
+        let (linear, jump) = match command.ptp_mode {
+            PtpMode::JumpXyz | PtpMode::JumpAngle => (false, true),
+            PtpMode::JumpMovlXyz => (true, true),
+            PtpMode::MovlXyz | PtpMode::MovlAngle | PtpMode::MovlInc => (true, false),
+            PtpMode::MovjXyz | PtpMode::MovjAngle | PtpMode::MovjInc | PtpMode::MovjXyzInc => {
+                (false, false)
+            }
+        };
+        let mut waypoints = vec![*current];
+        if jump {
+            // same legs as the device: up, across at the top, down
+            let high = current[2].max(joints[2]);
+            let top = (high + self.jump.jump_height)
+                .min(self.jump.z_limit)
+                .max(high);
+            waypoints.push([current[0], current[1], top, current[3]]);
+            waypoints.push([joints[0], joints[1], top, joints[3]]);
+        }
+        waypoints.push(joints);
+        for leg in waypoints.windows(2) {
+            // the lift and lowering legs only move J3
+            let linear = linear && leg[0][2] == leg[1][2];
+            self.check_segment(&leg[0], &leg[1], linear, orientation)?;
+        }
+        self.check_joints(&joints)?;
+        Ok(joints)
+    }
+
+    /// Resolves the target of a CP move to (x, y, z) and checks the line to
+    /// it. The orientation and r are kept from `current`.
+    pub fn check_cp(
+        &self,
+        mode: CpMode,
+        point: &[f32; 3],
+        current: &[f32; 4],
+        orientation: ArmOrientation,
+    ) -> Result<[f32; 4], DobotError> {
+        let mut cartesian = self.kinematics.forward(current);
+        for i in 0..3 {
+            cartesian[i] = match mode {
+                CpMode::Relative => cartesian[i] + point[i],
+                CpMode::Absolute => point[i],
+            };
+        }
+        let joints = self.solve(&cartesian, orientation)?;
+        self.check_segment(current, &joints, true, orientation)?;
+        self.check_joints(&joints)?;
+        Ok(joints)
+    }
+
+    /// Checks a Cartesian target (x, y, z, r) such as the points of an ARC
+    /// command, and returns its joint angles.
+    pub fn check_cartesian(
+        &self,
+        cartesian: &[f32; 4],
+        orientation: ArmOrientation,
+    ) -> Result<[f32; 4], DobotError> {
+        let joints = self.solve(cartesian, orientation)?;
+        self.check_joints(&joints)?;
+        Ok(joints)
+    }
+
+    /// Checks points about 5 deg apart on the arc from `current` through
+    /// `via` to `to`, and returns the joint angles at `to`. With `circle`,
+    /// the full circle through the three points is checked instead.
+    pub fn check_arc(
+        &self,
+        via: &ArcPoint,
+        to: &ArcPoint,
+        circle: bool,
+        current: &[f32; 4],
+        orientation: ArmOrientation,
+    ) -> Result<[f32; 4], DobotError> {
+        let start = self.kinematics.forward(current);
+        let turns = if circle { Some(1) } else { None };
+        let points = arc_points(
+            &[start[0], start[1], start[2]],
+            &[via.x, via.y, via.z],
+            &[to.x, to.y, to.z],
+            turns,
+        )
+        .ok_or_else(|| violation("the ARC points are on a line".to_string()))?;
+        let count = points.len() as f32;
+        let mut joints = *current;
+        for (i, point) in points.iter().enumerate() {
+            // r turns evenly along the arc, like on the device
+            let r = match turns {
+                Some(_) => start[3],
+                None => start[3] + (to.r - start[3]) * (i + 1) as f32 / count,
+            };
+            joints = self.check_cartesian(&[point[0], point[1], point[2], r], orientation)?;
+        }
+        Ok(joints)
+    }
+
+    /// Checks the first 1 mm or 1 deg step of a jog starting at `current`,
+    /// so a jog can not start towards a forbidden region. `Idel` always
+    /// passes.
+    ///
+    /// The jog goes on until `Idel` is sent and is not checked any further,
+    /// it can leave the envelope if it is not stopped in time.
+    pub fn check_jog(
+        &self,
+        mode: JogCommandType,
+        command: JogCommand,
+        current: &[f32; 4],
+        orientation: ArmOrientation,
+    ) -> Result<(), DobotError> {
+        const STEP: f32 = 1.0;
+        let (axis, delta) = match command {
+            JogCommand::Idel => return Ok(()),
+            JogCommand::ApDown => (0, STEP),
+            JogCommand::AnDown => (0, -STEP),
+            JogCommand::BpDown => (1, STEP),
+            JogCommand::BnDown => (1, -STEP),
+            JogCommand::CpDown => (2, STEP),
+            JogCommand::CnDown => (2, -STEP),
+            JogCommand::DpDown => (3, STEP),
+            JogCommand::DnDown => (3, -STEP),
+        };
+        let joints = match mode {
+            JogCommandType::Joint => {
+                let mut joints = *current;
+                joints[axis] += delta;
+                joints
+            }
+            JogCommandType::Cartesian => {
+                let mut cartesian = self.kinematics.forward(current);
+                cartesian[axis] += delta;
+                self.solve(&cartesian, orientation)?
+            }
+        };
+        self.check_joints(&joints)
+    }
+
+    /// Checks points about 5 mm or 5 deg apart from `from` to `to`, on a
+    /// straight line with `linear` and else interpolating the joints.
+    fn check_segment(
+        &self,
+        from: &[f32; 4],
+        to: &[f32; 4],
+        linear: bool,
+        orientation: ArmOrientation,
+    ) -> Result<(), DobotError> {
+        const STEP: f32 = 5.0;
+        let (a, b) = if linear {
+            (self.kinematics.forward(from), self.kinematics.forward(to))
+        } else {
+            (*from, *to)
+        };
+        let length = if linear {
+            (0..3).map(|i| (b[i] - a[i]).powi(2)).sum::<f32>().sqrt()
+        } else {
+            (0..4).map(|i| (b[i] - a[i]).abs()).fold(0.0, f32::max)
+        };
+        let steps = (length / STEP).ceil().max(1.0) as usize;
+        for step in 1..=steps {
+            let t = step as f32 / steps as f32;
+            let mut point = a;
+            for i in 0..4 {
+                point[i] += (b[i] - a[i]) * t;
+            }
+            if linear {
+                self.check_cartesian(&point, orientation)?;
+            } else {
+                self.check_joints(&point)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn solve(
+        &self,
+        cartesian: &[f32; 4],
+        orientation: ArmOrientation,
+    ) -> Result<[f32; 4], DobotError> {
+        self.kinematics
+            .inverse(cartesian, orientation)
+            .ok_or_else(|| {
+                violation(format!(
+                    "{} is out of reach",
+                    format_point(&[cartesian[0], cartesian[1], cartesian[2]])
+                ))
+            })
+    }
+}
+
+impl Default for SafetyEnvelope {
+    fn default() -> Self {
+        Self::new(Kinematics::default())
+    }
+}
+
+fn violation(message: String) -> DobotError {
+    DobotError::SafetyViolation(message)
+}
+
+fn format_point(point: &[f32; 3]) -> String {
+    format!("({:.2}, {:.2}, {:.2})", point[0], point[1], point[2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ptp(ptp_mode: PtpMode, x: f32, y: f32, z: f32, r: f32) -> PtpCommand {
+        PtpCommand {
+            ptp_mode,
+            x,
+            y,
+            z,
+            r,
+        }
+    }
+
+    #[test]
+    fn zones() {
+        let b = Zone::Box {
+            min: [0.0, 0.0, 0.0],
+            max: [10.0, 10.0, 10.0],
+        };
+        assert!(b.contains(&[5.0, 10.0, 0.0]));
+        assert!(!b.contains(&[5.0, 10.1, 0.0]));
+        let c = Zone::Cylinder {
+            center: [100.0, 0.0],
+            radius: 10.0,
+            z_min: 0.0,
+            z_max: 50.0,
+        };
+        assert!(c.contains(&[106.0, 8.0, 20.0]));
+        assert!(!c.contains(&[108.0, 8.0, 20.0]));
+        assert!(!c.contains(&[100.0, 0.0, 51.0]));
+    }
+
+    #[test]
+    fn checks_targets() {
+        let envelope = SafetyEnvelope::default()
+            .z_floor(20.0)
+            .keep_out(Zone::Cylinder {
+                center: [300.0, 0.0],
+                radius: 30.0,
+                z_min: 0.0,
+                z_max: 100.0,
+            })
+            .keep_in(Zone::Box {
+                min: [0.0, -350.0, 0.0],
+                max: [400.0, 350.0, 230.0],
+            });
+        let home = [0.0, 90.0, 100.0, 0.0];
+        let lefty = ArmOrientation::Lefty;
+        let ok = envelope
+            .check_ptp(
+                &ptp(PtpMode::MovjXyz, 250.0, 100.0, 50.0, 0.0),
+                &home,
+                lefty,
+            )
+            .unwrap();
+        assert!(ok[1] > 0.0);
+
+        let rejected = [
+            ptp(PtpMode::MovjXyz, 250.0, 100.0, 10.0, 0.0),
+            ptp(PtpMode::MovlXyz, 310.0, 10.0, 50.0, 0.0),
+            ptp(PtpMode::MovjXyz, -100.0, 200.0, 50.0, 0.0),
+            ptp(PtpMode::MovjXyz, 500.0, 0.0, 50.0, 0.0),
+            ptp(PtpMode::MovjAngle, 90.0, 0.0, 50.0, 0.0),
+            // relative to home (200, 200, 100): ends at z = 10
+            ptp(PtpMode::MovjXyzInc, 0.0, 0.0, -90.0, 0.0),
+            // J3 100 -> 0, below the floor
+            ptp(PtpMode::MovjInc, 0.0, 0.0, -100.0, 0.0),
+        ];
+        for command in &rejected {
+            let result = envelope.check_ptp(command, &home, lefty);
+            assert!(
+                matches!(result, Err(DobotError::SafetyViolation(_))),
+                "{:?} {:?}",
+                command,
+                result
+            );
+        }
+        assert!(envelope
+            .check_ptp(&ptp(PtpMode::MovjInc, 0.0, 0.0, -50.0, 0.0), &home, lefty)
+            .is_ok());
+    }
+
+    #[test]
+    fn checks_cp_targets() {
+        let envelope = SafetyEnvelope::default().z_floor(20.0);
+        let home = [0.0, 90.0, 100.0, 0.0];
+        let lefty = ArmOrientation::Lefty;
+        assert!(envelope
+            .check_cp(CpMode::Absolute, &[250.0, 0.0, 30.0], &home, lefty)
+            .is_ok());
+        assert!(envelope
+            .check_cp(CpMode::Relative, &[0.0, 0.0, -90.0], &home, lefty)
+            .is_err());
+        assert!(envelope
+            .check_cp(CpMode::Relative, &[0.0, 0.0, -70.0], &home, lefty)
+            .is_ok());
+    }
+
+    #[test]
+    fn checks_linear_paths() {
+        // a post between start and target, both ends are clear of it
+        let post = Zone::Box {
+            min: [240.0, -10.0, 0.0],
+            max: [260.0, 10.0, 100.0],
+        };
+        let envelope = SafetyEnvelope::default().keep_out(post);
+        let lefty = ArmOrientation::Lefty;
+        let start = Kinematics::M1
+            .inverse(&[250.0, -50.0, 50.0, 0.0], lefty)
+            .unwrap();
+        let across = |mode| ptp(mode, 250.0, 50.0, 50.0, 0.0);
+        for mode in &[PtpMode::MovlXyz, PtpMode::JumpMovlXyz] {
+            assert!(
+                envelope.check_ptp(&across(*mode), &start, lefty).is_err(),
+                "{:?}",
+                mode
+            );
+        }
+        assert!(envelope
+            .check_ptp(&ptp(PtpMode::MovlInc, 0.0, 100.0, 0.0, 0.0), &start, lefty)
+            .is_err());
+        assert!(envelope
+            .check_cp(CpMode::Absolute, &[250.0, 50.0, 50.0], &start, lefty)
+            .is_err());
+        // a jump high enough clears the post
+        let high = envelope.clone().jump_params(PtpJumpParams {
+            jump_height: 60.0,
+            z_limit: 230.0,
+            dummy: 0,
+        });
+        assert!(high
+            .check_ptp(&across(PtpMode::JumpMovlXyz), &start, lefty)
+            .is_ok());
+        // but not when the device limits its height
+        let capped = envelope.jump_params(PtpJumpParams {
+            jump_height: 60.0,
+            z_limit: 90.0,
+            dummy: 0,
+        });
+        assert!(capped
+            .check_ptp(&across(PtpMode::JumpMovlXyz), &start, lefty)
+            .is_err());
+    }
+
+    #[test]
+    fn checks_arc_path() {
+        let envelope = SafetyEnvelope::default().keep_out(Zone::Box {
+            min: [290.0, -10.0, 0.0],
+            max: [310.0, 10.0, 230.0],
+        });
+        let point = |x, y| ArcPoint {
+            x,
+            y,
+            z: 50.0,
+            r: 0.0,
+        };
+        let lefty = ArmOrientation::Lefty;
+        let start = Kinematics::M1
+            .inverse(&[250.0, -50.0, 50.0, 0.0], lefty)
+            .unwrap();
+        let to = point(250.0, 50.0);
+        assert!(envelope
+            .check_cartesian(&[to.x, to.y, to.z, to.r], lefty)
+            .is_ok());
+        // passes through the zone between via and end
+        assert!(envelope
+            .check_arc(&point(285.0, -35.0), &to, false, &start, lefty)
+            .is_err());
+        // the other way round the circle is clear
+        let joints = envelope
+            .check_arc(&point(200.0, 0.0), &to, false, &start, lefty)
+            .unwrap();
+        let end = Kinematics::M1.forward(&joints);
+        assert!((end[0] - 250.0).abs() < 0.01 && (end[1] - 50.0).abs() < 0.01);
+        assert!(envelope
+            .check_arc(&point(200.0, 0.0), &to, true, &start, lefty)
+            .is_err());
+    }
+
+    #[test]
+    fn checks_jog_direction() {
+        let envelope = SafetyEnvelope::default().z_floor(50.0);
+        let at_floor = [0.0, 90.0, 50.5, 0.0];
+        let lefty = ArmOrientation::Lefty;
+        assert!(envelope
+            .check_jog(JogCommandType::Joint, JogCommand::CnDown, &at_floor, lefty)
+            .is_err());
+        assert!(envelope
+            .check_jog(
+                JogCommandType::Cartesian,
+                JogCommand::CpDown,
+                &at_floor,
+                lefty
+            )
+            .is_ok());
+        assert!(envelope
+            .check_jog(
+                JogCommandType::Cartesian,
+                JogCommand::Idel,
+                &at_floor,
+                lefty
+            )
+            .is_ok());
+        let message = envelope
+            .check_joints(&[0.0, 90.0, 10.0, 0.0])
+            .unwrap_err()
+            .to_string();
+        assert_eq!(message, "safety violation: z = 10.00 is below the floor 50");
+    }
+}
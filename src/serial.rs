@@ -1,16 +1,22 @@
+use crate::decoder::FrameDecoder;
+use crate::error::DobotError;
 use crate::protocol::*;
 use crate::traits::Device;
-use failure::Error;
 use serial::{SerialPort, SerialPortSettings, SystemPort};
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
 use std::path::Path;
+use std::time::{Duration, Instant};
 
 pub struct SerialDevice {
     device: SystemPort,
+    decoder: FrameDecoder,
+    // how long `send` waits for a response, the port timeout is set to
+    // whatever is left of it before each read
+    timeout: Duration,
 }
 
 impl SerialDevice {
-    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, serial::Error> {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, DobotError> {
         let mut device = serial::open(path.as_ref())?;
         device.reconfigure(&|settings: &mut dyn SerialPortSettings| {
             settings.set_baud_rate(serial::Baud115200)?;
@@ -20,28 +26,69 @@ impl SerialDevice {
             settings.set_flow_control(serial::FlowNone);
             Ok(())
         })?;
-        device.set_timeout(std::time::Duration::from_millis(1000))?;
-        Ok(Self { device })
+        let timeout = Duration::from_millis(1000);
+        device.set_timeout(timeout)?;
+        Ok(Self {
+            device,
+            decoder: FrameDecoder::new(),
+            timeout,
+        })
     }
 }
 
 impl Device for SerialDevice {
-    fn send(&mut self, packet: PayloadStruct) -> Result<PayloadStruct, Error> {
+    fn send(&mut self, packet: PayloadStruct) -> Result<PayloadStruct, DobotError> {
+        let id = packet.id;
+        // whatever is left over belongs to an earlier, already failed request
+        self.decoder.clear();
         let send_buf = packet.serialize();
         self.device.write_all(&send_buf)?;
         self.device.flush()?;
-        let mut header_buf = [1; 3];
-        self.device.read_exact(&mut header_buf)?;
-        let len = check_header_and_get_payload_size(&header_buf)?;
-        let mut buf_remaining = Vec::new();
-        buf_remaining.resize(len + 3, 1);
-        self.device.read_exact(&mut buf_remaining)?;
+        let mut buf = [0; 64];
+        // reads return early on data or on nothing at all, neither must
+        // keep us here for longer than the timeout
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            while let Some(frame) = self.decoder.next_frame() {
+                // skip late responses to requests that timed out before
+                if frame.id == id {
+                    return Ok(frame);
+                }
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left == Duration::from_secs(0) {
+                return Err(DobotError::Timeout);
+            }
+            self.device.set_timeout(left)?;
+            let size = self.device.read(&mut buf)?;
+            self.decoder.push(&buf[..size]);
+        }
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), DobotError> {
+        self.device.set_timeout(timeout)?;
+        self.timeout = timeout;
+        Ok(())
+    }
 
-        let mut final_buf = vec![];
-        final_buf.push(header_buf[0]);
-        final_buf.push(header_buf[1]);
-        final_buf.push(header_buf[2]);
-        final_buf.append(&mut buf_remaining);
-        PayloadStruct::deserialize(&final_buf)
+    fn flush_input(&mut self) -> Result<(), DobotError> {
+        self.decoder.clear();
+        self.device.set_timeout(Duration::from_millis(0))?;
+        let mut buf = [0; 64];
+        let drained = loop {
+            match self.device.read(&mut buf) {
+                Ok(0) => break Ok(()),
+                Ok(_) => {}
+                Err(e)
+                    if e.kind() == io::ErrorKind::TimedOut
+                        || e.kind() == io::ErrorKind::WouldBlock =>
+                {
+                    break Ok(())
+                }
+                Err(e) => break Err(e),
+            }
+        };
+        self.device.set_timeout(self.timeout)?;
+        Ok(drained?)
     }
 }
@@ -0,0 +1,377 @@
+use crate::alarm::AlarmWatch;
+use crate::client::DobotClient;
+use crate::error::DobotError;
+use crate::protocol::PayloadStruct;
+use crate::request::{self, ClientState, Request};
+use crate::traits::Device;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+struct Gate {
+    busy: bool,
+    // waiting threads are served in order, so a thread that locks in a tight
+    // loop can not starve the others
+    next_ticket: u64,
+    now_serving: u64,
+    // stop requests waiting for the device, they go before everyone else
+    priority_waiting: usize,
+}
+
+/// `Mutex` that hands out its value in the order it was asked for, except
+/// that priority requests go first.
+struct Fair<V> {
+    value: Mutex<V>,
+    gate: Mutex<Gate>,
+    released: Condvar,
+}
+
+struct FairGuard<'a, V> {
+    value: MutexGuard<'a, V>,
+    fair: &'a Fair<V>,
+}
+
+impl<V> Deref for FairGuard<'_, V> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        &self.value
+    }
+}
+
+impl<V> DerefMut for FairGuard<'_, V> {
+    fn deref_mut(&mut self) -> &mut V {
+        &mut self.value
+    }
+}
+
+impl<V> Drop for FairGuard<'_, V> {
+    fn drop(&mut self) {
+        self.fair.gate().busy = false;
+        self.fair.released.notify_all();
+    }
+}
+
+impl<V> Fair<V> {
+    fn new(value: V) -> Self {
+        Self {
+            value: Mutex::new(value),
+            gate: Mutex::new(Gate::default()),
+            released: Condvar::new(),
+        }
+    }
+
+    // A panic while holding a lock must not make the robot impossible to
+    // stop, so poisoning is ignored.
+    fn gate(&self) -> MutexGuard<'_, Gate> {
+        self.gate.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn acquire(&self, priority: bool) -> FairGuard<'_, V> {
+        let mut gate = self.gate();
+        let ticket = if priority {
+            gate.priority_waiting += 1;
+            None
+        } else {
+            gate.next_ticket += 1;
+            Some(gate.next_ticket - 1)
+        };
+        while gate.busy
+            || ticket.is_some_and(|t| gate.priority_waiting > 0 || gate.now_serving != t)
+        {
+            gate = self.released.wait(gate).unwrap_or_else(|e| e.into_inner());
+        }
+        match ticket {
+            Some(_) => gate.now_serving += 1,
+            None => gate.priority_waiting -= 1,
+        }
+        gate.busy = true;
+        drop(gate);
+        FairGuard {
+            value: self.value.lock().unwrap_or_else(|e| e.into_inner()),
+            fair: self,
+        }
+    }
+}
+
+/// Device of the client in a `SharedDobotClient`, held for one request at a
+/// time so that `stop` and `force_stop` can go in between.
+pub struct SharedDevice<T: Device> {
+    device: Arc<Fair<T>>,
+}
+
+impl<T: Device> SharedDevice<T> {
+    /// Waits for the request in progress and gives direct access to the
+    /// device.
+    pub fn lock(&self) -> impl DerefMut<Target = T> + '_ {
+        self.device.acquire(false)
+    }
+}
+
+impl<T: Device> Device for SharedDevice<T> {
+    fn send(&mut self, packet: PayloadStruct) -> Result<PayloadStruct, DobotError> {
+        self.device.acquire(false).send(packet)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), DobotError> {
+        self.device.acquire(false).set_timeout(timeout)
+    }
+
+    fn flush_input(&mut self) -> Result<(), DobotError> {
+        self.device.acquire(false).flush_input()
+    }
+
+    fn disable_retries(&mut self) {
+        self.device.acquire(false).disable_retries()
+    }
+}
+
+struct Inner<T: Device> {
+    client: Fair<DobotClient<SharedDevice<T>>>,
+    device: Arc<Fair<T>>,
+    // a force stop went past the client, which still knows the old position
+    forced: AtomicBool,
+}
+
+/// Cloneable, `Send + Sync` handle to a `DobotClient` shared between
+/// threads.
+///
+/// Access to the client is serialized with `lock`, waiting threads get the
+/// client in the order they asked for it. The device is only held for one
+/// request at a time, so `stop` and `force_stop` never wait for more than
+/// the request in progress: not for other waiting threads, and not for a
+/// thread blocking in `home_and_wait`, `wait_for_index` or
+/// `QueueManager::run` with the client locked.
+///
+/// ```
+/// use dobot_client::*;
+///
+/// let shared = SharedDobotClient::new(DobotClient::new(SimulatedDevice::new()));
+/// let worker = shared.clone();
+/// std::thread::spawn(move || worker.lock().set_home_cmd())
+///     .join()
+///     .unwrap()
+///     .unwrap();
+/// shared.stop().unwrap();
+/// println!("{:?}", shared.lock().get_pose().unwrap());
+/// ```
+pub struct SharedDobotClient<T: Device> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T: Device> Clone for SharedDobotClient<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+/// Exclusive access to the client of a `SharedDobotClient`, released on
+/// drop.
+pub struct SharedClientGuard<'a, T: Device> {
+    client: FairGuard<'a, DobotClient<SharedDevice<T>>>,
+}
+
+impl<T: Device> Deref for SharedClientGuard<'_, T> {
+    type Target = DobotClient<SharedDevice<T>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.client
+    }
+}
+
+impl<T: Device> DerefMut for SharedClientGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.client
+    }
+}
+
+impl<T: Device> SharedDobotClient<T> {
+    pub fn new(client: DobotClient<T>) -> Self {
+        let mut shared = None;
+        let client = client.map_device(|device| {
+            let device = Arc::new(Fair::new(device));
+            shared = Some(device.clone());
+            SharedDevice { device }
+        });
+        Self {
+            inner: Arc::new(Inner {
+                client: Fair::new(client),
+                device: shared.unwrap(),
+                forced: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    /// Waits for exclusive access to the client.
+    ///
+    /// A guard held during a long wait does not hold up `stop` or
+    /// `force_stop`, but a position the client remembers from before a
+    /// `force_stop` is only dropped with the next `lock`.
+    pub fn lock(&self) -> SharedClientGuard<'_, T> {
+        let mut client = self.inner.client.acquire(false);
+        if self.inner.forced.swap(false, Ordering::SeqCst) {
+            client.forget_position();
+        }
+        SharedClientGuard { client }
+    }
+
+    // sends `request` ahead of everyone waiting for the device
+    fn send_now(&self, request: Request<()>) -> Result<(), DobotError> {
+        let mut state = ClientState::default();
+        let (packet, pending) = request.start(&mut state);
+        let response = self.inner.device.acquire(true).send(packet)?;
+        pending.finish(&mut state, response)
+    }
+
+    /// `set_queued_command_stop_exec` ahead of every waiting `lock`, even
+    /// while the client is locked.
+    pub fn stop(&self) -> Result<(), DobotError> {
+        self.send_now(request::set_queued_command_stop_exec()?)
+    }
+
+    /// `set_queued_command_force_stop_exec` ahead of every waiting `lock`,
+    /// even while the client is locked.
+    pub fn force_stop(&self) -> Result<(), DobotError> {
+        self.inner.forced.store(true, Ordering::SeqCst);
+        self.send_now(request::set_queued_command_force_stop_exec()?)
+    }
+
+    /// `DobotClient::wait_for_index` that locks the client for each poll
+    /// only.
+    pub fn wait_for_index(
+        &self,
+        index: u64,
+        timeout: Duration,
+        poll_interval: Duration,
+    ) -> Result<(), DobotError> {
+        let start = Instant::now();
+        let mut watch = AlarmWatch::default();
+        loop {
+            {
+                let mut client = self.lock();
+                watch.check(client.get_alarm_state()?)?;
+                if client.get_queued_command_current_index()? >= index {
+                    return Ok(());
+                }
+            }
+            if start.elapsed() >= timeout {
+                return Err(DobotError::Timeout);
+            }
+            std::thread::sleep(poll_interval);
+        }
+    }
+}
+
+impl<T: Device> From<DobotClient<T>> for SharedDobotClient<T> {
+    fn from(client: DobotClient<T>) -> Self {
+        Self::new(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockDevice;
+    use crate::params::*;
+    use crate::sim::{SimClock, SimulatedDevice};
+    use std::sync::mpsc;
+    use std::thread;
+
+    fn assert_send_sync<S: Send + Sync>() {}
+
+    #[test]
+    fn stop_goes_before_waiting_locks() {
+        assert_send_sync::<SharedDobotClient<MockDevice>>();
+        let mut mock = MockDevice::new();
+        mock.respond_with(|request| {
+            let params = if request.id == 246 {
+                vec![0; 8]
+            } else {
+                vec![]
+            };
+            Ok(PayloadStruct::with_id(request.id).set_params(params))
+        });
+        let shared = SharedDobotClient::new(DobotClient::new(mock));
+
+        // a long running operation holds the client
+        let busy = shared.lock();
+        let poller = shared.clone();
+        let poll = thread::spawn(move || poller.lock().get_queued_command_current_index());
+        thread::sleep(Duration::from_millis(50));
+        let stopper = shared.clone();
+        let stop = thread::spawn(move || stopper.force_stop());
+        thread::sleep(Duration::from_millis(50));
+        drop(busy);
+        stop.join().unwrap().unwrap();
+        poll.join().unwrap().unwrap();
+
+        let client = shared.lock();
+        let ids: Vec<u8> = client
+            .device()
+            .lock()
+            .requests()
+            .iter()
+            .map(|r| r.id)
+            .collect();
+        assert_eq!(ids, vec![242, 246]);
+    }
+
+    #[test]
+    fn force_stop_while_client_is_locked() {
+        let sim = SimulatedDevice::with_clock(SimClock::RealTime);
+        let shared = SharedDobotClient::new(DobotClient::new(sim));
+        let (locked, wait_locked) = mpsc::channel();
+        let worker = shared.clone();
+        let waiting = thread::spawn(move || {
+            let mut client = worker.lock();
+            let handle = client.set_wait_command_queued(60_000)?;
+            client.set_queued_command_start_exec()?;
+            locked.send(()).unwrap();
+            let start = Instant::now();
+            client.wait_for_index(
+                handle.index(),
+                Duration::from_secs(10),
+                Duration::from_millis(5),
+            )?;
+            Ok::<_, DobotError>(start.elapsed())
+        });
+        wait_locked.recv().unwrap();
+        thread::sleep(Duration::from_millis(50));
+        shared.force_stop().unwrap();
+        let waited = waiting.join().unwrap().unwrap();
+        assert!(waited < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn poll_pose_while_worker_waits() {
+        let shared = SharedDobotClient::new(DobotClient::new(SimulatedDevice::new()));
+        let worker = shared.clone();
+        let moving = thread::spawn(move || {
+            let handle = worker.lock().set_ptp_command_queued(PtpCommand {
+                ptp_mode: PtpMode::MovlXyz,
+                x: 250.0,
+                y: -50.0,
+                z: 80.0,
+                r: 0.0,
+            })?;
+            worker.lock().set_queued_command_start_exec()?;
+            worker.wait_for_index(
+                handle.index(),
+                Duration::from_secs(10),
+                Duration::from_millis(1),
+            )
+        });
+        let mut polls = 0;
+        while !moving.is_finished() {
+            shared.lock().get_pose().unwrap();
+            polls += 1;
+        }
+        moving.join().unwrap().unwrap();
+        assert!(polls > 0);
+        assert!((shared.lock().get_pose().unwrap().x - 250.0).abs() < 0.01);
+    }
+}
@@ -0,0 +1,1240 @@
+use crate::alarm::{Alarm, AlarmSet, Axis};
+use crate::codec::{Decode, Encode, Reader};
+use crate::command::CommandId;
+use crate::error::DobotError;
+use crate::kinematics::{arc_points, Kinematics};
+use crate::params::*;
+use crate::protocol::{PayloadStruct, ReadWrite};
+use crate::traits::Device;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::time::{Duration, Instant};
+
+/// Geometry and joint limits of the simulated arm.
+const KINEMATICS: Kinematics = Kinematics::M1;
+const QUEUE_CAPACITY: usize = 32;
+const ALARM_BYTES: usize = 16;
+
+/// How simulated time advances.
+#[derive(Clone, Copy, Debug)]
+pub enum SimClock {
+    /// Every request advances the simulation by this fixed step, which keeps
+    /// tests deterministic.
+    PerRequest(Duration),
+    /// The simulation follows the wall clock.
+    RealTime,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Interpolation {
+    Joint,
+    Linear,
+}
+
+#[derive(Clone, Debug)]
+struct Segment {
+    from: [f32; 4],
+    to: [f32; 4],
+    interpolation: Interpolation,
+    duration: f32,
+}
+
+#[derive(Clone, Debug)]
+enum Task {
+    Motion {
+        segments: VecDeque<Segment>,
+        elapsed: f32,
+    },
+    Wait {
+        remaining: f32,
+    },
+}
+
+#[derive(Clone, Debug)]
+struct QueueEntry {
+    index: u64,
+    payload: PayloadStruct,
+}
+
+/// Simulated Dobot M1 implementing `Device`.
+///
+/// It keeps pose, motion parameters, arm orientation, IO outputs, alarms and
+/// the command queue, moves the arm over simulated time and answers every
+/// command issued by `DobotClient`. Motions use constant velocities derived
+/// from the PTP/JOG parameters; accelerations are stored but not simulated.
+#[derive(Debug)]
+pub struct SimulatedDevice {
+    clock: SimClock,
+    last_tick: Instant,
+    serial_number: String,
+    /// J1, J2 (deg), J3 (mm) and J4 (deg)
+    joints: [f32; 4],
+    orientation: ArmOrientation,
+    home_params: HomeParams,
+    jog_joint_params: JogJointParams,
+    jog_coordinate_params: JogCoordinateParams,
+    jog_common_params: JogCommonParams,
+    jog: Option<(bool, usize, f32)>,
+    ptp_joint_params: PtpJointParams,
+    ptp_coordinate_params: PtpCoordinateParams,
+    ptp_jump_params: PtpJumpParams,
+    ptp_common_params: PtpCommonParams,
+    cp_params: CpParams,
+    arc_params: ArcParams,
+    end_effector_params: EndEffectorParams,
+    laser: EndEffectorState,
+    suction_cup: EndEffectorState,
+    gripper: EndEffectorState,
+    io_functions: [IoFunction; 22],
+    io_outputs: [u8; 22],
+    io_pwm: [(f32, f32); 22],
+    io_inputs: [u8; 22],
+    io_adc: [u16; 22],
+    alarms: AlarmSet,
+    queue: VecDeque<QueueEntry>,
+    queue_running: bool,
+    current_task: Option<(u64, Task)>,
+    last_queued_index: u64,
+    current_index: u64,
+}
+
+impl Default for SimulatedDevice {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimulatedDevice {
+    /// Creates a simulator that advances 20 ms per request.
+    pub fn new() -> Self {
+        Self::with_clock(SimClock::PerRequest(Duration::from_millis(20)))
+    }
+
+    pub fn with_clock(clock: SimClock) -> Self {
+        Self {
+            clock,
+            last_tick: Instant::now(),
+            serial_number: "SIM-M1-0000".to_owned(),
+            joints: [0.0, 90.0, 100.0, 0.0],
+            orientation: ArmOrientation::Lefty,
+            home_params: HomeParams {
+                x: 200.0,
+                y: 200.0,
+                z: 100.0,
+                r: 90.0,
+            },
+            jog_joint_params: JogJointParams {
+                velocity: [15.0, 15.0, 15.0, 30.0],
+                acceleration: [50.0; 4],
+            },
+            jog_coordinate_params: JogCoordinateParams {
+                velocity: [15.0, 15.0, 15.0, 30.0],
+                acceleration: [50.0; 4],
+            },
+            jog_common_params: JogCommonParams {
+                velocity_ratio: 100.0,
+                acceleration_ratio: 100.0,
+            },
+            jog: None,
+            ptp_joint_params: PtpJointParams {
+                velocity: [100.0; 4],
+                acceleration: [100.0; 4],
+            },
+            ptp_coordinate_params: PtpCoordinateParams {
+                xyz_velocity: 200.0,
+                r_velocity: 200.0,
+                xyz_acceleration: 200.0,
+                r_acceleration: 200.0,
+            },
+            ptp_jump_params: PtpJumpParams {
+                jump_height: 20.0,
+                z_limit: 230.0,
+                dummy: 0,
+            },
+            ptp_common_params: PtpCommonParams {
+                velocity_ratio: 100.0,
+                acceleration_ratio: 100.0,
+            },
+            cp_params: CpParams {
+                plan_acceleration: 100.0,
+                junction_velocity: 50.0,
+                acceleration: 100.0,
+                real_time_track: false,
+            },
+            arc_params: ArcParams {
+                xyz_velocity: 100.0,
+                r_velocity: 100.0,
+                xyz_acceleration: 200.0,
+                r_acceleration: 200.0,
+            },
+            end_effector_params: EndEffectorParams {
+                x_bias: 0.0,
+                y_bias: 0.0,
+                z_bias: 0.0,
+            },
+            laser: EndEffectorState::Disabled,
+            suction_cup: EndEffectorState::Disabled,
+            gripper: EndEffectorState::Disabled,
+            io_functions: [IoFunction::DigitalOutput; 22],
+            io_outputs: [0; 22],
+            io_pwm: [(0.0, 0.0); 22],
+            io_inputs: [0; 22],
+            io_adc: [0; 22],
+            alarms: AlarmSet::from_bytes(&[0; ALARM_BYTES]),
+            queue: VecDeque::new(),
+            queue_running: false,
+            current_task: None,
+            last_queued_index: 0,
+            current_index: 0,
+        }
+    }
+
+    /// Current joint angles: J1, J2 (deg), J3 (mm) and J4 (deg).
+    pub fn joints(&self) -> [f32; 4] {
+        self.joints
+    }
+
+    /// Current Cartesian pose (x, y, z, r).
+    pub fn cartesian(&self) -> [f32; 4] {
+        KINEMATICS.forward(&self.joints)
+    }
+
+    /// Level of digital output `address` (1 ~ 22).
+    pub fn io_output(&self, address: u8) -> Option<u8> {
+        self.io_outputs.get(pin_index(address)?).cloned()
+    }
+
+    /// Function `address` (1 ~ 22) is multiplexed to.
+    pub fn io_function(&self, address: u8) -> Option<IoFunction> {
+        self.io_functions.get(pin_index(address)?).cloned()
+    }
+
+    /// PWM (frequency, duty cycle) of `address` (1 ~ 22).
+    pub fn io_pwm(&self, address: u8) -> Option<(f32, f32)> {
+        self.io_pwm.get(pin_index(address)?).cloned()
+    }
+
+    /// Sets the level seen on digital input `address` (1 ~ 22).
+    pub fn set_io_input(&mut self, address: u8, level: IoLevel) {
+        if let Some(input) = pin_index(address).and_then(|i| self.io_inputs.get_mut(i)) {
+            *input = level as u8;
+        }
+    }
+
+    /// Sets the value read from ADC `address` (1 ~ 22).
+    pub fn set_io_adc(&mut self, address: u8, value: u16) {
+        if let Some(adc) = pin_index(address).and_then(|i| self.io_adc.get_mut(i)) {
+            *adc = value;
+        }
+    }
+
+    /// Returns true if `alarm` is raised.
+    pub fn has_alarm(&self, alarm: Alarm) -> bool {
+        self.alarms.contains(alarm)
+    }
+
+    /// Returns true while a queued command or jog is moving the arm.
+    pub fn is_busy(&self) -> bool {
+        self.current_task.is_some() || self.jog.is_some()
+    }
+
+    /// Advances simulated time by `dt`.
+    pub fn advance(&mut self, dt: Duration) {
+        let mut dt = dt.as_secs_f32();
+        if let Some((cartesian, axis, velocity)) = self.jog {
+            self.step_jog(cartesian, axis, velocity * dt);
+        }
+        while dt > 0.0 {
+            if self.current_task.is_none() {
+                if !self.queue_running {
+                    return;
+                }
+                match self.queue.pop_front() {
+                    Some(cmd) => self.start(cmd),
+                    None => return,
+                }
+                continue;
+            }
+            let (index, mut task) = self.current_task.take().unwrap();
+            let (done, left) = match &mut task {
+                Task::Wait { remaining } => {
+                    let used = remaining.min(dt);
+                    *remaining -= used;
+                    (*remaining <= 0.0, dt - used)
+                }
+                Task::Motion { segments, elapsed } => {
+                    *elapsed += dt;
+                    while let Some(segment) = segments.front() {
+                        if *elapsed < segment.duration {
+                            self.joints = interpolate(segment, *elapsed / segment.duration);
+                            break;
+                        }
+                        *elapsed -= segment.duration;
+                        self.joints = segment.to;
+                        segments.pop_front();
+                    }
+                    let done = segments.is_empty();
+                    (done, if done { *elapsed } else { 0.0 })
+                }
+            };
+            if done {
+                self.current_index = index;
+            } else {
+                self.current_task = Some((index, task));
+            }
+            dt = left;
+        }
+    }
+
+    /// Runs the simulation until the queue is drained or `limit` is reached.
+    pub fn run_until_idle(&mut self, limit: Duration) {
+        let step = Duration::from_millis(10);
+        let mut elapsed = Duration::from_secs(0);
+        while (self.current_task.is_some() || (self.queue_running && !self.queue.is_empty()))
+            && elapsed < limit
+        {
+            self.advance(step);
+            elapsed += step;
+        }
+    }
+
+    fn tick(&mut self) {
+        let dt = match self.clock {
+            SimClock::PerRequest(dt) => dt,
+            SimClock::RealTime => {
+                let now = Instant::now();
+                let dt = now - self.last_tick;
+                self.last_tick = now;
+                dt
+            }
+        };
+        self.advance(dt);
+    }
+
+    /// Replaces the task in progress with an immediate one. A queued
+    /// command cut short counts as executed, so waits on its index end.
+    fn preempt(&mut self, task: Option<Task>) {
+        if let Some((index, _)) = self.current_task.take() {
+            self.current_index = self.current_index.max(index);
+        }
+        self.current_task = task.map(|t| (self.current_index, t));
+    }
+
+    fn raise_alarm(&mut self, alarm: Alarm) {
+        self.alarms.insert(alarm);
+    }
+
+    fn step_jog(&mut self, cartesian: bool, axis: usize, delta: f32) {
+        let target = if cartesian {
+            let mut pose = KINEMATICS.forward(&self.joints);
+            pose[axis] += delta;
+            match KINEMATICS.inverse(&pose, self.orientation) {
+                Some(joints) => joints,
+                None => {
+                    self.raise_alarm(Alarm::PlanInvCalc);
+                    self.jog = None;
+                    return;
+                }
+            }
+        } else {
+            let mut joints = self.joints;
+            joints[axis] += delta;
+            joints
+        };
+        match KINEMATICS.limit_violation(&target) {
+            None => self.joints = target,
+            Some(axis) => {
+                let axis_number = Axis::new(axis as u8 + 1).unwrap();
+                self.raise_alarm(if target[axis] < KINEMATICS.joint_limits[axis].0 {
+                    Alarm::LimitNegative(axis_number)
+                } else {
+                    Alarm::LimitPositive(axis_number)
+                });
+                self.jog = None;
+            }
+        }
+    }
+
+    fn start(&mut self, cmd: QueueEntry) {
+        let params = &cmd.payload.params;
+        let task = match CommandId::try_from(cmd.payload.id) {
+            Ok(CommandId::PtpCmd) => match PtpCommand::decode(params) {
+                Ok(command) => self.plan_ptp(&command),
+                Err(_) => {
+                    self.raise_alarm(Alarm::UndefinedInstruction);
+                    None
+                }
+            },
+            Ok(CommandId::HomeCmd) => self.plan_home(),
+            Ok(CommandId::CpCmd) => match CpCommand::decode(params) {
+                Ok(command) => self.plan_cp(
+                    command.cp_mode,
+                    [command.x, command.y, command.z],
+                    command.velocity,
+                ),
+                Err(_) => {
+                    self.raise_alarm(Alarm::UndefinedInstruction);
+                    None
+                }
+            },
+            Ok(CommandId::CpLeCmd) => match CpLaserCommand::decode(params) {
+                Ok(command) => {
+                    let velocity = self.cp_params.junction_velocity;
+                    self.plan_cp(command.cp_mode, [command.x, command.y, command.z], velocity)
+                }
+                Err(_) => {
+                    self.raise_alarm(Alarm::UndefinedInstruction);
+                    None
+                }
+            },
+            Ok(CommandId::ArcCmd) => match ArcCommand::decode(params) {
+                Ok(command) => self.plan_arc(&command.via, &command.to, None),
+                Err(_) => {
+                    self.raise_alarm(Alarm::UndefinedInstruction);
+                    None
+                }
+            },
+            Ok(CommandId::CircleCmd) => match CircleCommand::decode(params) {
+                Ok(command) => self.plan_arc(&command.via, &command.to, Some(command.count)),
+                Err(_) => {
+                    self.raise_alarm(Alarm::UndefinedInstruction);
+                    None
+                }
+            },
+            Ok(CommandId::WaitCmd) => u32::decode(params).ok().map(|ms| Task::Wait {
+                remaining: ms as f32 / 1000.0,
+            }),
+            Ok(id @ CommandId::EndEffectorParams)
+            | Ok(id @ CommandId::EndEffectorLaser)
+            | Ok(id @ CommandId::EndEffectorSuctionCup)
+            | Ok(id @ CommandId::EndEffectorGripper) => {
+                if self.set_end_effector(id, params).is_err() {
+                    self.raise_alarm(Alarm::UndefinedInstruction);
+                }
+                None
+            }
+            Ok(id @ CommandId::IoMultiplexing)
+            | Ok(id @ CommandId::IoDo)
+            | Ok(id @ CommandId::IoPwm) => {
+                if self.set_io(id, params).is_err() {
+                    self.raise_alarm(Alarm::UndefinedInstruction);
+                }
+                None
+            }
+            Ok(CommandId::ArmOrientation) => {
+                if let Ok(orientation) = ArmOrientation::decode(params) {
+                    self.orientation = orientation;
+                }
+                None
+            }
+            // parameters and jogs take effect as if sent right now
+            Ok(CommandId::HomeParams)
+            | Ok(CommandId::JogJointParams)
+            | Ok(CommandId::JogCoordinateParams)
+            | Ok(CommandId::JogCommonParams)
+            | Ok(CommandId::JogCmd)
+            | Ok(CommandId::PtpJointParams)
+            | Ok(CommandId::PtpCoordinateParams)
+            | Ok(CommandId::PtpJumpParams)
+            | Ok(CommandId::PtpCommonParams)
+            | Ok(CommandId::CpParams)
+            | Ok(CommandId::ArcParams) => {
+                let payload = PayloadStruct {
+                    is_queued: false,
+                    ..cmd.payload.clone()
+                };
+                if self.handle(&payload).is_err() {
+                    self.raise_alarm(Alarm::UndefinedInstruction);
+                }
+                None
+            }
+            _ => None,
+        };
+        match task {
+            Some(task) => self.current_task = Some((cmd.index, task)),
+            None => self.current_index = cmd.index,
+        }
+    }
+
+    fn set_io(&mut self, id: CommandId, params: &[u8]) -> Result<(), DobotError> {
+        let index = pin_index(params.first().cloned().unwrap_or(0))
+            .ok_or_else(|| DobotError::InvalidValue(format!("invalid IO params {:?}", params)))?;
+        match id {
+            CommandId::IoMultiplexing => {
+                let mut reader = Reader::new(params, 2)?;
+                reader.u8();
+                self.io_functions[index] = IoFunction::read(&mut reader)?;
+            }
+            CommandId::IoDo => {
+                let mut reader = Reader::new(params, 2)?;
+                reader.u8();
+                self.io_outputs[index] = IoLevel::read(&mut reader)? as u8;
+            }
+            _ => {
+                let mut reader = Reader::new(params, 9)?;
+                reader.u8();
+                self.io_pwm[index] = (reader.f32(), reader.f32());
+            }
+        }
+        Ok(())
+    }
+
+    /// Answers an IO read with the address followed by the value.
+    fn read_io(&self, id: CommandId, params: &[u8]) -> Result<Vec<u8>, DobotError> {
+        let address = params.first().cloned().unwrap_or(0);
+        let index = pin_index(address)
+            .ok_or_else(|| DobotError::InvalidValue(format!("invalid IO address {}", address)))?;
+        let mut response = vec![address];
+        match id {
+            CommandId::IoMultiplexing => self.io_functions[index].encode(&mut response),
+            CommandId::IoDo => response.push(self.io_outputs[index]),
+            CommandId::IoPwm => self.io_pwm[index].encode(&mut response),
+            CommandId::IoDi => response.push(self.io_inputs[index]),
+            _ => self.io_adc[index].encode(&mut response),
+        }
+        Ok(response)
+    }
+
+    fn set_end_effector(&mut self, id: CommandId, params: &[u8]) -> Result<(), DobotError> {
+        match id {
+            CommandId::EndEffectorParams => {
+                self.end_effector_params = EndEffectorParams::decode(params)?
+            }
+            CommandId::EndEffectorLaser => self.laser = EndEffectorState::decode(params)?,
+            CommandId::EndEffectorSuctionCup => {
+                self.suction_cup = EndEffectorState::decode(params)?
+            }
+            _ => self.gripper = EndEffectorState::decode(params)?,
+        }
+        Ok(())
+    }
+
+    fn plan_home(&mut self) -> Option<Task> {
+        let home = self.home_params;
+        self.plan_ptp(&PtpCommand {
+            ptp_mode: PtpMode::MovjXyz,
+            x: home.x,
+            y: home.y,
+            z: home.z,
+            r: home.r,
+        })
+    }
+
+    fn plan_ptp(&mut self, command: &PtpCommand) -> Option<Task> {
+        let target = [command.x, command.y, command.z, command.r];
+        let start = self.joints;
+        let start_pose = KINEMATICS.forward(&start);
+        let add = |a: [f32; 4], b: [f32; 4]| [a[0] + b[0], a[1] + b[1], a[2] + b[2], a[3] + b[3]];
+        // (target is cartesian, target, interpolation, jump)
+        let (cartesian, target, interpolation, jump) = match command.ptp_mode {
+            PtpMode::JumpXyz => (true, target, Interpolation::Joint, true),
+            PtpMode::MovjXyz => (true, target, Interpolation::Joint, false),
+            PtpMode::MovlXyz => (true, target, Interpolation::Linear, false),
+            PtpMode::JumpAngle => (false, target, Interpolation::Joint, true),
+            PtpMode::MovjAngle => (false, target, Interpolation::Joint, false),
+            PtpMode::MovlAngle => (false, target, Interpolation::Linear, false),
+            PtpMode::MovjInc => (false, add(start, target), Interpolation::Joint, false),
+            PtpMode::MovlInc => (true, add(start_pose, target), Interpolation::Linear, false),
+            PtpMode::MovjXyzInc => (true, add(start_pose, target), Interpolation::Joint, false),
+            PtpMode::JumpMovlXyz => (true, target, Interpolation::Linear, true),
+        };
+        let goal = if cartesian {
+            match KINEMATICS.inverse(&target, self.orientation) {
+                Some(joints) => joints,
+                None => {
+                    self.raise_alarm(Alarm::PlanInvCalc);
+                    return None;
+                }
+            }
+        } else {
+            target
+        };
+        if KINEMATICS.limit_violation(&goal).is_some() {
+            self.raise_alarm(Alarm::PlanInvLimit);
+            return None;
+        }
+        let mut waypoints = vec![start];
+        if jump {
+            let top = (start[2].max(goal[2]) + self.ptp_jump_params.jump_height)
+                .min(self.ptp_jump_params.z_limit)
+                .max(start[2].max(goal[2]));
+            waypoints.push([start[0], start[1], top, start[3]]);
+            waypoints.push([goal[0], goal[1], top, goal[3]]);
+        }
+        waypoints.push(goal);
+        let segments = waypoints
+            .windows(2)
+            .map(|w| {
+                // vertical legs of a jump are plain joint moves
+                let interpolation = if jump && w[0][2] != w[1][2] {
+                    Interpolation::Joint
+                } else {
+                    interpolation
+                };
+                Segment {
+                    from: w[0],
+                    to: w[1],
+                    interpolation,
+                    duration: self.duration(&w[0], &w[1], interpolation),
+                }
+            })
+            .collect();
+        Some(Task::Motion {
+            segments,
+            elapsed: 0.0,
+        })
+    }
+
+    /// CP moves are straight lines at `velocity` (mm/s); blending between
+    /// consecutive moves is not simulated.
+    fn plan_cp(&mut self, mode: CpMode, point: [f32; 3], velocity: f32) -> Option<Task> {
+        let start = self.joints;
+        let mut target = KINEMATICS.forward(&start);
+        let from = target;
+        for i in 0..3 {
+            target[i] = match mode {
+                CpMode::Relative => target[i] + point[i],
+                CpMode::Absolute => point[i],
+            };
+        }
+        let goal = match KINEMATICS.inverse(&target, self.orientation) {
+            Some(joints) => joints,
+            None => {
+                self.raise_alarm(Alarm::PlanInvCalc);
+                return None;
+            }
+        };
+        if KINEMATICS.limit_violation(&goal).is_some() {
+            self.raise_alarm(Alarm::PlanInvLimit);
+            return None;
+        }
+        let distance = (0..3)
+            .map(|i| (target[i] - from[i]).powi(2))
+            .sum::<f32>()
+            .sqrt();
+        let mut segments = VecDeque::new();
+        segments.push_back(Segment {
+            from: start,
+            to: goal,
+            interpolation: Interpolation::Linear,
+            duration: distance / velocity.max(0.01),
+        });
+        Some(Task::Motion {
+            segments,
+            elapsed: 0.0,
+        })
+    }
+
+    /// Arcs are traced as short straight chords. With `turns`, full circles
+    /// are traced instead of stopping at `to`.
+    fn plan_arc(&mut self, via: &ArcPoint, to: &ArcPoint, turns: Option<u32>) -> Option<Task> {
+        let start = self.joints;
+        let start_pose = KINEMATICS.forward(&start);
+        let p0 = [start_pose[0], start_pose[1], start_pose[2]];
+        let points = match arc_points(&p0, &[via.x, via.y, via.z], &[to.x, to.y, to.z], turns) {
+            Some(points) => points,
+            None => {
+                self.raise_alarm(Alarm::PlanArcInputParam);
+                return None;
+            }
+        };
+        let mut segments = VecDeque::new();
+        let mut from = start;
+        let count = points.len() as f32;
+        for (i, point) in points.iter().enumerate() {
+            let r = match turns {
+                Some(_) => start_pose[3],
+                None => start_pose[3] + (to.r - start_pose[3]) * (i + 1) as f32 / count,
+            };
+            let joints =
+                match KINEMATICS.inverse(&[point[0], point[1], point[2], r], self.orientation) {
+                    Some(joints) => joints,
+                    None => {
+                        self.raise_alarm(Alarm::PlanInvCalc);
+                        return None;
+                    }
+                };
+            if KINEMATICS.limit_violation(&joints).is_some() {
+                self.raise_alarm(Alarm::PlanInvLimit);
+                return None;
+            }
+            let a = KINEMATICS.forward(&from);
+            let chord =
+                ((point[0] - a[0]).powi(2) + (point[1] - a[1]).powi(2) + (point[2] - a[2]).powi(2))
+                    .sqrt();
+            segments.push_back(Segment {
+                from,
+                to: joints,
+                interpolation: Interpolation::Linear,
+                duration: chord / self.arc_params.xyz_velocity.max(0.01),
+            });
+            from = joints;
+        }
+        Some(Task::Motion {
+            segments,
+            elapsed: 0.0,
+        })
+    }
+
+    fn duration(&self, from: &[f32; 4], to: &[f32; 4], interpolation: Interpolation) -> f32 {
+        let ratio = (self.ptp_common_params.velocity_ratio / 100.0).max(0.01);
+        match interpolation {
+            Interpolation::Joint => (0..4)
+                .map(|i| {
+                    (to[i] - from[i]).abs() / (self.ptp_joint_params.velocity[i] * ratio).max(0.01)
+                })
+                .fold(0.0, f32::max),
+            Interpolation::Linear => {
+                let a = KINEMATICS.forward(from);
+                let b = KINEMATICS.forward(to);
+                let distance =
+                    ((b[0] - a[0]).powi(2) + (b[1] - a[1]).powi(2) + (b[2] - a[2]).powi(2)).sqrt();
+                let xyz = distance / (self.ptp_coordinate_params.xyz_velocity * ratio).max(0.01);
+                let r =
+                    (b[3] - a[3]).abs() / (self.ptp_coordinate_params.r_velocity * ratio).max(0.01);
+                xyz.max(r)
+            }
+        }
+    }
+
+    fn set_jog(&mut self, mode: JogCommandType, cmd: JogCommand) {
+        let cartesian = mode == JogCommandType::Cartesian;
+        let cmd = cmd as u8;
+        if cmd == 0 {
+            self.jog = None;
+            return;
+        }
+        let axis = (cmd as usize - 1) / 2;
+        let sign = if cmd % 2 == 1 { 1.0 } else { -1.0 };
+        let velocity = if cartesian {
+            self.jog_coordinate_params.velocity[axis]
+        } else {
+            self.jog_joint_params.velocity[axis]
+        };
+        self.jog = Some((
+            cartesian,
+            axis,
+            sign * velocity * self.jog_common_params.velocity_ratio / 100.0,
+        ));
+    }
+
+    fn enqueue(&mut self, payload: PayloadStruct) -> Result<Vec<u8>, DobotError> {
+        if self.queue.len() >= QUEUE_CAPACITY {
+            return Err(DobotError::QueueFull);
+        }
+        self.last_queued_index += 1;
+        self.queue.push_back(QueueEntry {
+            index: self.last_queued_index,
+            payload,
+        });
+        Ok(self.last_queued_index.to_bytes())
+    }
+
+    fn handle(&mut self, packet: &PayloadStruct) -> Result<Vec<u8>, DobotError> {
+        let write = packet.rw == ReadWrite::WRITE;
+        let params = &packet.params;
+        let id = CommandId::try_from(packet.id)?;
+        if packet.is_queued {
+            if !write || !id.info().queued {
+                return Err(DobotError::Unsupported(format!("{} can not be queued", id)));
+            }
+            return self.enqueue(packet.clone());
+        }
+        Ok(match (id, write) {
+            (CommandId::DeviceSn, false) => self.serial_number.as_bytes().to_vec(),
+            (CommandId::Pose, false) => {
+                let [x, y, z, r] = KINEMATICS.forward(&self.joints);
+                Pose {
+                    x,
+                    y,
+                    z,
+                    r,
+                    joint_angles: self.joints,
+                }
+                .to_bytes()
+            }
+            (CommandId::AlarmsState, false) => self.alarms.as_bytes().to_vec(),
+            (CommandId::AlarmsState, true) => {
+                self.alarms = AlarmSet::from_bytes(&[0; ALARM_BYTES]);
+                vec![]
+            }
+            (CommandId::HomeParams, false) => self.home_params.to_bytes(),
+            (CommandId::HomeParams, true) => {
+                self.home_params = HomeParams::decode(params)?;
+                vec![]
+            }
+            (CommandId::HomeCmd, true) => {
+                let task = self.plan_home();
+                self.preempt(task);
+                vec![]
+            }
+            (CommandId::ArmOrientation, false) => self.orientation.to_bytes(),
+            (CommandId::ArmOrientation, true) => {
+                self.orientation = ArmOrientation::decode(params)?;
+                vec![]
+            }
+            (CommandId::EndEffectorParams, false) => self.end_effector_params.to_bytes(),
+            (CommandId::EndEffectorLaser, false) => self.laser.to_bytes(),
+            (CommandId::EndEffectorSuctionCup, false) => self.suction_cup.to_bytes(),
+            (CommandId::EndEffectorGripper, false) => self.gripper.to_bytes(),
+            (CommandId::EndEffectorParams, true)
+            | (CommandId::EndEffectorLaser, true)
+            | (CommandId::EndEffectorSuctionCup, true)
+            | (CommandId::EndEffectorGripper, true) => {
+                self.set_end_effector(id, params)?;
+                vec![]
+            }
+            (CommandId::JogJointParams, false) => self.jog_joint_params.to_bytes(),
+            (CommandId::JogJointParams, true) => {
+                self.jog_joint_params = JogJointParams::decode(params)?;
+                vec![]
+            }
+            (CommandId::JogCoordinateParams, false) => self.jog_coordinate_params.to_bytes(),
+            (CommandId::JogCoordinateParams, true) => {
+                self.jog_coordinate_params = JogCoordinateParams::decode(params)?;
+                vec![]
+            }
+            (CommandId::JogCommonParams, false) => self.jog_common_params.to_bytes(),
+            (CommandId::JogCommonParams, true) => {
+                self.jog_common_params = JogCommonParams::decode(params)?;
+                vec![]
+            }
+            (CommandId::JogCmd, true) => {
+                let mut reader = Reader::new(params, 2)?;
+                let mode = JogCommandType::read(&mut reader)?;
+                let cmd = JogCommand::read(&mut reader)?;
+                self.set_jog(mode, cmd);
+                vec![]
+            }
+            (CommandId::PtpJointParams, false) => self.ptp_joint_params.to_bytes(),
+            (CommandId::PtpJointParams, true) => {
+                self.ptp_joint_params = PtpJointParams::decode(params)?;
+                vec![]
+            }
+            (CommandId::PtpCoordinateParams, false) => self.ptp_coordinate_params.to_bytes(),
+            (CommandId::PtpCoordinateParams, true) => {
+                self.ptp_coordinate_params = PtpCoordinateParams::decode(params)?;
+                vec![]
+            }
+            (CommandId::PtpJumpParams, false) => self.ptp_jump_params.to_bytes(),
+            (CommandId::PtpJumpParams, true) => {
+                self.ptp_jump_params = PtpJumpParams::decode(params)?;
+                vec![]
+            }
+            (CommandId::PtpCommonParams, false) => self.ptp_common_params.to_bytes(),
+            (CommandId::PtpCommonParams, true) => {
+                self.ptp_common_params = PtpCommonParams::decode(params)?;
+                vec![]
+            }
+            (CommandId::PtpCmd, true) => {
+                // an immediate command replaces whatever is moving right now
+                let command = PtpCommand::decode(params)?;
+                let task = self.plan_ptp(&command);
+                self.preempt(task);
+                vec![]
+            }
+            (CommandId::CpParams, false) => self.cp_params.to_bytes(),
+            (CommandId::CpParams, true) => {
+                self.cp_params = CpParams::decode(params)?;
+                vec![]
+            }
+            (CommandId::CpCmd, true) => {
+                let command = CpCommand::decode(params)?;
+                let point = [command.x, command.y, command.z];
+                let task = self.plan_cp(command.cp_mode, point, command.velocity);
+                self.preempt(task);
+                vec![]
+            }
+            (CommandId::CpLeCmd, true) => {
+                let command = CpLaserCommand::decode(params)?;
+                let point = [command.x, command.y, command.z];
+                let velocity = self.cp_params.junction_velocity;
+                let task = self.plan_cp(command.cp_mode, point, velocity);
+                self.preempt(task);
+                vec![]
+            }
+            (CommandId::ArcParams, false) => self.arc_params.to_bytes(),
+            (CommandId::ArcParams, true) => {
+                self.arc_params = ArcParams::decode(params)?;
+                vec![]
+            }
+            (CommandId::ArcCmd, true) => {
+                let command = ArcCommand::decode(params)?;
+                let task = self.plan_arc(&command.via, &command.to, None);
+                self.preempt(task);
+                vec![]
+            }
+            (CommandId::CircleCmd, true) => {
+                let command = CircleCommand::decode(params)?;
+                let task = self.plan_arc(&command.via, &command.to, Some(command.count));
+                self.preempt(task);
+                vec![]
+            }
+            (CommandId::WaitCmd, true) => vec![],
+            (CommandId::IoMultiplexing, false)
+            | (CommandId::IoDo, false)
+            | (CommandId::IoPwm, false)
+            | (CommandId::IoDi, false)
+            | (CommandId::IoAdc, false) => self.read_io(id, params)?,
+            (CommandId::IoMultiplexing, true)
+            | (CommandId::IoDo, true)
+            | (CommandId::IoPwm, true) => {
+                self.set_io(id, params)?;
+                vec![]
+            }
+            (CommandId::QueuedCmdStartExec, true) => {
+                self.queue_running = true;
+                vec![]
+            }
+            (CommandId::QueuedCmdStopExec, true) => {
+                self.queue_running = false;
+                vec![]
+            }
+            (CommandId::QueuedCmdForceStopExec, true) => {
+                self.queue_running = false;
+                self.preempt(None);
+                vec![]
+            }
+            (CommandId::QueuedCmdStartDownload, true)
+            | (CommandId::QueuedCmdStopDownload, true) => vec![],
+            (CommandId::QueuedCmdClear, true) => {
+                self.queue.clear();
+                vec![]
+            }
+            (CommandId::QueuedCmdCurrentIndex, false) => self.current_index.to_le_bytes().to_vec(),
+            (CommandId::QueuedCmdLeftSpace, false) => ((QUEUE_CAPACITY - self.queue.len()) as u32)
+                .to_le_bytes()
+                .to_vec(),
+            (id, write) => {
+                return Err(DobotError::Unsupported(format!(
+                    "simulator does not support {} ({})",
+                    id,
+                    if write { "write" } else { "read" }
+                )))
+            }
+        })
+    }
+}
+
+impl Device for SimulatedDevice {
+    fn send(&mut self, packet: PayloadStruct) -> Result<PayloadStruct, DobotError> {
+        self.tick();
+        let params = self.handle(&packet)?;
+        Ok(PayloadStruct {
+            id: packet.id,
+            rw: packet.rw,
+            is_queued: packet.is_queued,
+            params,
+        })
+    }
+}
+
+fn interpolate(segment: &Segment, t: f32) -> [f32; 4] {
+    let lerp = |a: &[f32; 4], b: &[f32; 4]| {
+        let mut v = [0.0; 4];
+        for i in 0..4 {
+            v[i] = a[i] + (b[i] - a[i]) * t;
+        }
+        v
+    };
+    match segment.interpolation {
+        Interpolation::Joint => lerp(&segment.from, &segment.to),
+        Interpolation::Linear => {
+            let orientation = KINEMATICS.orientation(&segment.to);
+            let pose = lerp(
+                &KINEMATICS.forward(&segment.from),
+                &KINEMATICS.forward(&segment.to),
+            );
+            KINEMATICS
+                .inverse(&pose, orientation)
+                .unwrap_or_else(|| lerp(&segment.from, &segment.to))
+        }
+    }
+}
+
+/// Index of IO `address` (1 ~ 22) in the IO state arrays.
+fn pin_index(address: u8) -> Option<usize> {
+    IoPin::new(address)
+        .ok()
+        .map(|pin| pin.address() as usize - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::*;
+
+    fn approx(a: f32, b: f32) -> bool {
+        (a - b).abs() < 0.05
+    }
+
+    #[test]
+    fn answers_client_queries() {
+        let mut dobot = DobotClient::new(SimulatedDevice::new());
+        assert_eq!(dobot.get_device_sn().unwrap(), "SIM-M1-0000");
+        assert!(dobot.get_alarm_state().unwrap().is_empty());
+        let pose = dobot.get_pose().unwrap();
+        let (x, y) = (pose.x, pose.y);
+        assert!(approx(x, 200.0) && approx(y, 200.0));
+        assert_eq!(dobot.get_queued_command_left_space().unwrap(), 32);
+        let params = dobot.get_ptp_jump_params().unwrap();
+        dobot.set_ptp_jump_params(params).unwrap();
+        match dobot.get_arm_orientation().unwrap() {
+            ArmOrientation::Lefty => {}
+            o => panic!("unexpected orientation {:?}", o),
+        }
+    }
+
+    #[test]
+    fn executes_queue() {
+        let mut dobot = DobotClient::new(SimulatedDevice::new());
+        let cmd = PtpCommand {
+            ptp_mode: PtpMode::MovlXyz,
+            x: 250.0,
+            y: 100.0,
+            z: 80.0,
+            r: 0.0,
+        };
+        assert_eq!(dobot.set_ptp_command_queued(cmd).unwrap().index(), 1);
+        assert_eq!(
+            dobot
+                .set_iodo_queued(IoPin::new(18).unwrap(), IoLevel::High)
+                .unwrap()
+                .index(),
+            2
+        );
+        let wait = dobot.set_wait_command_queued(500).unwrap();
+        assert_eq!(wait.index(), 3);
+        assert_eq!(dobot.get_queued_command_left_space().unwrap(), 29);
+        assert_eq!(dobot.get_queued_command_current_index().unwrap(), 0);
+        dobot.set_queued_command_start_exec().unwrap();
+        // every poll advances the simulation by 20 ms
+        wait.wait(&mut dobot, Duration::from_secs(1), Duration::from_millis(0))
+            .unwrap();
+        assert_eq!(dobot.get_queued_command_current_index().unwrap(), 3);
+        assert_eq!(dobot.device().io_output(18), Some(1));
+        let pose = dobot.get_pose().unwrap();
+        let (x, y, z) = (pose.x, pose.y, pose.z);
+        assert!(approx(x, 250.0) && approx(y, 100.0) && approx(z, 80.0));
+    }
+
+    #[test]
+    fn preempted_queued_command_completes() {
+        let mut dobot = DobotClient::new(SimulatedDevice::new());
+        let far = PtpCommand {
+            ptp_mode: PtpMode::MovlXyz,
+            x: 250.0,
+            y: -100.0,
+            z: 80.0,
+            r: 0.0,
+        };
+        let queued = dobot.set_ptp_command_queued(far).unwrap();
+        dobot.set_queued_command_start_exec().unwrap();
+        dobot.get_pose().unwrap();
+        assert!(dobot.device().is_busy());
+        dobot
+            .set_ptp_command(PtpCommand {
+                ptp_mode: PtpMode::MovjXyz,
+                x: 200.0,
+                y: 0.0,
+                z: 50.0,
+                r: 0.0,
+            })
+            .unwrap();
+        queued
+            .wait(&mut dobot, Duration::from_secs(1), Duration::from_millis(0))
+            .unwrap();
+        dobot.device_mut().advance(Duration::from_secs(10));
+
+        let queued = dobot.set_wait_command_queued(60_000).unwrap();
+        dobot.get_pose().unwrap();
+        assert!(dobot.device().is_busy());
+        dobot.set_queued_command_force_stop_exec().unwrap();
+        queued
+            .wait(&mut dobot, Duration::from_secs(1), Duration::from_millis(0))
+            .unwrap();
+    }
+
+    #[test]
+    fn cp_path() {
+        let mut dobot = DobotClient::new(SimulatedDevice::new());
+        let square = [(50.0, 0.0), (0.0, -50.0), (-50.0, 0.0), (0.0, 50.0)];
+        let mut last = None;
+        for &(x, y) in &square {
+            let cmd = CpCommand {
+                cp_mode: CpMode::Relative,
+                x,
+                y,
+                z: 0.0,
+                velocity: 100.0,
+            };
+            last = Some(dobot.set_cp_command_queued(cmd).unwrap());
+        }
+        dobot.set_queued_command_start_exec().unwrap();
+        // 200 mm at 100 mm/s
+        dobot.device_mut().advance(Duration::from_millis(1500));
+        assert!(!last.unwrap().is_done(&mut dobot).unwrap());
+        last.unwrap()
+            .wait(&mut dobot, Duration::from_secs(1), Duration::from_millis(0))
+            .unwrap();
+        let [x, y, z, _] = dobot.device().cartesian();
+        assert!(approx(x, 200.0) && approx(y, 200.0) && approx(z, 100.0));
+        assert_eq!(dobot.get_cp_params().unwrap().junction_velocity, 50.0);
+    }
+
+    #[test]
+    fn arc_and_circle() {
+        let mut dobot = DobotClient::new(SimulatedDevice::new());
+        // start at (200, 200, 100), arc around (200, 100)
+        let point = |x, y| ArcPoint {
+            x,
+            y,
+            z: 100.0,
+            r: 90.0,
+        };
+        let arc = dobot
+            .set_arc_command_queued(ArcCommand {
+                via: point(300.0, 100.0),
+                to: point(200.0, 0.0),
+            })
+            .unwrap();
+        dobot.set_queued_command_start_exec().unwrap();
+        arc.wait(&mut dobot, Duration::from_secs(1), Duration::from_millis(0))
+            .unwrap();
+        let [x, y, _, _] = dobot.device().cartesian();
+        assert!(approx(x, 200.0) && approx(y, 0.0));
+        let circle = dobot
+            .set_circle_command_queued(CircleCommand {
+                via: point(250.0, 0.0),
+                to: point(300.0, 50.0),
+                count: 1,
+            })
+            .unwrap();
+        circle
+            .wait(&mut dobot, Duration::from_secs(1), Duration::from_millis(0))
+            .unwrap();
+        let [x, y, _, _] = dobot.device().cartesian();
+        assert!(approx(x, 200.0) && approx(y, 0.0));
+        assert!(dobot.get_alarm_state().unwrap().is_empty());
+    }
+
+    #[test]
+    fn queued_params_apply_when_executed() {
+        let mut dobot = DobotClient::new(SimulatedDevice::new());
+        let before = dobot.get_ptp_common_params().unwrap();
+        let params = PtpCommonParams {
+            velocity_ratio: 20.0,
+            acceleration_ratio: 30.0,
+        };
+        dobot.set_wait_command_queued(100).unwrap();
+        let handle = dobot.set_ptp_common_params_queued(params).unwrap();
+        dobot
+            .set_jog_command_queued(JogCommandType::Joint, JogCommand::Idel)
+            .unwrap();
+        assert_eq!(dobot.get_ptp_common_params().unwrap(), before);
+        dobot.set_queued_command_start_exec().unwrap();
+        handle
+            .wait(&mut dobot, Duration::from_secs(1), Duration::from_millis(0))
+            .unwrap();
+        assert_eq!(dobot.get_ptp_common_params().unwrap(), params);
+    }
+
+    #[test]
+    fn io() {
+        let mut dobot = DobotClient::new(SimulatedDevice::new());
+        let pin = IoPin::new(5).unwrap();
+        dobot.set_io_multiplexing(pin, IoFunction::Pwm).unwrap();
+        assert_eq!(dobot.get_io_multiplexing(pin).unwrap(), IoFunction::Pwm);
+        let handle = dobot.set_io_pwm_queued(pin, 1000.0, 25.0).unwrap();
+        dobot.set_queued_command_start_exec().unwrap();
+        handle
+            .wait(&mut dobot, Duration::from_secs(1), Duration::from_millis(0))
+            .unwrap();
+        assert_eq!(dobot.device().io_pwm(5), Some((1000.0, 25.0)));
+        assert_eq!(dobot.get_io_pwm(pin).unwrap(), (1000.0, 25.0));
+
+        dobot.set_iodo(pin, IoLevel::High).unwrap();
+        assert_eq!(dobot.get_iodo(pin).unwrap(), IoLevel::High);
+        dobot.device_mut().set_io_input(3, IoLevel::High);
+        dobot.device_mut().set_io_adc(4, 2048);
+        assert_eq!(
+            dobot.get_iodi(IoPin::new(3).unwrap()).unwrap(),
+            IoLevel::High
+        );
+        assert_eq!(dobot.get_io_adc(IoPin::new(4).unwrap()).unwrap(), 2048);
+    }
+
+    #[test]
+    fn motion_takes_time() {
+        let mut sim = SimulatedDevice::with_clock(SimClock::PerRequest(Duration::from_millis(0)));
+        let mut params = vec![PtpMode::MovjAngle as u8];
+        params.extend([50.0f32, 90.0, 100.0, 0.0].to_bytes());
+        sim.send(PayloadStruct::with_id(84).set_write().set_params(params))
+            .unwrap();
+        // 50 deg at 100 deg/s
+        sim.advance(Duration::from_millis(250));
+        assert!(approx(sim.joints()[0], 25.0));
+        assert!(sim.is_busy());
+        sim.advance(Duration::from_millis(300));
+        assert!(approx(sim.joints()[0], 50.0));
+        assert!(!sim.is_busy());
+    }
+
+    #[test]
+    fn unreachable_target_raises_alarm() {
+        let mut dobot = DobotClient::new(SimulatedDevice::new());
+        let cmd = PtpCommand {
+            ptp_mode: PtpMode::MovjXyz,
+            x: 600.0,
+            y: 0.0,
+            z: 50.0,
+            r: 0.0,
+        };
+        dobot.set_ptp_command_queued(cmd).unwrap();
+        dobot.set_queued_command_start_exec().unwrap();
+        dobot.device_mut().run_until_idle(Duration::from_secs(1));
+        assert!(dobot.device().has_alarm(Alarm::PlanInvCalc));
+        assert_eq!(dobot.get_queued_command_current_index().unwrap(), 1);
+        dobot.clear_all_alarm_state().unwrap();
+        assert!(dobot.get_alarm_state().unwrap().is_empty());
+    }
+
+    #[test]
+    fn jog_moves_until_idle() {
+        let mut sim = SimulatedDevice::with_clock(SimClock::PerRequest(Duration::from_millis(0)));
+        let jog = |cmd: JogCommand| {
+            PayloadStruct::with_id(73)
+                .set_write()
+                .set_params(vec![JogCommandType::Joint as u8, cmd as u8])
+        };
+        sim.send(jog(JogCommand::ApDown)).unwrap();
+        sim.advance(Duration::from_secs(1));
+        assert!(approx(sim.joints()[0], 15.0));
+        sim.send(jog(JogCommand::Idel)).unwrap();
+        sim.advance(Duration::from_secs(1));
+        assert!(approx(sim.joints()[0], 15.0));
+    }
+
+    #[test]
+    fn unsupported_id() {
+        let mut sim = SimulatedDevice::new();
+        assert!(sim.send(PayloadStruct::with_id(200)).is_err());
+    }
+
+    #[test]
+    fn home_and_wait() {
+        let mut dobot = DobotClient::new(SimulatedDevice::new());
+        let home = HomeParams {
+            x: 300.0,
+            y: 0.0,
+            z: 50.0,
+            r: 0.0,
+        };
+        dobot.set_home_params(home).unwrap();
+        dobot
+            .home_and_wait(Duration::from_secs(1), Duration::from_millis(0))
+            .unwrap();
+        let pose = dobot.get_pose().unwrap();
+        assert!(approx(pose.x, 300.0) && approx(pose.y, 0.0) && approx(pose.z, 50.0));
+    }
+}
@@ -0,0 +1,179 @@
+use crate::decoder::FrameDecoder;
+use crate::error::DobotError;
+use crate::protocol::PayloadStruct;
+use crate::traits::Device;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug)]
+pub struct TcpDeviceConfig {
+    pub connect_timeout: Duration,
+    pub read_timeout: Duration,
+    pub write_timeout: Duration,
+}
+
+impl Default for TcpDeviceConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_millis(3000),
+            read_timeout: Duration::from_millis(1000),
+            write_timeout: Duration::from_millis(1000),
+        }
+    }
+}
+
+/// Device behind a raw TCP socket, e.g. a ser2net style serial-to-Ethernet
+/// bridge.
+///
+/// The connection is re-established on the next request whenever it drops.
+pub struct TcpDevice {
+    addrs: Vec<SocketAddr>,
+    config: TcpDeviceConfig,
+    stream: Option<TcpStream>,
+    decoder: FrameDecoder,
+}
+
+impl TcpDevice {
+    pub fn new<A: ToSocketAddrs>(addr: A) -> Result<Self, DobotError> {
+        Self::with_config(addr, TcpDeviceConfig::default())
+    }
+
+    pub fn with_config<A: ToSocketAddrs>(
+        addr: A,
+        config: TcpDeviceConfig,
+    ) -> Result<Self, DobotError> {
+        let mut device = Self {
+            addrs: addr.to_socket_addrs()?.collect(),
+            config,
+            stream: None,
+            decoder: FrameDecoder::new(),
+        };
+        device.connect()?;
+        Ok(device)
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    fn connect(&mut self) -> Result<&mut TcpStream, io::Error> {
+        self.stream = None;
+        self.decoder.clear();
+        let mut last_err = io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "no socket address to connect to",
+        );
+        for addr in &self.addrs {
+            match TcpStream::connect_timeout(addr, self.config.connect_timeout) {
+                Ok(stream) => {
+                    stream.set_read_timeout(Some(self.config.read_timeout))?;
+                    stream.set_write_timeout(Some(self.config.write_timeout))?;
+                    stream.set_nodelay(true)?;
+                    return Ok(self.stream.get_or_insert(stream));
+                }
+                Err(e) => last_err = e,
+            }
+        }
+        Err(last_err)
+    }
+
+    fn write_request(&mut self, buf: &[u8], queued: bool) -> Result<(), io::Error> {
+        if let Some(stream) = self.stream.as_mut() {
+            match stream.write_all(buf).and_then(|_| stream.flush()) {
+                Ok(()) => return Ok(()),
+                // Part of the frame may be out already, so a queued write
+                // could end up in the queue twice if it was sent again.
+                Err(e) if queued => {
+                    self.stream = None;
+                    return Err(e);
+                }
+                Err(_) => {}
+            }
+        }
+        let stream = self.connect()?;
+        stream.write_all(buf)?;
+        stream.flush()
+    }
+
+    /// Reads until the response to `id` arrives or `read_timeout` has
+    /// passed, however many other frames arrive in between.
+    fn read_response(&mut self, id: u8) -> Result<PayloadStruct, DobotError> {
+        let mut buf = [0; 256];
+        let deadline = Instant::now() + self.config.read_timeout;
+        loop {
+            while let Some(frame) = self.decoder.next_frame() {
+                if frame.id == id {
+                    return Ok(frame);
+                }
+            }
+            let stream = self
+                .stream
+                .as_mut()
+                .ok_or_else(|| io::Error::from(io::ErrorKind::NotConnected))?;
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left == Duration::from_secs(0) {
+                return Err(DobotError::Timeout);
+            }
+            stream.set_read_timeout(Some(left))?;
+            match stream.read(&mut buf) {
+                Ok(0) => {
+                    self.stream = None;
+                    return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+                }
+                Ok(size) => self.decoder.push(&buf[..size]),
+                Err(e) => {
+                    if e.kind() != io::ErrorKind::WouldBlock && e.kind() != io::ErrorKind::TimedOut
+                    {
+                        self.stream = None;
+                    }
+                    return Err(e.into());
+                }
+            }
+        }
+    }
+}
+
+impl Device for TcpDevice {
+    fn send(&mut self, packet: PayloadStruct) -> Result<PayloadStruct, DobotError> {
+        let id = packet.id;
+        let queued = packet.is_queued;
+        self.decoder.clear();
+        self.write_request(&packet.serialize(), queued)?;
+        self.read_response(id)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), DobotError> {
+        self.config.read_timeout = timeout;
+        if let Some(stream) = self.stream.as_ref() {
+            stream.set_read_timeout(Some(timeout))?;
+        }
+        Ok(())
+    }
+
+    fn flush_input(&mut self) -> Result<(), DobotError> {
+        self.decoder.clear();
+        let stream = match self.stream.as_mut() {
+            Some(stream) => stream,
+            None => return Ok(()),
+        };
+        stream.set_nonblocking(true)?;
+        let mut buf = [0; 256];
+        let drained = loop {
+            match stream.read(&mut buf) {
+                Ok(0) => break Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+        match drained.and_then(|_| stream.set_nonblocking(false)) {
+            Ok(()) => Ok(()),
+            // reconnect on the next request
+            Err(e) => {
+                self.stream = None;
+                Err(e.into())
+            }
+        }
+    }
+}
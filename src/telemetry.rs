@@ -0,0 +1,273 @@
+use crate::alarm::AlarmSet;
+use crate::client::DobotClient;
+use crate::error::DobotError;
+use crate::params::Pose;
+use crate::shared::SharedDobotClient;
+use crate::traits::Device;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+#[derive(Clone, Debug)]
+pub struct TelemetryConfig {
+    /// Time between the starts of two polls.
+    pub interval: Duration,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(100),
+        }
+    }
+}
+
+/// State of the arm at one poll.
+#[derive(Clone, Debug)]
+pub struct TelemetrySnapshot {
+    /// Counts up from 1 with every published snapshot.
+    pub seq: u64,
+    /// When the poll finished.
+    pub timestamp: SystemTime,
+    pub pose: Pose,
+    pub alarms: AlarmSet,
+    pub current_index: u64,
+    /// Polls that failed since the poller started.
+    pub poll_errors: u64,
+}
+
+#[derive(Default)]
+struct WatchState {
+    latest: Option<TelemetrySnapshot>,
+    stopped: bool,
+    subscribers: Vec<Sender<TelemetrySnapshot>>,
+}
+
+#[derive(Default)]
+struct Watch {
+    state: Mutex<WatchState>,
+    changed: Condvar,
+}
+
+impl Watch {
+    fn state(&self) -> MutexGuard<'_, WatchState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn publish(&self, snapshot: TelemetrySnapshot) {
+        let mut state = self.state();
+        state
+            .subscribers
+            .retain(|subscriber| subscriber.send(snapshot.clone()).is_ok());
+        state.latest = Some(snapshot);
+        self.changed.notify_all();
+    }
+
+    fn stop(&self) {
+        let mut state = self.state();
+        state.stopped = true;
+        state.subscribers.clear();
+        self.changed.notify_all();
+    }
+}
+
+/// Watch-style view of the latest `TelemetrySnapshot`.
+#[derive(Clone)]
+pub struct TelemetryWatch {
+    watch: Arc<Watch>,
+    seen: u64,
+}
+
+impl TelemetryWatch {
+    /// Most recent snapshot, `None` before the first poll succeeded.
+    pub fn latest(&self) -> Option<TelemetrySnapshot> {
+        self.watch.state().latest.clone()
+    }
+
+    /// Waits for a snapshot newer than the last one this watch returned.
+    ///
+    /// Returns `None` on timeout or once the poller has stopped.
+    pub fn wait(&mut self, timeout: Duration) -> Option<TelemetrySnapshot> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.watch.state();
+        loop {
+            if let Some(snapshot) = state.latest.as_ref().filter(|s| s.seq > self.seen) {
+                self.seen = snapshot.seq;
+                return Some(snapshot.clone());
+            }
+            let now = Instant::now();
+            if state.stopped || now >= deadline {
+                return None;
+            }
+            state = self
+                .watch
+                .changed
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+}
+
+/// Background thread polling `get_pose`, `get_alarm_state` and
+/// `get_queued_command_current_index` of an arm.
+///
+/// Snapshots are published to `watch` handles and `subscribe`d channels.
+/// Commands can be sent in between polls through `client`.
+pub struct Telemetry<T: Device + Send + 'static> {
+    client: SharedDobotClient<T>,
+    watch: Arc<Watch>,
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<T: Device + Send + 'static> Telemetry<T> {
+    pub fn start(client: DobotClient<T>, config: TelemetryConfig) -> Self {
+        let client = SharedDobotClient::new(client);
+        let watch = Arc::new(Watch::default());
+        let (stop, stopped) = mpsc::channel();
+        let thread = {
+            let client = client.clone();
+            let watch = watch.clone();
+            thread::spawn(move || poll_loop(&client, &watch, &config, &stopped))
+        };
+        Self {
+            client,
+            watch,
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    /// Handle for sending commands in between polls.
+    pub fn client(&self) -> &SharedDobotClient<T> {
+        &self.client
+    }
+
+    pub fn watch(&self) -> TelemetryWatch {
+        TelemetryWatch {
+            watch: self.watch.clone(),
+            seen: 0,
+        }
+    }
+
+    /// Channel receiving every snapshot published from now on.
+    pub fn subscribe(&self) -> Receiver<TelemetrySnapshot> {
+        let (sender, receiver) = mpsc::channel();
+        self.watch.state().subscribers.push(sender);
+        receiver
+    }
+
+    /// Stops polling and returns the client.
+    pub fn stop(mut self) -> SharedDobotClient<T> {
+        self.shutdown();
+        self.client.clone()
+    }
+
+    fn shutdown(&mut self) {
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        self.watch.stop();
+    }
+}
+
+impl<T: Device + Send + 'static> Drop for Telemetry<T> {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn poll<T: Device>(client: &SharedDobotClient<T>) -> Result<(Pose, AlarmSet, u64), DobotError> {
+    let mut client = client.lock();
+    let pose = client.get_pose()?;
+    let alarms = client.get_alarm_state()?;
+    let current_index = client.get_queued_command_current_index()?;
+    Ok((pose, alarms, current_index))
+}
+
+fn poll_loop<T: Device>(
+    client: &SharedDobotClient<T>,
+    watch: &Watch,
+    config: &TelemetryConfig,
+    stopped: &Receiver<()>,
+) {
+    let mut seq = 0;
+    let mut poll_errors = 0;
+    loop {
+        let start = Instant::now();
+        match poll(client) {
+            Ok((pose, alarms, current_index)) => {
+                seq += 1;
+                watch.publish(TelemetrySnapshot {
+                    seq,
+                    timestamp: SystemTime::now(),
+                    pose,
+                    alarms,
+                    current_index,
+                    poll_errors,
+                });
+            }
+            Err(_) => poll_errors += 1,
+        }
+        // the sender is dropped to stop us
+        let wait = config.interval.saturating_sub(start.elapsed());
+        if let Err(RecvTimeoutError::Disconnected) = stopped.recv_timeout(wait) {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::*;
+    use crate::sim::SimulatedDevice;
+
+    fn config() -> TelemetryConfig {
+        TelemetryConfig {
+            interval: Duration::from_millis(1),
+        }
+    }
+
+    #[test]
+    fn follows_queued_motion() {
+        let telemetry = Telemetry::start(DobotClient::new(SimulatedDevice::new()), config());
+        let mut watch = telemetry.watch();
+        let first = watch.wait(Duration::from_secs(1)).unwrap();
+        assert!(first.alarms.is_empty());
+        assert_eq!(first.poll_errors, 0);
+
+        let snapshots = telemetry.subscribe();
+        let handle = {
+            let mut client = telemetry.client().lock();
+            let handle = client
+                .set_ptp_command_queued(PtpCommand {
+                    ptp_mode: PtpMode::MovlXyz,
+                    x: 250.0,
+                    y: -50.0,
+                    z: 80.0,
+                    r: 0.0,
+                })
+                .unwrap();
+            client.set_queued_command_start_exec().unwrap();
+            handle
+        };
+        let done = snapshots
+            .iter()
+            .find(|s| s.current_index >= handle.index())
+            .unwrap();
+        assert!((done.pose.x - 250.0).abs() < 0.01);
+        assert!(done.seq > first.seq);
+
+        let latest = watch.wait(Duration::from_secs(1)).unwrap();
+        assert!(latest.seq > first.seq);
+        assert!(latest.timestamp >= first.timestamp);
+
+        telemetry.stop();
+        assert!(watch.wait(Duration::from_secs(1)).is_none());
+        assert!(snapshots.recv().is_err());
+    }
+}
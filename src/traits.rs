@@ -1,6 +1,44 @@
+use crate::error::DobotError;
 use crate::protocol::PayloadStruct;
-use failure::Error;
+use std::time::Duration;
 
 pub trait Device {
-    fn send(&mut self, packet: PayloadStruct) -> Result<PayloadStruct, Error>;
+    fn send(&mut self, packet: PayloadStruct) -> Result<PayloadStruct, DobotError>;
+
+    /// Sets how long `send` waits for a response.
+    ///
+    /// Devices that never wait, like the simulator, ignore it.
+    fn set_timeout(&mut self, _timeout: Duration) -> Result<(), DobotError> {
+        Ok(())
+    }
+
+    /// Discards whatever has been received but not read yet, e.g. the rest
+    /// of a garbled frame.
+    fn flush_input(&mut self) -> Result<(), DobotError> {
+        Ok(())
+    }
+
+    /// Stops `send` from sending a request again by itself, for a wrapper
+    /// that retries instead, like `RetryDevice`.
+    ///
+    /// Devices that send every request once ignore it.
+    fn disable_retries(&mut self) {}
+}
+
+impl<D: Device + ?Sized> Device for Box<D> {
+    fn send(&mut self, packet: PayloadStruct) -> Result<PayloadStruct, DobotError> {
+        (**self).send(packet)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), DobotError> {
+        (**self).set_timeout(timeout)
+    }
+
+    fn flush_input(&mut self) -> Result<(), DobotError> {
+        (**self).flush_input()
+    }
+
+    fn disable_retries(&mut self) {
+        (**self).disable_retries()
+    }
 }
@@ -1,34 +1,132 @@
-// This is not working for now, i don't know why...
+use crate::decoder::FrameDecoder;
+use crate::error::DobotError;
 use crate::protocol::PayloadStruct;
 use crate::traits::*;
-use std::net::{IpAddr, UdpSocket};
+use std::io::ErrorKind;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug)]
+pub struct UdpDeviceConfig {
+    /// Local address to bind, `0.0.0.0:8889` by default.
+    pub local_addr: SocketAddr,
+    /// UDP port of the Wi-Fi module on the robot.
+    pub remote_port: u16,
+    /// How long to wait for each response.
+    pub timeout: Duration,
+    /// How many times a request is re-sent when no response arrives.
+    ///
+    /// Queued writes are never re-sent, the lost datagram may have been the
+    /// response and the command already be in the queue. `RetryDevice`
+    /// can retry them safely, and sets this to 0 for the device it wraps.
+    pub retries: usize,
+}
+
+impl Default for UdpDeviceConfig {
+    fn default() -> Self {
+        Self {
+            local_addr: ([0, 0, 0, 0], 8889).into(),
+            remote_port: 54321,
+            timeout: Duration::from_millis(1000),
+            retries: 2,
+        }
+    }
+}
 
 pub struct UdpDevice {
     udp_socket: UdpSocket,
+    decoder: FrameDecoder,
+    retries: usize,
+    timeout: Duration,
 }
 
 impl UdpDevice {
-    pub fn new(ip: IpAddr) -> Result<Self, std::io::Error> {
-        let udp_socket = UdpSocket::bind("192.168.1.10:8889")?;
-        udp_socket.connect((ip, 54321))?;
-        let duration = std::time::Duration::new(1, 0);
-        let dur = Option::Some(duration);
-        let _res = udp_socket
-            .set_read_timeout(dur)
-            .expect("failed to set timeout");
+    pub fn new(ip: IpAddr) -> Result<Self, DobotError> {
+        Self::with_config(ip, UdpDeviceConfig::default())
+    }
+
+    pub fn with_config(ip: IpAddr, config: UdpDeviceConfig) -> Result<Self, DobotError> {
+        let udp_socket = UdpSocket::bind(config.local_addr)?;
+        udp_socket.connect((ip, config.remote_port))?;
+        udp_socket.set_read_timeout(Some(config.timeout))?;
+        Ok(Self {
+            udp_socket,
+            decoder: FrameDecoder::new(),
+            retries: config.retries,
+            timeout: config.timeout,
+        })
+    }
 
-        Ok(Self { udp_socket })
+    pub fn local_addr(&self) -> Result<SocketAddr, std::io::Error> {
+        self.udp_socket.local_addr()
+    }
+
+    /// Waits for the response to `id` until `timeout` has passed, however
+    /// many other datagrams arrive in between.
+    fn recv_response(&mut self, id: u8) -> Result<Option<PayloadStruct>, DobotError> {
+        let mut buf = [0; 512];
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            while let Some(frame) = self.decoder.next_frame() {
+                if frame.id == id {
+                    return Ok(Some(frame));
+                }
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left == Duration::from_secs(0) {
+                return Ok(None);
+            }
+            self.udp_socket.set_read_timeout(Some(left))?;
+            match self.udp_socket.recv(&mut buf) {
+                Ok(size) => self.decoder.push(&buf[..size]),
+                Err(ref e)
+                    if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut =>
+                {
+                    return Ok(None);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
     }
 }
 
 impl Device for UdpDevice {
-    fn send(&mut self, packet: PayloadStruct) -> Result<PayloadStruct, failure::Error> {
+    fn send(&mut self, packet: PayloadStruct) -> Result<PayloadStruct, DobotError> {
+        let id = packet.id;
+        let retries = if packet.is_queued { 0 } else { self.retries };
         let buf = packet.serialize();
-        self.udp_socket.send(&buf)?;
-        println!("send");
-        let mut buf = [0; 128];
-        let _size = self.udp_socket.recv(&mut buf)?;
-        println!("recv");
-        PayloadStruct::deserialize(&buf)
+        for _ in 0..=retries {
+            self.decoder.clear();
+            self.udp_socket.send(&buf)?;
+            if let Some(frame) = self.recv_response(id)? {
+                return Ok(frame);
+            }
+        }
+        Err(DobotError::Timeout)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), DobotError> {
+        self.udp_socket.set_read_timeout(Some(timeout))?;
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    fn disable_retries(&mut self) {
+        self.retries = 0;
+    }
+
+    fn flush_input(&mut self) -> Result<(), DobotError> {
+        self.decoder.clear();
+        self.udp_socket.set_nonblocking(true)?;
+        let mut buf = [0; 512];
+        let drained = loop {
+            match self.udp_socket.recv(&mut buf) {
+                Ok(_) => {}
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+        self.udp_socket.set_nonblocking(false)?;
+        Ok(drained?)
     }
 }
@@ -0,0 +1,98 @@
+#![cfg(feature = "tokio")]
+
+use dobot_client::*;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::time;
+
+/// Stand-in for the serial-to-Ethernet bridge. The n-th answer carries n as
+/// left space, and the first one is only sent after `first_delay`, or never
+/// with `None`.
+async fn spawn_robot(first_delay: Option<Duration>) -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut decoder = FrameDecoder::new();
+        let mut buf = [0; 256];
+        let mut served = 0u32;
+        loop {
+            let size = stream.read(&mut buf).await.unwrap();
+            if size == 0 {
+                break;
+            }
+            for request in decoder.decode(&buf[..size]) {
+                served += 1;
+                if served == 1 {
+                    match first_delay {
+                        Some(delay) => time::sleep(delay).await,
+                        None => continue,
+                    }
+                }
+                let response = PayloadStruct::with_id(request.id)
+                    .set_params(served.to_le_bytes().to_vec())
+                    .serialize();
+                stream.write_all(&response).await.unwrap();
+            }
+        }
+    });
+    port
+}
+
+fn config() -> TcpDeviceConfig {
+    TcpDeviceConfig {
+        connect_timeout: Duration::from_millis(500),
+        read_timeout: Duration::from_millis(500),
+        write_timeout: Duration::from_millis(500),
+    }
+}
+
+#[tokio::test]
+async fn async_tcp_round_trip() {
+    let port = spawn_robot(Some(Duration::from_millis(0))).await;
+    let device = AsyncTcpDevice::with_config(("127.0.0.1", port), config())
+        .await
+        .unwrap();
+    let mut dobot = AsyncDobotClient::new(device);
+    for i in 1..=3 {
+        assert_eq!(dobot.get_queued_command_left_space().await.unwrap(), i);
+    }
+}
+
+#[tokio::test]
+async fn late_response_of_cancelled_request_is_dropped() {
+    let port = spawn_robot(Some(Duration::from_millis(200))).await;
+    let device = AsyncTcpDevice::with_config(("127.0.0.1", port), config())
+        .await
+        .unwrap();
+    let mut dobot = AsyncDobotClient::new(device);
+    let cancelled = time::timeout(
+        Duration::from_millis(50),
+        dobot.get_queued_command_left_space(),
+    )
+    .await;
+    assert!(cancelled.is_err());
+    // the answer to the cancelled request (1) arrives first
+    assert_eq!(dobot.get_queued_command_left_space().await.unwrap(), 2);
+    assert!(dobot.device().is_connected());
+}
+
+#[tokio::test]
+async fn other_request_after_cancel_does_not_wait() {
+    let port = spawn_robot(None).await;
+    let device = AsyncTcpDevice::with_config(("127.0.0.1", port), config())
+        .await
+        .unwrap();
+    let mut dobot = AsyncDobotClient::new(device);
+    let cancelled = time::timeout(
+        Duration::from_millis(50),
+        dobot.get_queued_command_left_space(),
+    )
+    .await;
+    assert!(cancelled.is_err());
+    // a response to the cancelled request could not be taken for this one
+    let start = time::Instant::now();
+    assert_eq!(dobot.get_device_sn().await.unwrap(), "\u{2}\0\0\0");
+    assert!(start.elapsed() < Duration::from_millis(250));
+}
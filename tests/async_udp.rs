@@ -0,0 +1,54 @@
+#![cfg(feature = "tokio")]
+
+use dobot_client::*;
+use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+use std::thread;
+use std::time::Duration;
+
+const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+/// Stand-in for the Wi-Fi module that never answers, returns how many
+/// requests it received.
+fn spawn_silent_robot() -> (u16, thread::JoinHandle<usize>) {
+    let socket = UdpSocket::bind((LOCALHOST, 0)).unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_millis(500)))
+        .unwrap();
+    let port = socket.local_addr().unwrap().port();
+    let handle = thread::spawn(move || {
+        let mut decoder = FrameDecoder::new();
+        let mut buf = [0; 512];
+        let mut received = 0;
+        while let Ok(size) = socket.recv(&mut buf) {
+            received += decoder.decode(&buf[..size]).len();
+        }
+        received
+    });
+    (port, handle)
+}
+
+fn config(remote_port: u16) -> UdpDeviceConfig {
+    UdpDeviceConfig {
+        local_addr: (LOCALHOST, 0).into(),
+        remote_port,
+        timeout: Duration::from_millis(100),
+        retries: 2,
+    }
+}
+
+#[tokio::test]
+async fn async_udp_does_not_resend_queued_writes() {
+    let (port, robot) = spawn_silent_robot();
+    let device = AsyncUdpDevice::with_config(LOCALHOST, config(port))
+        .await
+        .unwrap();
+    let mut dobot = AsyncDobotClient::new(device);
+    assert!(matches!(
+        dobot.set_wait_command_queued(100).await,
+        Err(DobotError::Timeout)
+    ));
+    assert!(matches!(dobot.get_pose().await, Err(DobotError::Timeout)));
+    drop(dobot);
+    // one queued write, three attempts to read the pose
+    assert_eq!(robot.join().unwrap(), 4);
+}
@@ -0,0 +1,48 @@
+use std::process::{Command, Output};
+
+fn dobot(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_dobot"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn queries_simulator() {
+    let output = dobot(&["sim://", "info"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("alarms: no alarm"));
+
+    let output = dobot(&["sim://", "queue", "status"]);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "current index: 0\nleft space: 32\n");
+}
+
+#[test]
+fn move_and_wait() {
+    let output = dobot(&[
+        "sim://", "move", "--mode", "movl", "--wait", "250", "-50", "80", "0",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    let out = stdout(&output);
+    assert!(out.starts_with("queued index 1\n"));
+    assert!(out.contains("x: 250"));
+}
+
+#[test]
+fn usage_errors() {
+    let output = dobot(&["sim://", "move", "1", "2"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("expected 4 values, got 2"));
+
+    let output = dobot(&["ftp://x", "pose"]);
+    assert_eq!(output.status.code(), Some(2));
+
+    let output = dobot(&["--help"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).starts_with("usage: dobot"));
+}
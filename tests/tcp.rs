@@ -0,0 +1,169 @@
+use dobot_client::*;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Stand-in for the serial-to-Ethernet bridge. Each accepted connection
+/// serves `per_connection` requests and is then closed.
+fn spawn_robot(connections: usize, per_connection: usize) -> (u16, thread::JoinHandle<usize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let handle = thread::spawn(move || {
+        let mut served = 0;
+        for _ in 0..connections {
+            let (stream, _) = listener.accept().unwrap();
+            served += serve(stream, per_connection);
+        }
+        served
+    });
+    (port, handle)
+}
+
+fn serve(mut stream: TcpStream, count: usize) -> usize {
+    let mut decoder = FrameDecoder::new();
+    let mut buf = [0; 256];
+    let mut served = 0;
+    while served < count {
+        let size = stream.read(&mut buf).unwrap();
+        if size == 0 {
+            break;
+        }
+        for request in decoder.decode(&buf[..size]) {
+            let mut response = vec![0x00, 0x13];
+            response.extend(
+                PayloadStruct::with_id(request.id)
+                    .set_params(vec![7, 0, 0, 0])
+                    .serialize(),
+            );
+            // trickle the answer to exercise the decoder
+            for b in response {
+                stream.write_all(&[b]).unwrap();
+            }
+            served += 1;
+        }
+    }
+    served
+}
+
+fn config() -> TcpDeviceConfig {
+    TcpDeviceConfig {
+        connect_timeout: Duration::from_millis(500),
+        read_timeout: Duration::from_millis(500),
+        write_timeout: Duration::from_millis(500),
+    }
+}
+
+#[test]
+fn tcp_round_trip() {
+    let (port, robot) = spawn_robot(1, 3);
+    let device = TcpDevice::with_config(("127.0.0.1", port), config()).unwrap();
+    let mut dobot = DobotClient::new(device);
+    for _ in 0..3 {
+        assert_eq!(dobot.get_queued_command_left_space().unwrap(), 7);
+    }
+    drop(dobot);
+    assert_eq!(robot.join().unwrap(), 3);
+}
+
+#[test]
+fn tcp_reconnects_after_connection_drop() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (closed, wait_closed) = std::sync::mpsc::channel();
+    let robot = thread::spawn(move || {
+        // the bridge hangs up after the first answer
+        let (stream, _) = listener.accept().unwrap();
+        let mut served = serve(stream, 1);
+        closed.send(()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        served += serve(stream, 1);
+        served
+    });
+    let device = TcpDevice::with_config(("127.0.0.1", port), config()).unwrap();
+    let mut dobot = DobotClient::new(device);
+    assert_eq!(dobot.get_queued_command_left_space().unwrap(), 7);
+    wait_closed.recv().unwrap();
+    // The write still goes into the socket buffer, the hang up shows when
+    // reading the response.
+    assert!(dobot.get_queued_command_left_space().is_err());
+    assert!(!dobot.device().is_connected());
+    assert_eq!(dobot.get_queued_command_left_space().unwrap(), 7);
+    drop(dobot);
+    assert_eq!(robot.join().unwrap(), 2);
+}
+
+#[test]
+fn tcp_connect_error() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    drop(listener);
+    assert!(TcpDevice::with_config(("127.0.0.1", port), config()).is_err());
+}
+
+#[test]
+fn tcp_retry_after_lost_response() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let robot = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut decoder = FrameDecoder::new();
+        let mut buf = [0; 256];
+        let mut requests = 0;
+        while requests < 2 {
+            let size = stream.read(&mut buf).unwrap();
+            for request in decoder.decode(&buf[..size]) {
+                requests += 1;
+                // the first answer is garbled
+                let mut response = PayloadStruct::with_id(request.id)
+                    .set_params(vec![7, 0, 0, 0])
+                    .serialize();
+                if requests == 1 {
+                    response.truncate(5);
+                }
+                stream.write_all(&response).unwrap();
+            }
+        }
+        requests
+    });
+    let device = TcpDevice::with_config(("127.0.0.1", port), config()).unwrap();
+    let policy = RetryPolicy {
+        read_timeout: Duration::from_millis(100),
+        ..RetryPolicy::default()
+    };
+    let mut dobot = DobotClient::new(RetryDevice::new(device, policy));
+    assert_eq!(dobot.get_queued_command_left_space().unwrap(), 7);
+    assert_eq!(robot.join().unwrap(), 2);
+}
+
+#[test]
+fn tcp_timeout_holds_while_other_frames_arrive() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let robot = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buf = [0; 256];
+        assert!(stream.read(&mut buf).unwrap() > 0);
+        // late answers to some other request keep coming in
+        let other = PayloadStruct::with_id(20)
+            .set_params(vec![0; 16])
+            .serialize();
+        for _ in 0..20 {
+            if stream.write_all(&other).is_err() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    });
+    let config = TcpDeviceConfig {
+        read_timeout: Duration::from_millis(200),
+        ..config()
+    };
+    let device = TcpDevice::with_config(("127.0.0.1", port), config).unwrap();
+    let mut dobot = DobotClient::new(device);
+    let start = Instant::now();
+    assert!(matches!(dobot.get_pose(), Err(DobotError::Timeout)));
+    assert!(start.elapsed() < Duration::from_millis(600));
+    drop(dobot);
+    robot.join().unwrap();
+}
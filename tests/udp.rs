@@ -0,0 +1,147 @@
+use dobot_client::*;
+use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+/// Stand-in for the Wi-Fi module: answers every request with `respond`,
+/// optionally dropping the first requests and splitting the answer in two
+/// datagrams.
+fn spawn_robot<F>(drop_first: usize, split: bool, respond: F) -> (u16, thread::JoinHandle<usize>)
+where
+    F: Fn(PayloadStruct) -> PayloadStruct + Send + 'static,
+{
+    let socket = UdpSocket::bind((LOCALHOST, 0)).unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_millis(500)))
+        .unwrap();
+    let port = socket.local_addr().unwrap().port();
+    let handle = thread::spawn(move || {
+        let mut decoder = FrameDecoder::new();
+        let mut buf = [0; 512];
+        let mut received = 0;
+        while let Ok((size, from)) = socket.recv_from(&mut buf) {
+            for request in decoder.decode(&buf[..size]) {
+                received += 1;
+                if received <= drop_first {
+                    continue;
+                }
+                let response = respond(request).serialize();
+                if split {
+                    let (head, tail) = response.split_at(response.len() / 2);
+                    socket.send_to(head, from).unwrap();
+                    socket.send_to(tail, from).unwrap();
+                } else {
+                    socket.send_to(&response, from).unwrap();
+                }
+            }
+        }
+        received
+    });
+    (port, handle)
+}
+
+fn config(remote_port: u16) -> UdpDeviceConfig {
+    UdpDeviceConfig {
+        local_addr: (LOCALHOST, 0).into(),
+        remote_port,
+        timeout: Duration::from_millis(200),
+        retries: 2,
+    }
+}
+
+#[test]
+fn udp_round_trip_with_split_datagrams() {
+    let (port, robot) = spawn_robot(0, true, |request| {
+        PayloadStruct::with_id(request.id).set_params(b"DOBOT-M1".to_vec())
+    });
+    let device = UdpDevice::with_config(LOCALHOST, config(port)).unwrap();
+    let mut dobot = DobotClient::new(device);
+    assert_eq!(dobot.get_device_sn().unwrap(), "DOBOT-M1");
+    assert_eq!(dobot.get_device_sn().unwrap(), "DOBOT-M1");
+    drop(dobot);
+    assert_eq!(robot.join().unwrap(), 2);
+}
+
+#[test]
+fn udp_retries_lost_request() {
+    let (port, robot) = spawn_robot(1, false, |request| {
+        PayloadStruct::with_id(request.id).set_params(vec![3, 0, 0, 0, 0, 0, 0, 0])
+    });
+    let device = UdpDevice::with_config(LOCALHOST, config(port)).unwrap();
+    let mut dobot = DobotClient::new(device);
+    assert_eq!(dobot.get_queued_command_current_index().unwrap(), 3);
+    drop(dobot);
+    assert_eq!(robot.join().unwrap(), 2);
+}
+
+#[test]
+fn udp_gives_up_after_retries() {
+    let (port, robot) = spawn_robot(usize::MAX, false, |request| request);
+    let device = UdpDevice::with_config(LOCALHOST, config(port)).unwrap();
+    let mut dobot = DobotClient::new(device);
+    assert!(matches!(dobot.get_pose(), Err(DobotError::Timeout)));
+    drop(dobot);
+    assert_eq!(robot.join().unwrap(), 3);
+}
+
+#[test]
+fn udp_does_not_resend_queued_writes() {
+    let (port, robot) = spawn_robot(usize::MAX, false, |request| request);
+    let device = UdpDevice::with_config(LOCALHOST, config(port)).unwrap();
+    let mut dobot = DobotClient::new(device);
+    assert!(matches!(
+        dobot.set_wait_command_queued(100),
+        Err(DobotError::Timeout)
+    ));
+    drop(dobot);
+    assert_eq!(robot.join().unwrap(), 1);
+}
+
+#[test]
+fn udp_leaves_retries_to_retry_device() {
+    let (port, robot) = spawn_robot(usize::MAX, false, |request| request);
+    let device = UdpDevice::with_config(LOCALHOST, config(port)).unwrap();
+    let policy = RetryPolicy {
+        read_timeout: Duration::from_millis(100),
+        backoff: Duration::from_millis(1),
+        ..RetryPolicy::default()
+    };
+    let mut dobot = DobotClient::new(RetryDevice::new(device, policy));
+    assert!(matches!(dobot.get_pose(), Err(DobotError::Timeout)));
+    drop(dobot);
+    // once per attempt of the `RetryDevice`, not three times each
+    assert_eq!(robot.join().unwrap(), 3);
+}
+
+#[test]
+fn udp_timeout_holds_while_other_frames_arrive() {
+    let socket = UdpSocket::bind((LOCALHOST, 0)).unwrap();
+    let port = socket.local_addr().unwrap().port();
+    let robot = thread::spawn(move || {
+        let mut buf = [0; 512];
+        let (_, from) = socket.recv_from(&mut buf).unwrap();
+        // late answers to some other request keep coming in
+        let other = PayloadStruct::with_id(20)
+            .set_params(vec![0; 16])
+            .serialize();
+        for _ in 0..20 {
+            if socket.send_to(&other, from).is_err() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    });
+    let config = UdpDeviceConfig {
+        retries: 0,
+        ..config(port)
+    };
+    let device = UdpDevice::with_config(LOCALHOST, config).unwrap();
+    let mut dobot = DobotClient::new(device);
+    let start = Instant::now();
+    assert!(matches!(dobot.get_pose(), Err(DobotError::Timeout)));
+    assert!(start.elapsed() < Duration::from_millis(600));
+    drop(dobot);
+    robot.join().unwrap();
+}